@@ -0,0 +1,572 @@
+use std::{io::ErrorKind, path::Path};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    csv_utils::{ReaderState, RejectionsWriter},
+    dispute_reasons::{DisputeReason, DisputeReasonStats},
+    errors::CliError,
+    held_funds::HeldDisputes,
+    history::{HistorySnapshot, HistoryStorage, InMemoryTransactionStorage},
+    pipeline::InputPosition,
+    reorder::ReorderState,
+    storage::{ClientId, UserAccount},
+    transactions::{SequenceNumber, TransactionMeta},
+    transactions_processor::{
+        InMemoryTransactionProcessor, RowOrigin, TransactionLogEntry, TransactionProcessor,
+    },
+};
+
+// The processor state after the rows processed so far and where reading got to, a run restored
+// from it continues as if it had never stopped. Metrics aren't part of it, rejections only by the
+// size of the rejections file written so far
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub reader: ReaderState,
+    meta: TransactionMeta,
+    accounts: Vec<(ClientId, UserAccount)>,
+    history: HistorySnapshot,
+    dispute_report: Vec<(Option<DisputeReason>, DisputeReasonStats)>,
+    held_disputes: HeldDisputes,
+    rejections: Option<u64>,
+}
+
+// Rows processed between two checkpoints and where reading got to after them. Segments are appended
+// to a log next to the checkpoint, so a checkpoint doesn't write the whole state again
+#[derive(Debug, Serialize, Deserialize)]
+struct Segment {
+    // sequence number of the last row processed before the first of `rows`
+    after: SequenceNumber,
+    rows: Vec<(TransactionLogEntry, Option<RowOrigin>)>,
+    reader: ReaderState,
+    rejections: Option<u64>,
+}
+
+type Processor = InMemoryTransactionProcessor<HistoryStorage>;
+
+// spilled transactions would have to be read back into memory, so only the in-memory history is
+// captured by checkpoints
+fn in_memory_history(processor: &Processor) -> Result<&InMemoryTransactionStorage, CliError> {
+    processor.get_history().in_memory().ok_or_else(|| {
+        CliError::OutputUnwritable(
+            "checkpoints can't be combined with --history-memory-budget".to_string(),
+        )
+    })
+}
+
+fn log_path(path: &str) -> String {
+    format!("{path}.log")
+}
+
+// makes a rename in the directory of `path` survive a crash
+async fn sync_directory(path: &str) -> Result<(), std::io::Error> {
+    let directory = match Path::new(path).parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+    File::open(directory).await?.sync_all().await
+}
+
+impl Checkpoint {
+    pub fn take(
+        processor: &Processor,
+        reader: ReaderState,
+        rejections: Option<u64>,
+    ) -> Result<Self, CliError> {
+        Ok(Self {
+            reader,
+            meta: processor.current_meta(),
+            accounts: processor.get_accounts_storage().snapshot(),
+            history: in_memory_history(processor)?.snapshot(),
+            dispute_report: processor.get_dispute_report().snapshot(),
+            held_disputes: processor.held_disputes(),
+            rejections,
+        })
+    }
+
+    // replaces the processor state, the reader state is left to restore by the caller
+    pub fn restore(self, processor: &Processor) -> Result<ReaderState, CliError> {
+        let history = in_memory_history(processor)?;
+        processor.restore_meta(self.meta);
+        processor.get_accounts_storage().restore(self.accounts);
+        history.restore(self.history);
+        processor.get_dispute_report().restore(self.dispute_report);
+        processor.restore_held_disputes(self.held_disputes);
+        Ok(self.reader)
+    }
+
+    // Written next to the path, synced and renamed, so a crash while writing keeps the previous
+    // checkpoint
+    pub async fn save(&self, path: &str) -> Result<(), CliError> {
+        let unwritable = |e: std::io::Error| CliError::OutputUnwritable(format!("{path}: {e}"));
+        let content = serde_json::to_vec(self)
+            .map_err(|e| CliError::OutputUnwritable(format!("{path}: {e}")))?;
+        let temporary_path = format!("{path}.tmp");
+        let mut file = File::create(&temporary_path).await.map_err(unwritable)?;
+        file.write_all(&content).await.map_err(unwritable)?;
+        file.sync_all().await.map_err(unwritable)?;
+        tokio::fs::rename(&temporary_path, path)
+            .await
+            .map_err(unwritable)?;
+        sync_directory(path).await.map_err(unwritable)
+    }
+
+    // none when no checkpoint was written yet
+    pub async fn load(path: &str) -> Result<Option<Self>, CliError> {
+        let content = match tokio::fs::read(path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(CliError::InputUnreadable(format!("{path}: {e}"))),
+        };
+        serde_json::from_slice(&content)
+            .map(Some)
+            .map_err(|e| CliError::InputUnreadable(format!("{path}: {e}")))
+    }
+}
+
+// Segments logged after the checkpoint at `path`. A last line cut short by a crash while appending
+// is left out, the checkpoint before it still holds
+async fn load_segments(path: &str) -> Result<Vec<Segment>, CliError> {
+    let path = log_path(path);
+    let content = match tokio::fs::read_to_string(&path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(CliError::InputUnreadable(format!("{path}: {e}"))),
+    };
+    let mut lines = content.lines().peekable();
+    let mut segments = Vec::new();
+    while let Some(line) = lines.next() {
+        match serde_json::from_str(line) {
+            Ok(segment) => segments.push(segment),
+            Err(e) if lines.peek().is_none() => {
+                warn!("Ignoring the incomplete last line of {path}: {e}")
+            }
+            Err(e) => return Err(CliError::InputUnreadable(format!("{path}: {e}"))),
+        }
+    }
+    Ok(segments)
+}
+
+// The latest checkpoint, a base state with the segments logged after it
+pub struct SavedCheckpoint {
+    path: String,
+    base: Checkpoint,
+    segments: Vec<Segment>,
+}
+
+impl SavedCheckpoint {
+    // none when no checkpoint was written yet
+    pub async fn load(path: &str) -> Result<Option<Self>, CliError> {
+        let Some(base) = Checkpoint::load(path).await? else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            path: path.to_string(),
+            base,
+            segments: load_segments(path).await?,
+        }))
+    }
+
+    pub fn reader(&self) -> &ReaderState {
+        self.segments
+            .last()
+            .map_or(&self.base.reader, |segment| &segment.reader)
+    }
+
+    // Replaces the processor state with the base one and processes the logged rows again. Returns
+    // where reading got to and the size of the rejections file, rows rejected again aren't written
+    // as they are in that size already
+    pub fn restore(self, processor: &Processor) -> Result<(ReaderState, Option<u64>), CliError> {
+        let mut rejections = self.base.rejections;
+        let mut reader = self.base.restore(processor)?;
+        for segment in self.segments {
+            let sequence_number = processor.current_meta().sequence_number;
+            if segment.after != sequence_number {
+                return Err(CliError::InputUnreadable(format!(
+                    "{}: segment after row {} doesn't follow row {sequence_number}",
+                    log_path(&self.path),
+                    segment.after
+                )));
+            }
+            for (transaction_entry, origin) in segment.rows {
+                let _result = processor.process(TransactionLogEntry {
+                    origin,
+                    ..transaction_entry
+                });
+            }
+            reader = segment.reader;
+            rejections = segment.rejections;
+        }
+        Ok((reader, rejections))
+    }
+}
+
+// where a resumed run continues reading and writing rejections
+pub struct ResumePoint {
+    pub position: InputPosition,
+    pub rejections: Option<u64>,
+}
+
+// Checkpoints of one run, written every `interval` rows read. Each checkpoint logs the rows
+// processed since the previous one, the whole state is written again once the log holds more rows
+// than it, so a run writes about twice its rows in total
+pub struct Checkpoints<'a> {
+    processor: &'a Processor,
+    path: String,
+    pub interval: u64,
+    resumed: Option<ReorderState>,
+    // processed since the latest checkpoint
+    pending: Vec<(TransactionLogEntry, Option<RowOrigin>)>,
+    // sequence number of the written state and rows logged after it, none until the state is
+    // written by this run or when logging failed
+    logged: Option<(SequenceNumber, u64)>,
+}
+
+impl<'a> Checkpoints<'a> {
+    pub fn new(processor: &'a Processor, path: &str, interval: u64) -> Self {
+        Self {
+            processor,
+            path: path.to_string(),
+            interval,
+            resumed: None,
+            pending: Vec::new(),
+            logged: None,
+        }
+    }
+
+    // Restores the processor from the latest checkpoint and returns where to continue,
+    // nothing is restored when there is no checkpoint yet
+    pub async fn resume(&mut self) -> Result<Option<ResumePoint>, CliError> {
+        let Some(checkpoint) = SavedCheckpoint::load(&self.path).await? else {
+            info!(
+                "No checkpoint in {}, starting from the beginning",
+                self.path
+            );
+            return Ok(None);
+        };
+        let (reader, rejections) = checkpoint.restore(self.processor)?;
+        info!(
+            "Resuming {} after line {}",
+            reader.position.input, reader.position.line
+        );
+        self.resumed = Some(reader.reorder);
+        Ok(Some(ResumePoint {
+            position: reader.position,
+            rejections,
+        }))
+    }
+
+    // rows the resumed run had read but not processed yet
+    pub fn take_resumed(&mut self) -> Option<ReorderState> {
+        self.resumed.take()
+    }
+
+    // called for every row given to the processor, rejected ones too
+    pub fn record(&mut self, transaction_entry: TransactionLogEntry) {
+        let origin = transaction_entry.origin.clone();
+        self.pending.push((transaction_entry, origin));
+    }
+
+    // A failed checkpoint doesn't stop processing, the previous one is kept
+    pub async fn write(&mut self, reader: ReaderState, rejections: Option<&mut RejectionsWriter>) {
+        let position = format!("{}:{}", reader.position.input, reader.position.line);
+        let rejections = match rejections {
+            Some(writer) => match writer.offset().await {
+                Ok(offset) => Some(offset),
+                Err(e) => {
+                    error!("Can't write checkpoint after {position}, rejections not flushed: {e}");
+                    return;
+                }
+            },
+            None => None,
+        };
+        let rows = std::mem::take(&mut self.pending);
+        let result = match self.logged {
+            Some((base, logged)) if logged + rows.len() as u64 <= base => {
+                self.append(rows, reader, rejections).await
+            }
+            _ => self.rewrite(reader, rejections).await,
+        };
+        match result {
+            Ok(()) => info!("Checkpoint written after {position}"),
+            Err(e) => {
+                self.logged = None;
+                error!("Can't write checkpoint after {position}: {e}");
+            }
+        }
+    }
+
+    async fn append(
+        &mut self,
+        rows: Vec<(TransactionLogEntry, Option<RowOrigin>)>,
+        reader: ReaderState,
+        rejections: Option<u64>,
+    ) -> Result<(), CliError> {
+        let path = log_path(&self.path);
+        let unwritable = |e: std::io::Error| CliError::OutputUnwritable(format!("{path}: {e}"));
+        let row_count = rows.len() as u64;
+        let segment = Segment {
+            after: self.processor.current_meta().sequence_number - row_count,
+            rows,
+            reader,
+            rejections,
+        };
+        let mut line = serde_json::to_vec(&segment)
+            .map_err(|e| CliError::OutputUnwritable(format!("{path}: {e}")))?;
+        line.push(b'\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(unwritable)?;
+        file.write_all(&line).await.map_err(unwritable)?;
+        file.sync_data().await.map_err(unwritable)?;
+        if let Some((_, logged)) = &mut self.logged {
+            *logged += row_count;
+        }
+        Ok(())
+    }
+
+    // The log is emptied before the new state replaces the previous one, a crash in between
+    // leaves the previous state alone, an earlier checkpoint the run can resume from
+    async fn rewrite(
+        &mut self,
+        reader: ReaderState,
+        rejections: Option<u64>,
+    ) -> Result<(), CliError> {
+        let checkpoint = Checkpoint::take(self.processor, reader, rejections)?;
+        let path = log_path(&self.path);
+        let unwritable = |e: std::io::Error| CliError::OutputUnwritable(format!("{path}: {e}"));
+        let log = File::create(&path).await.map_err(unwritable)?;
+        log.sync_all().await.map_err(unwritable)?;
+        checkpoint.save(&self.path).await?;
+        self.logged = Some((checkpoint.meta.sequence_number, 0));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        history::TransactionHistoryStorage,
+        transactions_processor::{TransactionLogEntry, TransactionProcessor},
+    };
+    use rust_decimal::{Decimal, dec};
+
+    fn entry(
+        transaction_type: &str,
+        client_id: ClientId,
+        transaction_id: u64,
+        amount: Option<Decimal>,
+    ) -> TransactionLogEntry {
+        TransactionLogEntry {
+            transaction_type: transaction_type.to_string(),
+            client_id,
+            transaction_id,
+            amount,
+            timestamp: Some(100 + transaction_id),
+            reason: Some("fraud".to_string()),
+            origin: None,
+        }
+    }
+
+    fn in_memory_processor() -> Processor {
+        InMemoryTransactionProcessor::with_history(InMemoryTransactionStorage::new().into())
+    }
+
+    #[tokio::test]
+    async fn test_save_and_restore() {
+        let processor = in_memory_processor();
+        for transaction_entry in [
+            entry("deposit", 1, 1, Some(dec!(10.1234))),
+            entry("deposit", 2, 2, Some(dec!(5))),
+            entry("dispute", 1, 1, None),
+            entry("chargeback", 1, 1, None),
+        ] {
+            processor.process(transaction_entry).unwrap();
+        }
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("checkpoint.json");
+        let path = path.to_str().unwrap();
+        let reader = ReaderState {
+            position: InputPosition {
+                input_index: 1,
+                input: "day2.csv".to_string(),
+                header: 22,
+                line: 5,
+                offset: 120,
+            },
+            reorder: ReorderState::default(),
+        };
+
+        assert!(Checkpoint::load(path).await.unwrap().is_none());
+        Checkpoint::take(&processor, reader, None)
+            .unwrap()
+            .save(path)
+            .await
+            .unwrap();
+        let restored = in_memory_processor();
+        let reader = Checkpoint::load(path)
+            .await
+            .unwrap()
+            .unwrap()
+            .restore(&restored)
+            .unwrap();
+
+        assert_eq!(reader.position.offset, 120);
+        assert_eq!(restored.current_meta(), processor.current_meta());
+        assert_eq!(
+            restored.get_history().in_memory().unwrap().snapshot(),
+            processor.get_history().in_memory().unwrap().snapshot()
+        );
+        assert_eq!(
+            restored.get_dispute_report().snapshot(),
+            processor.get_dispute_report().snapshot()
+        );
+        assert_eq!(restored.held_disputes(), processor.held_disputes());
+        let accounts = restored.get_accounts_storage().accounts.read().unwrap();
+        assert_eq!(accounts[&1].total_balance(), dec!(0));
+        assert!(accounts[&1].is_locked());
+        assert_eq!(accounts[&2].total_balance(), dec!(5));
+        assert_eq!(
+            restored.get_history().client_transactions(2).unwrap(),
+            processor.get_history().client_transactions(2).unwrap()
+        );
+    }
+
+    fn reader_at(line: u64) -> ReaderState {
+        ReaderState {
+            position: InputPosition {
+                input_index: 0,
+                input: "day1.csv".to_string(),
+                header: 22,
+                line,
+                offset: 22 + line * 20,
+            },
+            reorder: ReorderState::default(),
+        }
+    }
+
+    fn balances(processor: &Processor) -> Vec<(ClientId, Decimal, Decimal, bool)> {
+        let accounts = processor.get_accounts_storage().accounts.read().unwrap();
+        let mut balances = accounts
+            .iter()
+            .map(|(client_id, account)| {
+                (
+                    *client_id,
+                    account.available_balance(),
+                    account.held_balance(),
+                    account.is_locked(),
+                )
+            })
+            .collect::<Vec<_>>();
+        balances.sort_by_key(|(client_id, ..)| *client_id);
+        balances
+    }
+
+    // a checkpoint every 2 rows: the state, a segment, the state again as the log outgrew it and a
+    // segment after it
+    async fn write_logged_checkpoints(processor: &Processor, path: &str) {
+        let mut checkpoints = Checkpoints::new(processor, path, 2);
+        for (line, transaction_entry) in (2..).zip([
+            entry("deposit", 1, 1, Some(dec!(10))),
+            entry("deposit", 2, 2, Some(dec!(5))),
+            entry("withdrawal", 2, 3, Some(dec!(50))),
+            entry("dispute", 1, 1, None),
+            entry("deposit", 1, 4, Some(dec!(1))),
+            entry("resolve", 1, 1, None),
+            entry("deposit", 2, 5, Some(dec!(3))),
+            entry("dispute", 2, 2, None),
+        ]) {
+            checkpoints.record(transaction_entry.clone());
+            let _result = processor.process(transaction_entry);
+            if line % 2 == 1 {
+                checkpoints.write(reader_at(line), None).await;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_logged_checkpoints_restore_the_processor() {
+        let processor = in_memory_processor();
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("checkpoint.json");
+        let path = path.to_str().unwrap();
+
+        write_logged_checkpoints(&processor, path).await;
+        let log = std::fs::read_to_string(log_path(path)).unwrap();
+        let restored = in_memory_processor();
+        let checkpoint = SavedCheckpoint::load(path).await.unwrap().unwrap();
+        assert_eq!(checkpoint.base.meta.sequence_number, 6);
+        let (reader, rejections) = checkpoint.restore(&restored).unwrap();
+
+        assert_eq!(log.lines().count(), 1);
+        assert_eq!(reader.position.line, 9);
+        assert_eq!(rejections, None);
+        assert_eq!(restored.current_meta(), processor.current_meta());
+        assert_eq!(balances(&restored), balances(&processor));
+        assert_eq!(
+            restored.get_history().in_memory().unwrap().snapshot(),
+            processor.get_history().in_memory().unwrap().snapshot()
+        );
+        assert_eq!(
+            restored.get_dispute_report().snapshot(),
+            processor.get_dispute_report().snapshot()
+        );
+        assert_eq!(restored.held_disputes(), processor.held_disputes());
+    }
+
+    #[tokio::test]
+    async fn test_incomplete_last_segment_is_ignored() {
+        let processor = in_memory_processor();
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("checkpoint.json");
+        let path = path.to_str().unwrap();
+        write_logged_checkpoints(&processor, path).await;
+        let mut log = OpenOptions::new()
+            .append(true)
+            .open(log_path(path))
+            .await
+            .unwrap();
+        log.write_all(br#"{"after":8,"rows":[[{"type":"dep"#)
+            .await
+            .unwrap();
+
+        let restored = in_memory_processor();
+        let (reader, _) = SavedCheckpoint::load(path)
+            .await
+            .unwrap()
+            .unwrap()
+            .restore(&restored)
+            .unwrap();
+
+        assert_eq!(reader.position.line, 9);
+        assert_eq!(balances(&restored), balances(&processor));
+    }
+
+    #[tokio::test]
+    async fn test_segment_not_following_the_state_is_refused() {
+        let processor = in_memory_processor();
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("checkpoint.json");
+        let path = path.to_str().unwrap();
+        write_logged_checkpoints(&processor, path).await;
+        let log = std::fs::read_to_string(log_path(path)).unwrap();
+        std::fs::write(log_path(path), format!("{log}{log}")).unwrap();
+
+        let result = SavedCheckpoint::load(path)
+            .await
+            .unwrap()
+            .unwrap()
+            .restore(&in_memory_processor());
+
+        assert!(matches!(result, Err(CliError::InputUnreadable(_))));
+    }
+}
@@ -0,0 +1,548 @@
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+
+use crate::{
+    compression::Compression,
+    history::DisputeWindow,
+    reorder,
+    storage::ClientId,
+    transactions::{SequenceNumber, Timestamp},
+};
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "Process transactions from a CSV file and print account balances as CSV",
+    long_about = "Process transactions from a CSV file and print account balances as CSV.\n\n\
+                  Without a subcommand the arguments are the ones of `process`.\n\n\
+                  Exit codes: 0 success, 1 invalid input rows (validate), 2 wrong arguments, \
+                  3 unreadable input, 4 unwritable output, 5 rejected row (fail-fast), \
+                  6 server can't listen, 7 interrupted with partial results.",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[arg(
+        required = true,
+        value_name = "INPUT",
+        help = "Input CSV or JSON Lines files with transactions, read in order, `-` reads stdin"
+    )]
+    pub inputs: Vec<String>,
+    #[command(flatten)]
+    pub process: ProcessOptions,
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = LogLevel::Error,
+        help = "Level of the log written to stderr"
+    )]
+    pub log_level: LogLevel,
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = LogFormat::Text,
+        help = "Format of the log lines"
+    )]
+    pub log_format: LogFormat,
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Append the log to this file instead of writing it to stderr"
+    )]
+    pub log_file: Option<String>,
+}
+
+impl Cli {
+    pub fn into_command(self) -> Command {
+        match self.command {
+            Some(command) => command,
+            // clap requires the input argument when no subcommand is given
+            None => Command::Process(ProcessArgs {
+                inputs: self.inputs,
+                options: self.process,
+            }),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    #[command(about = "Process transactions and print account balances")]
+    Process(ProcessArgs),
+    #[command(about = "Check input rows without processing them, invalid rows are listed")]
+    Validate(ValidateArgs),
+    #[command(about = "Process transactions and print row, rejection and balance counters")]
+    Stats(StatsArgs),
+    #[command(about = "Process transactions and print account statements of the given clients")]
+    Statement(StatementArgs),
+    #[command(about = "Process transactions up to a given row or time and print account balances")]
+    Replay(ReplayArgs),
+    #[command(about = "Accept transaction lines and balance queries over TCP")]
+    Serve(ServeArgs),
+    #[command(about = "Serve a JSON HTTP API for transactions, accounts and open disputes")]
+    Http(HttpArgs),
+    #[command(about = "Answer JSON-RPC requests read line by line from stdin on stdout")]
+    Rpc(RpcArgs),
+}
+
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for tracing::Level {
+    fn from(value: LogLevel) -> Self {
+        match value {
+            LogLevel::Error => tracing::Level::ERROR,
+            LogLevel::Warn => tracing::Level::WARN,
+            LogLevel::Info => tracing::Level::INFO,
+            LogLevel::Debug => tracing::Level::DEBUG,
+            LogLevel::Trace => tracing::Level::TRACE,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+    Pretty,
+}
+
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq)]
+pub enum InputFormat {
+    Csv,
+    Jsonl,
+}
+
+impl InputFormat {
+    // `.jsonl` and `.ndjson` files are JSON Lines, everything else including stdin is CSV
+    pub fn for_input(input: &str) -> Self {
+        match extension(input).as_deref() {
+            Some("jsonl" | "ndjson") => InputFormat::Jsonl,
+            _ => InputFormat::Csv,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq)]
+pub enum OutputFormat {
+    Csv,
+    Json,
+    Jsonl,
+}
+
+// `.gz` and `.zst` are skipped, the format is the extension of the compressed content
+fn extension(path: &str) -> Option<String> {
+    std::path::Path::new(Compression::strip_extension(path))
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+#[derive(Args, Debug, Default)]
+pub struct EngineArgs {
+    #[arg(
+        long,
+        value_enum,
+        help = "Format of all inputs, taken from each input extension when not given"
+    )]
+    pub input_format: Option<InputFormat>,
+    #[arg(
+        long,
+        help = "Reject deposits to clients without an opened or onboarded account"
+    )]
+    pub strict_accounts: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "CSV file with a `client` column listing onboarded clients"
+    )]
+    pub onboarding: Option<String>,
+    #[arg(
+        long,
+        help = "Unlock accounts locked by a chargeback once the chargeback is represented"
+    )]
+    pub unlock_on_representment: bool,
+    #[arg(
+        long,
+        help = "Stop at the first rejected row and exit with an error instead of printing accounts"
+    )]
+    pub fail_fast: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write metrics in Prometheus text format to this file when the run finishes"
+    )]
+    pub metrics: Option<String>,
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        help = "Serve metrics in Prometheus text format on http://ADDRESS/metrics while running"
+    )]
+    pub metrics_listen: Option<String>,
+    #[arg(
+        long,
+        value_name = "N|Ns",
+        help = "Reject disputes more than N rows or N seconds after their transaction and drop such transactions from the history"
+    )]
+    pub dispute_window: Option<DisputeWindow>,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Keep at most about this many bytes of transaction history in memory and spill the rest to a temporary file"
+    )]
+    pub history_memory_budget: Option<u64>,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = reorder::DEFAULT_TOLERANCE_SECONDS,
+        help = "Put input rows in timestamp order when they are at most this many seconds late, later rows are rejected"
+    )]
+    pub reorder_tolerance: Timestamp,
+    #[arg(
+        long,
+        value_name = "ROWS",
+        default_value_t = reorder::DEFAULT_CAPACITY,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        help = "Rows held at most while putting input rows in timestamp order"
+    )]
+    pub reorder_buffer: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct OutputArgs {
+    #[arg(
+        short,
+        long,
+        value_name = "FILE",
+        help = "Write the result to this file instead of stdout"
+    )]
+    pub output: Option<String>,
+    #[arg(
+        long,
+        value_enum,
+        help = "Format of the result, taken from the --output extension when not given, csv by default"
+    )]
+    pub format: Option<OutputFormat>,
+}
+
+impl OutputArgs {
+    pub fn format(&self) -> OutputFormat {
+        let from_extension = || match self.output.as_deref().and_then(extension).as_deref() {
+            Some("json") => OutputFormat::Json,
+            Some("jsonl" | "ndjson") => OutputFormat::Jsonl,
+            _ => OutputFormat::Csv,
+        };
+        self.format.unwrap_or_else(from_extension)
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ProcessArgs {
+    #[arg(
+        required = true,
+        value_name = "INPUT",
+        help = "Input CSV or JSON Lines files with transactions, read in order, `-` reads stdin"
+    )]
+    pub inputs: Vec<String>,
+    #[command(flatten)]
+    pub options: ProcessOptions,
+}
+
+#[derive(Args, Debug)]
+pub struct ProcessOptions {
+    #[command(flatten)]
+    pub engine: EngineArgs,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write rejected rows with the rejection reason to this CSV file"
+    )]
+    pub rejections: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write per-reason dispute counts and amounts to this CSV file"
+    )]
+    pub dispute_report: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write every transaction status change with the row that caused it to this CSV file"
+    )]
+    pub audit: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write every transaction holding funds with its dispute age to this CSV file"
+    )]
+    pub open_disputes: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write held funds per client, matching the `held` output column, to this CSV file"
+    )]
+    pub held_funds: Option<String>,
+    #[arg(
+        long,
+        help = "Keep reading the last input as it grows, reopening it when it is rotated or truncated"
+    )]
+    pub follow: bool,
+    #[arg(
+        long,
+        value_name = "MILLISECONDS",
+        default_value_t = 500,
+        requires = "follow",
+        help = "How often a followed input is checked for new rows"
+    )]
+    pub poll_interval: u64,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        requires = "follow",
+        help = "Write the accounts to the output every given seconds while following, SIGUSR1 writes them at any time"
+    )]
+    pub snapshot_interval: Option<u64>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["follow", "history_memory_budget"],
+        help = "Write the processing state to this file every --checkpoint-interval rows and when interrupted"
+    )]
+    pub checkpoint: Option<String>,
+    #[arg(
+        long,
+        value_name = "ROWS",
+        default_value_t = 100_000,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "checkpoint",
+        help = "Rows read between two checkpoints"
+    )]
+    pub checkpoint_interval: u64,
+    #[arg(
+        long,
+        requires = "checkpoint",
+        help = "Continue from the --checkpoint file of an earlier run over the same inputs, if there is one"
+    )]
+    pub resume: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ValidateArgs {
+    #[arg(
+        required = true,
+        value_name = "INPUT",
+        help = "Input CSV or JSON Lines files with transactions, read in order, `-` reads stdin"
+    )]
+    pub inputs: Vec<String>,
+    #[arg(
+        long,
+        value_enum,
+        help = "Format of all inputs, taken from each input extension when not given"
+    )]
+    pub input_format: Option<InputFormat>,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    #[arg(
+        required = true,
+        value_name = "INPUT",
+        help = "Input CSV or JSON Lines files with transactions, read in order, `-` reads stdin"
+    )]
+    pub inputs: Vec<String>,
+    #[command(flatten)]
+    pub engine: EngineArgs,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct StatementArgs {
+    #[arg(
+        required = true,
+        value_name = "INPUT",
+        help = "Input CSV or JSON Lines files with transactions, read in order, `-` reads stdin"
+    )]
+    pub inputs: Vec<String>,
+    #[command(flatten)]
+    pub engine: EngineArgs,
+    #[arg(
+        long = "client",
+        value_name = "CLIENT",
+        value_delimiter = ',',
+        required = true,
+        help = "Client ids to write account statements for, comma-separated or repeated"
+    )]
+    pub client_ids: Vec<ClientId>,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Args, Debug)]
+#[command(group(
+    ArgGroup::new("until")
+        .args(["until_row", "until_timestamp"])
+        .required(true)
+        .multiple(true)
+))]
+pub struct ReplayArgs {
+    #[arg(
+        required = true,
+        value_name = "INPUT",
+        help = "Input CSV or JSON Lines files with transactions, read in order, `-` reads stdin"
+    )]
+    pub inputs: Vec<String>,
+    #[command(flatten)]
+    pub engine: EngineArgs,
+    #[arg(
+        long,
+        value_name = "ROW",
+        help = "Stop after this many input rows, counted in processing order"
+    )]
+    pub until_row: Option<SequenceNumber>,
+    #[arg(
+        long,
+        value_name = "TIMESTAMP",
+        help = "Stop before the first row with a later timestamp"
+    )]
+    pub until_timestamp: Option<Timestamp>,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        default_value = "127.0.0.1:7878",
+        help = "Address to listen on"
+    )]
+    pub listen: String,
+    #[command(flatten)]
+    pub engine: EngineArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct RpcArgs {
+    #[command(flatten)]
+    pub engine: EngineArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct HttpArgs {
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        default_value = "127.0.0.1:8080",
+        help = "Address to listen on"
+    )]
+    pub listen: String,
+    #[command(flatten)]
+    pub engine: EngineArgs,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn test_input_without_subcommand_is_process() {
+        let cli = Cli::try_parse_from(["app", "input.csv", "--strict-accounts", "-o", "out.csv"])
+            .unwrap();
+
+        let Command::Process(args) = cli.into_command() else {
+            panic!("expected process command");
+        };
+        assert_eq!(args.inputs, vec!["input.csv"]);
+        assert!(args.options.engine.strict_accounts);
+        assert_eq!(args.options.output.output.as_deref(), Some("out.csv"));
+    }
+
+    #[rstest]
+    #[case(&["app"])]
+    #[case(&["app", "--strict-accounts"])]
+    #[case(&["app", "replay", "input.csv"])]
+    #[case(&["app", "statement", "input.csv"])]
+    #[case(&["app", "validate", "input.csv", "--client", "1"])]
+    #[case(&["app", "input.csv", "--snapshot-interval", "5"])]
+    #[case(&["app", "stats", "input.csv", "--follow"])]
+    #[case(&["app", "in.csv", "--checkpoint", "c.json", "--history-memory-budget", "1000"])]
+    #[case(&["app", "in.csv", "--history-memory-budget", "0"])]
+    #[case(&["app", "in.csv", "--dispute-window", "1h"])]
+    fn test_invalid_arguments(#[case] args: &[&str]) {
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[rstest]
+    #[case(&["app", "in.csv"], OutputFormat::Csv)]
+    #[case(&["app", "in.csv", "-o", "out.JSON"], OutputFormat::Json)]
+    #[case(&["app", "in.csv", "-o", "out.ndjson"], OutputFormat::Jsonl)]
+    #[case(&["app", "in.csv", "-o", "out.json.gz"], OutputFormat::Json)]
+    #[case(&["app", "in.csv", "-o", "out.json", "--format", "csv"], OutputFormat::Csv)]
+    #[case(&["app", "in.csv", "--format", "jsonl"], OutputFormat::Jsonl)]
+    fn test_output_format(#[case] args: &[&str], #[case] format: OutputFormat) {
+        let Command::Process(args) = Cli::try_parse_from(args).unwrap().into_command() else {
+            panic!("expected process command");
+        };
+        assert_eq!(args.options.output.format(), format);
+    }
+
+    #[rstest]
+    #[case("-", InputFormat::Csv)]
+    #[case("input.csv", InputFormat::Csv)]
+    #[case("dir.jsonl/input", InputFormat::Csv)]
+    #[case("input.jsonl", InputFormat::Jsonl)]
+    #[case("input.NDJSON", InputFormat::Jsonl)]
+    #[case("input.jsonl.gz", InputFormat::Jsonl)]
+    #[case("input.csv.zst", InputFormat::Csv)]
+    fn test_input_format_for_input(#[case] input: &str, #[case] format: InputFormat) {
+        assert_eq!(InputFormat::for_input(input), format);
+    }
+
+    #[rstest]
+    #[case(&["app", "in.csv"], None)]
+    #[case(&["app", "in.csv", "--dispute-window", "500"], Some(DisputeWindow::Transactions(500)))]
+    #[case(&["app", "in.csv", "--dispute-window", "3600s"], Some(DisputeWindow::Seconds(3600)))]
+    fn test_dispute_window(#[case] args: &[&str], #[case] dispute_window: Option<DisputeWindow>) {
+        let Command::Process(args) = Cli::try_parse_from(args).unwrap().into_command() else {
+            panic!("expected process command");
+        };
+        assert_eq!(args.options.engine.dispute_window, dispute_window);
+    }
+
+    #[test]
+    fn test_subcommand_with_global_log_level() {
+        let cli = Cli::try_parse_from([
+            "app",
+            "statement",
+            "input.csv",
+            "--client",
+            "1,2",
+            "--log-level",
+            "debug",
+        ])
+        .unwrap();
+
+        assert_eq!(cli.log_level, LogLevel::Debug);
+        let Command::Statement(args) = cli.into_command() else {
+            panic!("expected statement command");
+        };
+        assert_eq!(args.client_ids, vec![1, 2]);
+    }
+}
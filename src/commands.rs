@@ -0,0 +1,1219 @@
+use std::{collections::BTreeMap, convert::Infallible, sync::Arc, time::Duration};
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+use tokio::{io::AsyncWriteExt, net::TcpListener};
+use tracing::{error, info};
+
+use crate::{
+    checkpoint::Checkpoints,
+    cli::{
+        Command, EngineArgs, HttpArgs, OutputArgs, ProcessArgs, ReplayArgs, RpcArgs, ServeArgs,
+        StatementArgs, StatsArgs, ValidateArgs,
+    },
+    compression::Compression,
+    csv_utils::{self, ReadEvent, ReadOutcome, RejectionsWriter},
+    errors::{CliError, TransactionLogError},
+    follow::SnapshotTrigger,
+    history::{HistoryStorage, InMemoryTransactionStorage, TransactionHistoryStorage},
+    http_api, metrics,
+    pipeline::{InputsSource, TransactionSource, WriterSink},
+    reorder::{self, ReorderBuffer},
+    rpc,
+    server::{self, ServerOptions},
+    shutdown::Shutdown,
+    storage::{AccountStatus, AccountStorage, InMemoryAccountsStorage, RepresentmentPolicy},
+    tiered_history::TieredTransactionStorage,
+    transactions::{SequenceNumber, Timestamp},
+    transactions_processor::{InMemoryTransactionProcessor, RowOrigin, TransactionProcessor},
+};
+
+const CHANNEL_SIZE: usize = 4096;
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct StopAt {
+    pub row: Option<SequenceNumber>,
+    pub timestamp: Option<Timestamp>,
+}
+
+// How `feed` reads its source: rows after `stop_at` are not processed, with `fail_fast` the first
+// rejected row stops the run, rows are put in timestamp order within `reorder_tolerance` seconds
+// holding at most `reorder_buffer` rows
+#[derive(Debug, Copy, Clone)]
+pub struct FeedOptions {
+    pub stop_at: StopAt,
+    pub fail_fast: bool,
+    pub reorder_tolerance: Timestamp,
+    pub reorder_buffer: usize,
+}
+
+impl Default for FeedOptions {
+    fn default() -> Self {
+        Self {
+            stop_at: StopAt::default(),
+            fail_fast: false,
+            reorder_tolerance: reorder::DEFAULT_TOLERANCE_SECONDS,
+            reorder_buffer: reorder::DEFAULT_CAPACITY,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ProcessingStats {
+    pub rows: u64,
+    pub rejected: u64,
+    pub rows_by_type: BTreeMap<String, u64>,
+    pub rejections_by_error: BTreeMap<String, u64>,
+    pub last_origin: Option<RowOrigin>,
+    // the shutdown stopped reading before the end of the inputs
+    pub interrupted: bool,
+}
+
+pub async fn run(command: Command) -> Result<(), CliError> {
+    match command {
+        Command::Process(args) => process(args).await,
+        Command::Validate(args) => validate(args).await,
+        Command::Stats(args) => stats(args).await,
+        Command::Statement(args) => statement(args).await,
+        Command::Replay(args) => replay(args).await,
+        Command::Serve(args) => serve(args).await,
+        Command::Http(args) => http(args).await,
+        Command::Rpc(args) => rpc(args).await,
+    }
+}
+
+fn output_error(e: impl ToString) -> CliError {
+    CliError::OutputUnwritable(e.to_string())
+}
+
+// Runs until an output error, it is dropped when the followed input ends
+async fn write_snapshots<H: TransactionHistoryStorage>(
+    processor: &InMemoryTransactionProcessor<H>,
+    output: &OutputArgs,
+    trigger: &mut SnapshotTrigger,
+) -> Result<Infallible, CliError> {
+    loop {
+        trigger.wait().await;
+        info!("Writing accounts snapshot");
+        write_accounts(processor, output).await?;
+    }
+}
+
+// A file output is rewritten on every call, so it always holds the latest accounts
+async fn write_accounts<H: TransactionHistoryStorage>(
+    processor: &InMemoryTransactionProcessor<H>,
+    output: &OutputArgs,
+) -> Result<(), CliError> {
+    let mut sink = WriterSink::open(output.output.as_deref(), output.format()).await?;
+    csv_utils::output_data(processor.get_accounts_storage(), &mut sink)
+        .await
+        .map_err(output_error)
+}
+
+fn inputs_source(inputs: &[String], engine: &EngineArgs) -> InputsSource {
+    InputsSource::new(inputs.to_vec(), engine.input_format)
+}
+
+fn feed_options(engine: &EngineArgs) -> FeedOptions {
+    FeedOptions {
+        fail_fast: engine.fail_fast,
+        reorder_tolerance: engine.reorder_tolerance,
+        reorder_buffer: engine.reorder_buffer,
+        ..FeedOptions::default()
+    }
+}
+
+fn build_history(engine: &EngineArgs) -> Result<HistoryStorage, CliError> {
+    let Some(memory_budget) = engine.history_memory_budget else {
+        let mut history = InMemoryTransactionStorage::new();
+        if let Some(dispute_window) = engine.dispute_window {
+            history = history.with_dispute_window(dispute_window);
+        }
+        return Ok(history.into());
+    };
+    let memory_budget = usize::try_from(memory_budget).unwrap_or(usize::MAX);
+    let mut history = TieredTransactionStorage::new(memory_budget)
+        .map_err(|e| CliError::OutputUnwritable(format!("history spill file: {e}")))?;
+    if let Some(dispute_window) = engine.dispute_window {
+        history = history.with_dispute_window(dispute_window);
+    }
+    Ok(history.into())
+}
+
+// Starts the metrics endpoint along with the processor, it is served until the command returns
+async fn build_processor(
+    engine: &EngineArgs,
+) -> Result<Arc<InMemoryTransactionProcessor<HistoryStorage>>, CliError> {
+    let mut accounts_storage = InMemoryAccountsStorage::new();
+    if engine.strict_accounts {
+        accounts_storage = accounts_storage.with_strict_opening();
+    }
+    if engine.unlock_on_representment {
+        accounts_storage = accounts_storage.with_representment_policy(RepresentmentPolicy::Unlock);
+    }
+    if let Some(onboarding_path) = &engine.onboarding {
+        let client_ids = csv_utils::read_onboarding_list(onboarding_path)
+            .await
+            .map_err(|e| CliError::InputUnreadable(format!("{onboarding_path}: {e}")))?;
+        for client_id in client_ids {
+            accounts_storage.create_user(client_id);
+        }
+    }
+    let processor = Arc::new(InMemoryTransactionProcessor::with_storages(
+        accounts_storage,
+        build_history(engine)?,
+    ));
+    if let Some(address) = &engine.metrics_listen {
+        let listener = TcpListener::bind(address)
+            .await
+            .map_err(|e| CliError::ServerUnavailable(format!("{address}: {e}")))?;
+        info!("Serving metrics on {address}");
+        let router = metrics::router(processor.clone());
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router).await {
+                error!("Metrics endpoint stopped: {e}");
+            }
+        });
+    }
+    Ok(processor)
+}
+
+async fn write_metrics<H: TransactionHistoryStorage>(
+    processor: &InMemoryTransactionProcessor<H>,
+    engine: &EngineArgs,
+) -> Result<(), CliError> {
+    let Some(metrics_path) = &engine.metrics else {
+        return Ok(());
+    };
+    let mut output = csv_utils::open_output(Some(metrics_path))
+        .await
+        .map_err(output_error)?;
+    output
+        .write_all(metrics::render(processor).as_bytes())
+        .await
+        .map_err(output_error)?;
+    output.shutdown().await.map_err(output_error)
+}
+
+// written next to a partial output file
+#[derive(Serialize)]
+struct PartialOutput<'a> {
+    rows: u64,
+    last_processed: Option<&'a RowOrigin>,
+}
+
+fn partial_marker_path(output_path: &str) -> String {
+    format!("{output_path}.partial")
+}
+
+// The results of an interrupted run are written as usual, the error marks them as partial. A file
+// output gets a `<FILE>.partial` marker with the rows processed and the last processed row, a
+// complete run removes the marker left by an earlier one
+async fn check_complete(stats: &ProcessingStats, output: &OutputArgs) -> Result<(), CliError> {
+    if let Some(output_path) = &output.output {
+        let marker_path = partial_marker_path(output_path);
+        let unwritable =
+            |e: std::io::Error| CliError::OutputUnwritable(format!("{marker_path}: {e}"));
+        if stats.interrupted {
+            let marker = serde_json::to_vec(&PartialOutput {
+                rows: stats.rows,
+                last_processed: stats.last_origin.as_ref(),
+            })
+            .map_err(|e| CliError::OutputUnwritable(format!("{marker_path}: {e}")))?;
+            tokio::fs::write(&marker_path, marker)
+                .await
+                .map_err(unwritable)?;
+        } else {
+            match tokio::fs::remove_file(&marker_path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(unwritable(e)),
+                _ => {}
+            }
+        }
+    }
+    if !stats.interrupted {
+        return Ok(());
+    }
+    Err(CliError::Interrupted {
+        rows: stats.rows,
+        last_origin: stats.last_origin.clone(),
+    })
+}
+
+// Feeds the rows of `source` through the processor. With `fail_fast` the first rejected row stops
+// the run and is returned as error. Rows too late to be put in timestamp order are rejected without
+// being processed. On shutdown reading stops and the rows already read are processed before returning.
+// Checkpoints are written between rows, when the processor holds exactly the rows sent before them
+async fn feed<P: TransactionProcessor, S: TransactionSource + 'static>(
+    processor: &P,
+    source: S,
+    options: FeedOptions,
+    mut rejections: Option<&mut RejectionsWriter>,
+    mut checkpoints: Option<&mut Checkpoints<'_>>,
+    shutdown: Shutdown,
+) -> Result<ProcessingStats, CliError> {
+    let FeedOptions {
+        stop_at, fail_fast, ..
+    } = options;
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(CHANNEL_SIZE);
+    let mut reorder_buffer = ReorderBuffer::new(options.reorder_tolerance, options.reorder_buffer);
+    if let Some(resumed) = checkpoints.as_mut().and_then(|c| c.take_resumed()) {
+        reorder_buffer.restore(resumed);
+    }
+    let reader = tokio::spawn(csv_utils::read_data(
+        source,
+        sender,
+        reorder_buffer,
+        fail_fast,
+        checkpoints.as_ref().map(|c| c.interval),
+        shutdown,
+    ));
+
+    let mut stats = ProcessingStats::default();
+    while let Some(event) = receiver.recv().await {
+        let (tx, is_late) = match event {
+            ReadEvent::Row(tx) => (tx, false),
+            ReadEvent::Late(tx) => (tx, true),
+            ReadEvent::Checkpoint(state) => {
+                if let Some(checkpoints) = checkpoints.as_deref_mut() {
+                    checkpoints.write(state, rejections.as_deref_mut()).await;
+                }
+                continue;
+            }
+        };
+        let is_after_stop = stop_at.row.is_some_and(|row| stats.rows >= row)
+            || stop_at
+                .timestamp
+                .zip(tx.timestamp)
+                .is_some_and(|(stop_timestamp, timestamp)| timestamp > stop_timestamp);
+        if is_after_stop {
+            info!("Stopped after {} rows", stats.rows);
+            reader.abort();
+            return Ok(stats);
+        }
+        stats.rows += 1;
+        stats.last_origin.clone_from(&tx.origin);
+        *stats
+            .rows_by_type
+            .entry(tx.transaction_type.clone())
+            .or_default() += 1;
+        let entry = (fail_fast || rejections.is_some()).then(|| tx.clone());
+        let result = if is_late {
+            Err(Box::new(TransactionLogError::LateRow).into())
+        } else {
+            if let Some(checkpoints) = checkpoints.as_deref_mut() {
+                checkpoints.record(tx.clone());
+            }
+            processor.process(tx)
+        };
+        if let Err(e) = result {
+            stats.rejected += 1;
+            *stats.rejections_by_error.entry(e.to_string()).or_default() += 1;
+            if let (Some(writer), Some(entry)) = (rejections.as_deref_mut(), &entry) {
+                writer.write(entry, e.as_ref()).await;
+            }
+            if fail_fast && let Some(entry) = entry {
+                reader.abort();
+                return Err(CliError::RejectedRow {
+                    record: entry.to_string(),
+                    origin: entry.origin,
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+    match reader.await {
+        Ok(result) => result.map(|outcome| ProcessingStats {
+            interrupted: outcome == ReadOutcome::Interrupted,
+            ..stats
+        }),
+        Err(e) => Err(CliError::InputUnreadable(e.to_string())),
+    }
+}
+
+async fn process(args: ProcessArgs) -> Result<(), CliError> {
+    let processor = build_processor(&args.options.engine).await?;
+    // checkpoints record how much of the rejections file was written by its size
+    if args.options.checkpoint.is_some()
+        && let Some(rejections_path) = &args.options.rejections
+        && Compression::for_path(rejections_path) != Compression::None
+    {
+        return Err(CliError::OutputUnwritable(format!(
+            "{rejections_path}: checkpoints can't be combined with compressed rejections"
+        )));
+    }
+
+    let mut source = inputs_source(&args.inputs, &args.options.engine);
+    if args.options.follow {
+        source = source.with_follow(Duration::from_millis(args.options.poll_interval));
+    }
+    let mut checkpoints = args.options.checkpoint.as_deref().map(|checkpoint_path| {
+        Checkpoints::new(
+            processor.as_ref(),
+            checkpoint_path,
+            args.options.checkpoint_interval,
+        )
+    });
+    let mut rejections_offset = None;
+    if args.options.resume
+        && let Some(checkpoints) = &mut checkpoints
+        && let Some(resume_point) = checkpoints.resume().await?
+    {
+        source = source.with_resume(resume_point.position);
+        rejections_offset = resume_point.rejections;
+    }
+    let mut rejections = match (&args.options.rejections, rejections_offset) {
+        (Some(rejections_path), Some(offset)) => Some(
+            RejectionsWriter::append(rejections_path, offset)
+                .await
+                .map_err(output_error)?,
+        ),
+        (Some(rejections_path), None) => Some(
+            RejectionsWriter::create(rejections_path)
+                .await
+                .map_err(output_error)?,
+        ),
+        (None, _) => None,
+    };
+    let feeding = feed(
+        processor.as_ref(),
+        source,
+        feed_options(&args.options.engine),
+        rejections.as_mut(),
+        checkpoints.as_mut(),
+        Shutdown::on_signals(),
+    );
+    let stats = if args.options.follow {
+        let snapshot_interval = args.options.snapshot_interval.map(Duration::from_secs);
+        let mut trigger = SnapshotTrigger::new(snapshot_interval).map_err(output_error)?;
+        let snapshots = write_snapshots(&processor, &args.options.output, &mut trigger);
+        tokio::select! {
+            result = feeding => result?,
+            result = snapshots => {
+                let Err(e) = result;
+                return Err(e);
+            }
+        }
+    } else {
+        feeding.await?
+    };
+    if let Some(writer) = rejections {
+        writer.finish().await;
+    }
+
+    if let Some(dispute_report_path) = &args.options.dispute_report {
+        csv_utils::write_dispute_report(dispute_report_path, processor.get_dispute_report())
+            .await
+            .map_err(output_error)?;
+    }
+    if let Some(audit_path) = &args.options.audit {
+        csv_utils::write_audit_trail(audit_path, processor.get_history())
+            .await
+            .map_err(output_error)?;
+    }
+    if let Some(open_disputes_path) = &args.options.open_disputes {
+        csv_utils::write_open_disputes(
+            open_disputes_path,
+            processor.get_history(),
+            &processor.current_meta(),
+        )
+        .await
+        .map_err(output_error)?;
+    }
+    if let Some(held_funds_path) = &args.options.held_funds {
+        csv_utils::write_held_funds(
+            held_funds_path,
+            processor.get_history(),
+            &processor.current_meta(),
+        )
+        .await
+        .map_err(output_error)?;
+    }
+
+    write_accounts(&processor, &args.options.output).await?;
+    write_metrics(&processor, &args.options.engine).await?;
+    // a followed input has no end, the shutdown is how following stops
+    if args.options.follow {
+        let last_origin = stats
+            .last_origin
+            .map_or("none".to_string(), |o| o.to_string());
+        info!(
+            "Stopped following after {} rows, last processed row at {last_origin}",
+            stats.rows
+        );
+        return Ok(());
+    }
+    check_complete(&stats, &args.options.output).await
+}
+
+async fn validate(args: ValidateArgs) -> Result<(), CliError> {
+    let output = csv_utils::open_output(args.output.output.as_deref())
+        .await
+        .map_err(output_error)?;
+    let invalid_rows = csv_utils::validate_data(
+        InputsSource::new(args.inputs, args.input_format),
+        output,
+        args.output.format(),
+    )
+    .await?;
+    if invalid_rows > 0 {
+        return Err(CliError::InvalidRows(invalid_rows));
+    }
+    Ok(())
+}
+
+async fn stats(args: StatsArgs) -> Result<(), CliError> {
+    let processor = build_processor(&args.engine).await?;
+    let stats = feed(
+        processor.as_ref(),
+        inputs_source(&args.inputs, &args.engine),
+        feed_options(&args.engine),
+        None,
+        None,
+        Shutdown::on_signals(),
+    )
+    .await?;
+
+    let (clients, locked_accounts, available, held) = {
+        let accounts = processor.get_accounts_storage().accounts.read().unwrap();
+        let locked_accounts = accounts
+            .values()
+            .filter(|account| account.status() == AccountStatus::Locked)
+            .count();
+        let available = accounts
+            .values()
+            .map(|account| account.available_balance())
+            .sum::<Decimal>();
+        let held = accounts
+            .values()
+            .map(|account| account.held_balance())
+            .sum::<Decimal>();
+        (accounts.len(), locked_accounts, available, held)
+    };
+    let mut metrics = vec![
+        ("rows".to_string(), stats.rows.to_string()),
+        (
+            "accepted".to_string(),
+            (stats.rows - stats.rejected).to_string(),
+        ),
+        ("rejected".to_string(), stats.rejected.to_string()),
+        ("clients".to_string(), clients.to_string()),
+        ("locked_accounts".to_string(), locked_accounts.to_string()),
+        ("available".to_string(), available.to_string()),
+        ("held".to_string(), held.to_string()),
+    ];
+    for (transaction_type, count) in &stats.rows_by_type {
+        metrics.push((format!("rows.{transaction_type}"), count.to_string()));
+    }
+    for (error, count) in &stats.rejections_by_error {
+        metrics.push((format!("rejected.{error}"), count.to_string()));
+    }
+
+    let output = csv_utils::open_output(args.output.output.as_deref())
+        .await
+        .map_err(output_error)?;
+    csv_utils::write_stats(output, args.output.format(), &metrics)
+        .await
+        .map_err(output_error)?;
+    write_metrics(&processor, &args.engine).await?;
+    check_complete(&stats, &args.output).await
+}
+
+async fn statement(args: StatementArgs) -> Result<(), CliError> {
+    let processor = build_processor(&args.engine).await?;
+    let stats = feed(
+        processor.as_ref(),
+        inputs_source(&args.inputs, &args.engine),
+        feed_options(&args.engine),
+        None,
+        None,
+        Shutdown::on_signals(),
+    )
+    .await?;
+
+    let output = csv_utils::open_output(args.output.output.as_deref())
+        .await
+        .map_err(output_error)?;
+    csv_utils::write_statements(
+        output,
+        args.output.format(),
+        processor.get_history(),
+        &args.client_ids,
+    )
+    .await
+    .map_err(output_error)?;
+    write_metrics(&processor, &args.engine).await?;
+    check_complete(&stats, &args.output).await
+}
+
+async fn replay(args: ReplayArgs) -> Result<(), CliError> {
+    let processor = build_processor(&args.engine).await?;
+    let stop_at = StopAt {
+        row: args.until_row,
+        timestamp: args.until_timestamp,
+    };
+    let stats = feed(
+        processor.as_ref(),
+        inputs_source(&args.inputs, &args.engine),
+        FeedOptions {
+            stop_at,
+            ..feed_options(&args.engine)
+        },
+        None,
+        None,
+        Shutdown::on_signals(),
+    )
+    .await?;
+
+    write_accounts(&processor, &args.output).await?;
+    write_metrics(&processor, &args.engine).await?;
+    check_complete(&stats, &args.output).await
+}
+
+// Runs until the listener fails or the shutdown, `--input-format` fixes the line format and
+// `--fail-fast` closes a connection on its first rejected line
+async fn serve(args: ServeArgs) -> Result<(), CliError> {
+    let processor = build_processor(&args.engine).await?;
+    let listener = TcpListener::bind(&args.listen)
+        .await
+        .map_err(|e| CliError::ServerUnavailable(format!("{}: {e}", args.listen)))?;
+    info!("Listening on {}", args.listen);
+    let options = ServerOptions {
+        line_format: args.engine.input_format,
+        fail_fast: args.engine.fail_fast,
+    };
+    let mut shutdown = Shutdown::on_signals();
+    tokio::select! {
+        result = server::serve(listener, processor.clone(), options) => {
+            result.map_err(|e| CliError::ServerUnavailable(e.to_string()))?;
+        }
+        _ = shutdown.wait() => info!("Stopped accepting connections"),
+    }
+    write_metrics(&processor, &args.engine).await
+}
+
+// Runs until the listener fails or the shutdown, which waits for running requests. Every request is
+// processed right away, so `--fail-fast` has no effect
+async fn http(args: HttpArgs) -> Result<(), CliError> {
+    let processor = build_processor(&args.engine).await?;
+    let listener = TcpListener::bind(&args.listen)
+        .await
+        .map_err(|e| CliError::ServerUnavailable(format!("{}: {e}", args.listen)))?;
+    info!("Listening for HTTP requests on {}", args.listen);
+    let mut shutdown = Shutdown::on_signals();
+    axum::serve(listener, http_api::router(processor.clone()))
+        .with_graceful_shutdown(async move { shutdown.wait().await })
+        .await
+        .map_err(|e| CliError::ServerUnavailable(e.to_string()))?;
+    write_metrics(&processor, &args.engine).await
+}
+
+// Runs until stdin ends or the shutdown, a broken stdout stops it as an output error
+async fn rpc(args: RpcArgs) -> Result<(), CliError> {
+    let processor = build_processor(&args.engine).await?;
+    let input = tokio::io::BufReader::new(tokio::io::stdin());
+    rpc::serve(
+        &processor,
+        input,
+        tokio::io::stdout(),
+        Shutdown::on_signals(),
+    )
+    .await
+    .map_err(output_error)?;
+    write_metrics(&processor, &args.engine).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        checkpoint::SavedCheckpoint,
+        cli::{Cli, OutputFormat},
+        compression::decompress,
+    };
+    use clap::Parser;
+    use rstest::rstest;
+    use rust_decimal::dec;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn input_file(content: &str) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        file
+    }
+
+    fn path(file: &NamedTempFile) -> String {
+        file.path().to_str().unwrap().to_string()
+    }
+
+    fn source(files: &[&NamedTempFile]) -> InputsSource {
+        InputsSource::new(files.iter().map(|file| path(file)).collect(), None)
+    }
+
+    const INPUT: &str = "type,client,tx,amount,timestamp
+deposit,1,1,10,100
+deposit,1,2,5,110
+withdrawal,1,3,20,120
+dispute,1,1,,130
+";
+
+    #[rstest]
+    #[case(StopAt::default(), 4, 1)]
+    #[case(StopAt { row: Some(2), timestamp: None }, 2, 0)]
+    #[case(StopAt { row: None, timestamp: Some(120) }, 3, 1)]
+    #[case(StopAt { row: Some(3), timestamp: Some(105) }, 1, 0)]
+    #[tokio::test]
+    async fn test_feed_stops_at(#[case] stop_at: StopAt, #[case] rows: u64, #[case] rejected: u64) {
+        let file = input_file(INPUT);
+        let processor = InMemoryTransactionProcessor::new();
+
+        let stats = feed(
+            &processor,
+            source(&[&file]),
+            FeedOptions {
+                stop_at,
+                ..FeedOptions::default()
+            },
+            None,
+            None,
+            Shutdown::never(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(stats.rows, rows);
+        assert_eq!(stats.rejected, rejected);
+        assert_eq!(stats.rows_by_type.values().sum::<u64>(), rows);
+        assert_eq!(processor.current_meta().sequence_number, rows);
+    }
+
+    #[tokio::test]
+    async fn test_feed_reads_inputs_in_order() {
+        let first = input_file("type,client,tx,amount\ndeposit,1,1,10\n");
+        let second = input_file("tx,type,amount,client\n2,withdrawal,4,1\n3,deposit,1,2\n");
+        let processor = InMemoryTransactionProcessor::new();
+
+        let stats = feed(
+            &processor,
+            source(&[&first, &second]),
+            FeedOptions::default(),
+            None,
+            None,
+            Shutdown::never(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(stats.rows, 3);
+        assert_eq!(stats.rejected, 0);
+        let accounts = processor.get_accounts_storage();
+        assert_eq!(accounts.get_balance(1), Some(dec!(6)));
+        assert_eq!(accounts.get_balance(2), Some(dec!(1)));
+    }
+
+    #[rstest]
+    #[case(
+        "type,client,tx,amount\ndeposit,1,1,10\nwithdrawal,1,2,50\ndeposit,1,3,1\n",
+        "withdrawal,1,2,50,,",
+        "Insufficient money",
+        2
+    )]
+    #[case(
+        "type,client,tx,amount\ndeposit,1,1,10\nwithdrawal,x,2,5\ndeposit,1,3,1\n",
+        "withdrawal,x,2,5",
+        "CSV deserialize error: record 2 (line 3, byte: 37): field 2: invalid digit found in string",
+        1
+    )]
+    #[tokio::test]
+    async fn test_feed_fail_fast(
+        #[case] content: &str,
+        #[case] record: &str,
+        #[case] error: &str,
+        #[case] processed_rows: u64,
+    ) {
+        let valid = input_file("type,client,tx,amount\ndeposit,2,10,1\n");
+        let file = input_file(content);
+        let processor = InMemoryTransactionProcessor::new();
+
+        let result = feed(
+            &processor,
+            source(&[&valid, &file]),
+            FeedOptions {
+                fail_fast: true,
+                ..FeedOptions::default()
+            },
+            None,
+            None,
+            Shutdown::never(),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            result,
+            CliError::RejectedRow {
+                origin: Some(RowOrigin {
+                    input: path(&file).into(),
+                    line: 3,
+                }),
+                record: record.to_string(),
+                error: error.to_string(),
+            }
+        );
+        assert_eq!(result.exit_code(), 5);
+        assert_eq!(processor.current_meta().sequence_number, processed_rows + 1);
+    }
+
+    #[tokio::test]
+    async fn test_process_jsonl_input_to_json() {
+        let csv = input_file("type,client,tx,amount\ndeposit,1,1,1.5\n");
+        let jsonl = NamedTempFile::with_suffix(".jsonl").unwrap();
+        std::fs::write(
+            jsonl.path(),
+            "{\"type\":\"deposit\",\"client\":1,\"tx\":2,\"amount\":0.0001}\n\
+             {\"type\":\"dispute\",\"client\":1,\"tx\":2}\n",
+        )
+        .unwrap();
+        let output = NamedTempFile::with_suffix(".json").unwrap();
+
+        let cli =
+            Cli::try_parse_from(["app", &path(&csv), &path(&jsonl), "-o", &path(&output)]).unwrap();
+        run(cli.into_command()).await.unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(output.path()).unwrap(),
+            "[{\"client\":1,\"available\":\"1.5000\",\"held\":\"0.0001\",\"total\":\"1.5001\",\"state\":\"active\"}]\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_process_compressed_input_and_output() {
+        let input = NamedTempFile::with_suffix(".csv.gz").unwrap();
+        let mut writer = csv_utils::open_output(Some(&path(&input))).await.unwrap();
+        writer.write_all(INPUT.as_bytes()).await.unwrap();
+        writer.shutdown().await.unwrap();
+        let output = NamedTempFile::with_suffix(".csv.zst").unwrap();
+
+        let cli = Cli::try_parse_from(["app", &path(&input), "-o", &path(&output)]).unwrap();
+        run(cli.into_command()).await.unwrap();
+
+        let file = tokio::fs::File::open(output.path()).await.unwrap();
+        let mut accounts = String::new();
+        decompress(Box::new(file), None)
+            .await
+            .unwrap()
+            .read_to_string(&mut accounts)
+            .await
+            .unwrap();
+        assert_eq!(
+            accounts,
+            "client,available,held,total,state\n1,5,10,15,active\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_process_writes_metrics() {
+        let input = input_file(INPUT);
+        let output = NamedTempFile::new().unwrap();
+        let metrics = NamedTempFile::with_suffix(".prom").unwrap();
+
+        let cli = Cli::try_parse_from([
+            "app",
+            &path(&input),
+            "-o",
+            &path(&output),
+            "--metrics",
+            &path(&metrics),
+        ])
+        .unwrap();
+        run(cli.into_command()).await.unwrap();
+
+        let text = std::fs::read_to_string(metrics.path()).unwrap();
+        for line in [
+            "transaction_rows_total{type=\"deposit\"} 2",
+            "transaction_rejections_total{error=\"INSUFFICIENT_MONEY\"} 1",
+            "transaction_processing_seconds_count 4",
+            "open_disputes 1",
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "missing `{line}` in\n{text}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process_with_history_memory_budget() {
+        let mut content = String::from("type,client,tx,amount\n");
+        for transaction_id in 1..=200 {
+            content.push_str(&format!(
+                "deposit,{},{transaction_id},1\n",
+                transaction_id % 3
+            ));
+        }
+        content.push_str("dispute,1,1,\nchargeback,1,1,\ndispute,2,2,\ndeposit,2,5,1\n");
+        let input = input_file(&content);
+        let run_with = |extra: &[&str]| {
+            let output = NamedTempFile::new().unwrap();
+            let audit = NamedTempFile::new().unwrap();
+            let mut args = vec!["app".to_string(), path(&input)];
+            args.extend(["-o", &path(&output), "--audit", &path(&audit)].map(String::from));
+            args.extend(extra.iter().map(|arg| arg.to_string()));
+            async move {
+                run(Cli::try_parse_from(args).unwrap().into_command())
+                    .await
+                    .unwrap();
+                [output, audit].map(|file| {
+                    let mut lines = std::fs::read_to_string(file.path())
+                        .unwrap()
+                        .lines()
+                        .map(String::from)
+                        .collect::<Vec<_>>();
+                    lines.sort();
+                    lines
+                })
+            }
+        };
+
+        let in_memory = run_with(&[]).await;
+        let tiered = run_with(&["--history-memory-budget", "1000"]).await;
+
+        assert_eq!(tiered, in_memory);
+        assert!(in_memory[0].contains(&"1,66,0,66,locked".to_string()));
+        assert_eq!(in_memory[1].len(), 4);
+    }
+
+    #[rstest]
+    #[case(&[], true, "7")]
+    #[case(&["--reorder-tolerance", "200"], false, "15")]
+    #[case(&["--reorder-tolerance", "200", "--reorder-buffer", "1"], true, "7")]
+    #[tokio::test]
+    async fn test_process_rejects_late_rows(
+        #[case] extra: &[&str],
+        #[case] is_rejected: bool,
+        #[case] balance: &str,
+    ) {
+        let input = input_file(
+            "type,client,tx,amount,timestamp\ndeposit,1,1,1,100\ndeposit,1,2,2,200\n\
+             deposit,1,3,4,300\ndeposit,1,4,8,150\n",
+        );
+        let output = NamedTempFile::new().unwrap();
+        let rejections = NamedTempFile::new().unwrap();
+        let mut args = vec!["app".to_string(), path(&input)];
+        args.extend(["-o", &path(&output), "--rejections", &path(&rejections)].map(String::from));
+        args.extend(extra.iter().map(|arg| arg.to_string()));
+
+        run(Cli::try_parse_from(args).unwrap().into_command())
+            .await
+            .unwrap();
+
+        let rejected = if is_rejected {
+            "type,client,tx,amount,timestamp,reason,error\n\
+             deposit,1,4,8,150,,Row is older than the reordering tolerance allows\n"
+        } else {
+            ""
+        };
+        assert_eq!(
+            std::fs::read_to_string(rejections.path()).unwrap(),
+            rejected
+        );
+        assert_eq!(
+            std::fs::read_to_string(output.path()).unwrap(),
+            format!("client,available,held,total,state\n1,{balance},0,{balance},active\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_process_with_dispute_window() {
+        let input = input_file(
+            "type,client,tx,amount,timestamp\ndeposit,1,1,1,100\ndeposit,1,2,1,150\n\
+             deposit,1,3,1,200\ndispute,1,1,,200\ndispute,1,2,,200\n",
+        );
+        let output = NamedTempFile::new().unwrap();
+        let rejections = NamedTempFile::new().unwrap();
+
+        let cli = Cli::try_parse_from([
+            "app",
+            &path(&input),
+            "-o",
+            &path(&output),
+            "--rejections",
+            &path(&rejections),
+            "--dispute-window",
+            "60s",
+        ])
+        .unwrap();
+        run(cli.into_command()).await.unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(output.path()).unwrap(),
+            "client,available,held,total,state\n1,2,1,3,active\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(rejections.path()).unwrap(),
+            "type,client,tx,amount,timestamp,reason,error\n\
+             dispute,1,1,,200,,Origin transaction is out of dispute window\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resumed_process_matches_uninterrupted() {
+        let first = input_file(
+            "type,client,tx,amount,timestamp\r\ndeposit,1,1,10,100\r\ndeposit,2,2,20,105\r\n\
+             deposit,1,3,5,103\r\nwithdrawal,1,4,2,108\r\ndispute,1,1,,112\r\n\
+             withdrawal,2,8,50,101\r\n",
+        );
+        let second = NamedTempFile::with_suffix(".csv.gz").unwrap();
+        let mut writer = csv_utils::open_output(Some(&path(&second))).await.unwrap();
+        writer
+            .write_all(
+                b"type,client,tx,amount,timestamp\ndeposit,2,5,1,115\nresolve,1,1,,118\n\
+                  dispute,2,2,,116\nbogus,2,7,1,117\nchargeback,2,2,,125\ndeposit,1,6,3,130\n",
+            )
+            .await
+            .unwrap();
+        writer.shutdown().await.unwrap();
+        let directory = tempfile::tempdir().unwrap();
+        let checkpoint = directory.path().join("checkpoint.json");
+        let checkpoint = checkpoint.to_str().unwrap();
+        let rejections = directory.path().join("rejections.csv");
+        let rejections = rejections.to_str().unwrap();
+        let run_with = |extra: &[&str]| {
+            let output = NamedTempFile::new().unwrap();
+            let audit = NamedTempFile::new().unwrap();
+            let mut args = vec!["app".to_string(), path(&first), path(&second)];
+            args.extend(["-o", &path(&output), "--audit", &path(&audit)].map(String::from));
+            args.extend(extra.iter().map(|arg| arg.to_string()));
+            async move {
+                run(Cli::try_parse_from(args).unwrap().into_command())
+                    .await
+                    .unwrap();
+                // accounts are written in no particular order
+                [output, audit].map(|file| {
+                    let mut lines = std::fs::read_to_string(file.path())
+                        .unwrap()
+                        .lines()
+                        .map(String::from)
+                        .collect::<Vec<_>>();
+                    lines.sort();
+                    lines
+                })
+            }
+        };
+
+        let uninterrupted = run_with(&["--rejections", rejections]).await;
+        let uninterrupted_rejections = std::fs::read_to_string(rejections).unwrap();
+        // the last checkpoint is left as if the run had stopped right after it, rejections
+        // written after it are written again by the resumed run
+        run_with(&[
+            "--checkpoint",
+            checkpoint,
+            "--checkpoint-interval",
+            "5",
+            "--rejections",
+            rejections,
+        ])
+        .await;
+        let position = SavedCheckpoint::load(checkpoint)
+            .await
+            .unwrap()
+            .unwrap()
+            .reader()
+            .position
+            .clone();
+        assert_eq!((position.input_index, position.line), (1, 5));
+        let resumed = run_with(&[
+            "--checkpoint",
+            checkpoint,
+            "--resume",
+            "--rejections",
+            rejections,
+        ])
+        .await;
+
+        assert_eq!(resumed, uninterrupted);
+        assert_eq!(
+            std::fs::read_to_string(rejections).unwrap(),
+            uninterrupted_rejections
+        );
+        assert_eq!(uninterrupted_rejections.lines().count(), 3);
+        assert!(uninterrupted[0].contains(&"2,1,0,1,locked".to_string()));
+        assert_eq!(uninterrupted[1].len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_feed_follows_last_input() {
+        let first = input_file("type,client,tx,amount\ndeposit,1,1,10\n");
+        let followed = input_file("type,client,tx,amount\ndeposit,2,2,1\n");
+        let processor = InMemoryTransactionProcessor::new();
+        let processor = &processor;
+        let processed = |rows| async move {
+            while processor.current_meta().sequence_number < rows {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        let appended = async {
+            processed(2).await;
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(followed.path())
+                .unwrap();
+            file.write_all(b"deposit,2,3,").unwrap();
+            file.write_all(b"4\n").unwrap();
+            processed(3).await;
+        };
+
+        tokio::select! {
+            _ = feed(
+                processor,
+                source(&[&first, &followed]).with_follow(Duration::from_millis(10)),
+                FeedOptions::default(),
+                None,
+                None,
+                Shutdown::never(),
+            ) => panic!("followed input ended"),
+            result = tokio::time::timeout(Duration::from_secs(5), appended) => result.unwrap(),
+        }
+
+        let accounts = processor.get_accounts_storage();
+        assert_eq!(accounts.get_balance(1), Some(dec!(10)));
+        assert_eq!(accounts.get_balance(2), Some(dec!(5)));
+    }
+
+    #[tokio::test]
+    async fn test_feed_shutdown_processes_read_rows() {
+        let first = input_file("type,client,tx,amount\ndeposit,1,1,10\n");
+        let followed = input_file("type,client,tx,amount\ndeposit,2,2,1\n");
+        let processor = InMemoryTransactionProcessor::new();
+        let (trigger, shutdown) = Shutdown::new();
+        let interrupt = async {
+            while processor.current_meta().sequence_number < 2 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            trigger.send_replace(true);
+        };
+
+        let (stats, ()) = tokio::join!(
+            feed(
+                &processor,
+                source(&[&first, &followed]).with_follow(Duration::from_millis(10)),
+                FeedOptions::default(),
+                None,
+                None,
+                shutdown,
+            ),
+            interrupt,
+        );
+
+        let stats = stats.unwrap();
+        assert!(stats.interrupted);
+        assert_eq!(stats.rows, 2);
+        let last_origin = RowOrigin {
+            input: path(&followed).into(),
+            line: 2,
+        };
+        assert_eq!(stats.last_origin, Some(last_origin.clone()));
+        assert_eq!(
+            check_complete(
+                &stats,
+                &OutputArgs {
+                    output: None,
+                    format: None,
+                }
+            )
+            .await,
+            Err(CliError::Interrupted {
+                rows: 2,
+                last_origin: Some(last_origin),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_run_marks_output_partial() {
+        let directory = tempfile::tempdir().unwrap();
+        let output_path = directory.path().join("accounts.csv");
+        let output = OutputArgs {
+            output: Some(output_path.to_str().unwrap().to_string()),
+            format: None,
+        };
+        let marker_path = directory.path().join("accounts.csv.partial");
+        let mut stats = ProcessingStats {
+            rows: 2,
+            last_origin: Some(RowOrigin {
+                input: "day1.csv".into(),
+                line: 3,
+            }),
+            interrupted: true,
+            ..ProcessingStats::default()
+        };
+
+        let result = check_complete(&stats, &output).await;
+
+        assert!(matches!(result, Err(CliError::Interrupted { rows: 2, .. })));
+        let marker: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&marker_path).unwrap()).unwrap();
+        assert_eq!(
+            marker,
+            serde_json::json!({"rows": 2, "last_processed": {"input": "day1.csv", "line": 3}})
+        );
+
+        stats.interrupted = false;
+        assert_eq!(check_complete(&stats, &output).await, Ok(()));
+        assert!(!marker_path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_feed_unreadable_input() {
+        let valid = input_file(INPUT);
+        let processor = InMemoryTransactionProcessor::new();
+
+        let error = feed(
+            &processor,
+            InputsSource::new(
+                vec![path(&valid), "/nonexistent/input.csv".to_string()],
+                None,
+            ),
+            FeedOptions::default(),
+            None,
+            None,
+            Shutdown::never(),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(error, CliError::InputUnreadable(_)));
+        assert_eq!(error.exit_code(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_validate_reports_invalid_rows() {
+        let first = input_file("type,client,tx,amount\ndeposit,1,1,10\nbogus,1,2,1\n");
+        let second = input_file("type,client,tx,amount\ndeposit,1,3,\n");
+        let output = NamedTempFile::new().unwrap();
+
+        let error = validate(ValidateArgs {
+            inputs: vec![path(&first), path(&second)],
+            input_format: None,
+            output: OutputArgs {
+                output: Some(path(&output)),
+                format: Some(OutputFormat::Csv),
+            },
+        })
+        .await
+        .unwrap_err();
+
+        assert_eq!(error, CliError::InvalidRows(2));
+        assert_eq!(error.exit_code(), 1);
+        let invalid_rows = std::fs::read_to_string(output.path()).unwrap();
+        assert_eq!(
+            invalid_rows,
+            format!(
+                "input,line,error\n{},3,Invalid transaction type in entry\n{},2,Missing amount in entry\n",
+                path(&first),
+                path(&second)
+            )
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use std::io;
+
+use async_compression::tokio::{
+    bufread::{GzipDecoder, ZstdDecoder},
+    write::{GzipEncoder, ZstdEncoder},
+};
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, BufReader},
+};
+
+pub type BoxedReader = Box<dyn AsyncRead + Unpin + Send>;
+pub type BoxedWriter = Box<dyn AsyncWrite + Unpin + Send>;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn for_path(path: &str) -> Self {
+        match path
+            .rsplit_once('.')
+            .map(|(_, extension)| extension.to_lowercase())
+        {
+            Some(extension) if extension == "gz" || extension == "gzip" => Compression::Gzip,
+            Some(extension) if extension == "zst" || extension == "zstd" => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    pub fn for_magic_bytes(bytes: &[u8]) -> Self {
+        if bytes.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if bytes.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    // `data.jsonl.gz` becomes `data.jsonl`, so the format is taken from the inner extension
+    pub fn strip_extension(path: &str) -> &str {
+        match Compression::for_path(path) {
+            Compression::None => path,
+            _ => path.rsplit_once('.').map_or(path, |(stem, _)| stem),
+        }
+    }
+}
+
+// Decompresses on the fly, the compression is taken from the path extension and
+// from the first bytes of the source when the extension doesn't name one, e.g. for stdin.
+// Concatenated gzip members and zstd frames are read as one stream
+pub async fn decompress(source: BoxedReader, path: Option<&str>) -> io::Result<BoxedReader> {
+    let mut reader = BufReader::new(source);
+    let compression = match path.map(Compression::for_path) {
+        Some(Compression::None) | None => Compression::for_magic_bytes(reader.fill_buf().await?),
+        Some(compression) => compression,
+    };
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => {
+            let mut decoder = GzipDecoder::new(reader);
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        }
+        Compression::Zstd => {
+            let mut decoder = ZstdDecoder::new(reader);
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        }
+    })
+}
+
+// Compression of a file by its extension, or by its first bytes when the extension doesn't name one.
+// The file is rewound afterwards
+pub async fn file_compression(file: &mut File, path: &str) -> io::Result<Compression> {
+    let compression = Compression::for_path(path);
+    if compression != Compression::None {
+        return Ok(compression);
+    }
+    let mut magic = [0; ZSTD_MAGIC.len()];
+    let read = file.read(&mut magic).await?;
+    file.rewind().await?;
+    Ok(Compression::for_magic_bytes(&magic[..read]))
+}
+
+// Compresses by the path extension, the writer has to be shut down to write the trailer
+pub fn compress(output: BoxedWriter, path: &str) -> BoxedWriter {
+    match Compression::for_path(path) {
+        Compression::None => output,
+        Compression::Gzip => Box::new(GzipEncoder::new(output)),
+        Compression::Zstd => Box::new(ZstdEncoder::new(output)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const CONTENT: &str = "type,client,tx,amount\ndeposit,1,1,1.5\n";
+
+    async fn compressed(path: &str) -> Vec<u8> {
+        let (writer, mut reader) = tokio::io::duplex(64);
+        let mut output = compress(Box::new(writer), path);
+        let write = tokio::spawn(async move {
+            output.write_all(CONTENT.as_bytes()).await.unwrap();
+            output.shutdown().await.unwrap();
+        });
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await.unwrap();
+        write.await.unwrap();
+        bytes
+    }
+
+    #[rstest]
+    #[case("out.csv", Compression::None)]
+    #[case("out.csv.gz", Compression::Gzip)]
+    #[case("out.csv.ZST", Compression::Zstd)]
+    #[tokio::test]
+    async fn test_round_trip(#[case] path: &str, #[case] compression: Compression) {
+        let bytes = compressed(path).await;
+        assert_eq!(Compression::for_magic_bytes(&bytes), compression);
+
+        for path in [Some(path), Some("-"), None] {
+            let mut content = String::new();
+            decompress(Box::new(std::io::Cursor::new(bytes.clone())), path)
+                .await
+                .unwrap()
+                .read_to_string(&mut content)
+                .await
+                .unwrap();
+            assert_eq!(content, CONTENT);
+        }
+    }
+
+    #[rstest]
+    #[case("data.jsonl.gz", "data.jsonl")]
+    #[case("data.csv.zstd", "data.csv")]
+    #[case("data.csv", "data.csv")]
+    fn test_strip_extension(#[case] path: &str, #[case] stripped: &str) {
+        assert_eq!(Compression::strip_extension(path), stripped);
+    }
+}
@@ -1,49 +1,687 @@
-use csv_async::Trim;
+use std::{
+    error::Error,
+    io::{Cursor, SeekFrom},
+    sync::Arc,
+    time::Duration,
+};
+
+use csv_async::{AsyncReader, AsyncSerializer, StringRecord, Trim};
 use rust_decimal::Decimal;
-use serde::Serialize;
-use tokio::sync::mpsc::Sender;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc::Sender,
+};
 use tokio_stream::StreamExt;
-use tracing::error;
+use tracing::{error, info, warn};
 
 use crate::{
-    storage::ClientId,
-    transactions_processor::{InMemoryTransactionProcessor, TransactionLogEntry},
+    cli::{InputFormat, OutputFormat},
+    compression::{BoxedReader, BoxedWriter, Compression, compress, decompress, file_compression},
+    dispute_reasons::{DisputeReport, reason_label},
+    errors::CliError,
+    follow::follow_file,
+    held_funds::{HeldTransaction, held_funds_by_client, held_transactions},
+    history::TransactionHistoryStorage,
+    json_utils::{JsonlRows, write_json, write_jsonl},
+    pipeline::{AccountSink, InputPosition, TransactionSource},
+    reorder::{ReorderBuffer, ReorderState},
+    shutdown::Shutdown,
+    statement::statement,
+    storage::{AccountStatus, ClientId, InMemoryAccountsStorage},
+    transactions::{SequenceNumber, Timestamp, Transaction, TransactionId, TransactionMeta},
+    transactions_processor::{
+        RowOrigin, TransactionInfoType, TransactionLogEntry, TransactionStatus,
+    },
 };
 
-#[derive(Serialize)]
+pub const STDIN_INPUT: &str = "-";
+const STDIN_NAME: &str = "<stdin>";
+
+#[derive(Serialize, Debug, PartialEq, Clone)]
 pub struct CsvAccountData {
+    #[serde(rename = "client")]
+    pub client_id: ClientId,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    pub state: AccountStatus,
+}
+
+#[derive(Serialize)]
+pub struct CsvRejectedEntry {
+    #[serde(rename = "type")]
+    transaction_type: String,
     #[serde(rename = "client")]
     client_id: ClientId,
+    #[serde(rename = "tx")]
+    transaction_id: TransactionId,
+    amount: Option<Decimal>,
+    timestamp: Option<Timestamp>,
+    reason: Option<String>,
+    error: String,
+}
+
+#[derive(Serialize)]
+pub struct CsvStatusChangeData {
+    #[serde(rename = "tx")]
+    transaction_id: TransactionId,
+    previous_status: TransactionStatus,
+    new_status: TransactionStatus,
+    sequence_number: SequenceNumber,
+    timestamp: Option<Timestamp>,
+}
+
+#[derive(Serialize)]
+pub struct CsvStatementData {
+    #[serde(rename = "client")]
+    client_id: ClientId,
+    #[serde(rename = "tx")]
+    transaction_id: TransactionId,
+    sequence_number: SequenceNumber,
+    timestamp: Option<Timestamp>,
+    #[serde(rename = "type")]
+    transaction_type: TransactionInfoType,
+    status: TransactionStatus,
+    amount: Decimal,
     available: Decimal,
     held: Decimal,
-    total: Decimal,
-    locked: bool,
 }
 
-pub async fn read_data(file_path: String, sender: Sender<TransactionLogEntry>) {
-    let mut file = tokio::fs::File::open(&file_path)
-        .await
-        .expect("Can't read file");
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CsvOpenDisputeData {
+    #[serde(rename = "client")]
+    client_id: ClientId,
+    #[serde(rename = "tx")]
+    transaction_id: TransactionId,
+    #[serde(rename = "type")]
+    transaction_type: TransactionInfoType,
+    status: TransactionStatus,
+    reason: String,
+    amount: Decimal,
+    disputed_sequence_number: SequenceNumber,
+    disputed_timestamp: Option<Timestamp>,
+    age_transactions: SequenceNumber,
+    age_seconds: Option<Timestamp>,
+}
+
+impl From<HeldTransaction> for CsvOpenDisputeData {
+    fn from(held_transaction: HeldTransaction) -> Self {
+        Self {
+            client_id: held_transaction.client_id,
+            transaction_id: held_transaction.transaction_id,
+            transaction_type: held_transaction.transaction_type,
+            status: held_transaction.status,
+            reason: reason_label(held_transaction.dispute_reason.as_ref()),
+            amount: held_transaction.amount,
+            disputed_sequence_number: held_transaction.disputed_at.sequence_number,
+            disputed_timestamp: held_transaction.disputed_at.timestamp,
+            age_transactions: held_transaction.age_transactions,
+            age_seconds: held_transaction.age_seconds,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct CsvHeldFundsData {
+    #[serde(rename = "client")]
+    client_id: ClientId,
+    open_disputes: u64,
+    held: Decimal,
+    oldest_age_transactions: SequenceNumber,
+}
+
+#[derive(Serialize)]
+pub struct CsvDisputeReasonData {
+    reason: String,
+    disputes: u64,
+    escalations: u64,
+    resolved: u64,
+    chargebacks: u64,
+    represented: u64,
+    disputed_amount: Decimal,
+}
+
+#[derive(Serialize)]
+pub struct CsvInvalidRow {
+    input: String,
+    line: Option<u64>,
+    error: String,
+}
+
+#[derive(Serialize)]
+pub struct CsvStatData {
+    metric: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct CsvOnboardingEntry {
+    #[serde(rename = "client")]
+    client_id: ClientId,
+}
+
+pub struct RejectionsWriter {
+    writer: AsyncSerializer<BoxedWriter>,
+    path: String,
+}
+
+impl RejectionsWriter {
+    pub async fn create(file_path: &str) -> Result<Self, Box<dyn Error>> {
+        let file = open_output(Some(file_path)).await?;
+        Ok(Self {
+            writer: csv_async::AsyncWriterBuilder::new().create_serializer(file),
+            path: file_path.to_string(),
+        })
+    }
+
+    // Continues a file written up to `offset` by an earlier run, what was written after it is cut off.
+    // The header is only written when the file is empty
+    pub async fn append(file_path: &str, offset: u64) -> Result<Self, Box<dyn Error>> {
+        if Compression::for_path(file_path) != Compression::None {
+            return Err(format!("{file_path}: compressed rejections can't be appended to").into());
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(file_path)
+            .await?;
+        file.set_len(offset).await?;
+        file.seek(SeekFrom::End(0)).await?;
+        Ok(Self {
+            writer: csv_async::AsyncWriterBuilder::new()
+                .has_headers(offset == 0)
+                .create_serializer(Box::new(file)),
+            path: file_path.to_string(),
+        })
+    }
+
+    // Flushes the rows written so far and returns the size of the file with them
+    pub async fn offset(&mut self) -> Result<u64, Box<dyn Error>> {
+        self.writer.flush().await?;
+        Ok(tokio::fs::metadata(&self.path).await?.len())
+    }
+
+    pub async fn write(&mut self, entry: &TransactionLogEntry, error: &dyn Error) {
+        let rejected_entry = CsvRejectedEntry {
+            transaction_type: entry.transaction_type.clone(),
+            client_id: entry.client_id,
+            transaction_id: entry.transaction_id,
+            amount: entry.amount,
+            timestamp: entry.timestamp,
+            reason: entry.reason.clone(),
+            error: error.to_string(),
+        };
+        if let Err(e) = self.writer.serialize(rejected_entry).await {
+            error!("Can't write rejected entry, got: {e}");
+        }
+    }
+
+    pub async fn finish(self) {
+        if let Err(e) = finish(self.writer).await {
+            error!("Can't flush rejections file, got: {e}");
+        }
+    }
+}
+
+pub async fn read_onboarding_list(file_path: &str) -> Result<Vec<ClientId>, Box<dyn Error>> {
+    let file = decompress(Box::new(File::open(file_path).await?), Some(file_path)).await?;
     let mut reader = csv_async::AsyncReaderBuilder::new()
         .trim(Trim::All)
-        .create_deserializer(&mut file);
-    let mut records = reader.deserialize::<TransactionLogEntry>();
-    while let Some(fetched_tx) = records.next().await {
-        match fetched_tx {
-            Ok(transaction_entry) => {
-                sender.send(transaction_entry).await.ok();
+        .create_deserializer(file);
+    let mut records = reader.deserialize::<CsvOnboardingEntry>();
+    let mut client_ids = Vec::new();
+    while let Some(record) = records.next().await {
+        client_ids.push(record?.client_id);
+    }
+    Ok(client_ids)
+}
+
+// Files ending in `.gz` or `.zst` are compressed while written
+pub async fn open_output(file_path: Option<&str>) -> Result<BoxedWriter, std::io::Error> {
+    match file_path {
+        Some(file_path) => Ok(compress(
+            Box::new(File::create(file_path).await?),
+            file_path,
+        )),
+        None => Ok(Box::new(tokio::io::stdout())),
+    }
+}
+
+// Deserializes one CSV row, a row that doesn't fit `TransactionLogEntry` is returned as `CliError::RejectedRow`
+fn parse_row(
+    fetched_record: Result<StringRecord, csv_async::Error>,
+    headers: &StringRecord,
+    input: &Arc<str>,
+    line_shift: u64,
+) -> Result<TransactionLogEntry, CliError> {
+    let origin = |line| RowOrigin {
+        input: input.clone(),
+        line: line + line_shift,
+    };
+    match fetched_record {
+        Ok(record) => {
+            let origin = record.position().map(|position| origin(position.line()));
+            match record.deserialize::<TransactionLogEntry>(Some(headers)) {
+                Ok(transaction_entry) => Ok(TransactionLogEntry {
+                    origin,
+                    ..transaction_entry
+                }),
+                Err(e) => Err(CliError::RejectedRow {
+                    origin,
+                    record: record.iter().collect::<Vec<_>>().join(","),
+                    error: e.to_string(),
+                }),
             }
-            Err(e) => {
-                error!("Can't deserialize data into TransactionLogEntry, got: {e:#?}");
-                continue;
+        }
+        Err(e) if e.is_io_error() => Err(CliError::InputUnreadable(format!("{input}: {e}"))),
+        Err(e) => Err(CliError::RejectedRow {
+            origin: e.position().map(|position| origin(position.line())),
+            record: String::new(),
+            error: e.to_string(),
+        }),
+    }
+}
+
+// Rows of one CSV input, the header row is read when the input is opened
+pub struct CsvRows {
+    name: Arc<str>,
+    reader: AsyncReader<Box<dyn AsyncRead + Unpin + Send>>,
+    headers: StringRecord,
+    header: u64,
+    // lines and bytes of the input skipped between the header row and what the reader reads next
+    line_shift: u64,
+    offset_shift: u64,
+}
+
+impl CsvRows {
+    async fn new(
+        name: Arc<str>,
+        source: Box<dyn AsyncRead + Unpin + Send>,
+    ) -> Result<Self, CliError> {
+        Self::starting_at(name, source, None).await
+    }
+
+    // A reopened input is read from its header row followed by the rest of the input after `start`
+    async fn starting_at(
+        name: Arc<str>,
+        source: Box<dyn AsyncRead + Unpin + Send>,
+        start: Option<&InputPosition>,
+    ) -> Result<Self, CliError> {
+        let mut reader = csv_async::AsyncReaderBuilder::new()
+            .trim(Trim::All)
+            .create_reader(source);
+        // every input has its own header row
+        let headers = reader
+            .headers()
+            .await
+            .map_err(|e| CliError::InputUnreadable(format!("{name}: {e}")))?
+            .clone();
+        let header_end = reader.position();
+        let header = header_end.byte();
+        let (line_shift, offset_shift) = start.map_or((0, 0), |start| {
+            (
+                start.line.saturating_sub(header_end.line() - 1),
+                start.offset.saturating_sub(header),
+            )
+        });
+        Ok(Self {
+            name,
+            reader,
+            headers,
+            header,
+            line_shift,
+            offset_shift,
+        })
+    }
+
+    pub fn position(&self) -> InputPosition {
+        let position = self.reader.position();
+        InputPosition {
+            header: self.header,
+            line: self.line_shift + position.line() - 1,
+            offset: self.offset_shift + position.byte(),
+            ..InputPosition::default()
+        }
+    }
+}
+
+impl TransactionSource for CsvRows {
+    async fn next_row(&mut self) -> Option<Result<TransactionLogEntry, CliError>> {
+        let mut record = StringRecord::new();
+        let fetched_record = match self.reader.read_record(&mut record).await {
+            Ok(false) => return None,
+            Ok(true) => Ok(record),
+            Err(e) => Err(e),
+        };
+        Some(parse_row(
+            fetched_record,
+            &self.headers,
+            &self.name,
+            self.line_shift,
+        ))
+    }
+}
+
+pub enum InputRows {
+    Csv(CsvRows),
+    Jsonl(JsonlRows),
+}
+
+impl InputRows {
+    pub fn position(&self) -> InputPosition {
+        match self {
+            InputRows::Csv(rows) => rows.position(),
+            InputRows::Jsonl(rows) => rows.position(),
+        }
+    }
+}
+
+impl TransactionSource for InputRows {
+    async fn next_row(&mut self) -> Option<Result<TransactionLogEntry, CliError>> {
+        match self {
+            InputRows::Csv(rows) => rows.next_row().await,
+            InputRows::Jsonl(rows) => rows.next_row().await,
+        }
+    }
+}
+
+// Without an explicit format it is taken from the input extension.
+// Compressed inputs are detected by extension or magic bytes and decompressed while read.
+// With `follow` a file is watched for appended rows after its end instead of ending the input
+pub async fn open_input(
+    input: &str,
+    input_format: Option<InputFormat>,
+    follow: Option<Duration>,
+) -> Result<InputRows, CliError> {
+    let input_format = input_format.unwrap_or_else(|| InputFormat::for_input(input));
+    let unreadable = |e: std::io::Error| CliError::InputUnreadable(format!("{input}: {e}"));
+    let (name, source): (Arc<str>, BoxedReader) = if input == STDIN_INPUT {
+        let source = decompress(Box::new(tokio::io::stdin()), None)
+            .await
+            .map_err(unreadable)?;
+        (STDIN_NAME.into(), source)
+    } else {
+        let file: BoxedReader = match follow {
+            Some(poll_interval) => {
+                follow_file(input, poll_interval, input_format == InputFormat::Csv)
+                    .await
+                    .map_err(unreadable)?
+            }
+            None => Box::new(File::open(input).await.map_err(unreadable)?),
+        };
+        let source = decompress(file, Some(input)).await.map_err(unreadable)?;
+        (input.into(), source)
+    };
+    match input_format {
+        InputFormat::Csv => Ok(InputRows::Csv(CsvRows::new(name, source).await?)),
+        InputFormat::Jsonl => Ok(InputRows::Jsonl(JsonlRows::new(name, source))),
+    }
+}
+
+// Opens `input` again to continue after `position`, a plain file is seeked to the position and any
+// other input is read up to it. Stdin has to be given the same rows again
+pub async fn reopen_input(
+    input: &str,
+    input_format: Option<InputFormat>,
+    position: &InputPosition,
+) -> Result<InputRows, CliError> {
+    if input != position.input {
+        return Err(CliError::InputUnreadable(format!(
+            "{input}: the checkpoint was taken while reading {}",
+            position.input
+        )));
+    }
+    let input_format = input_format.unwrap_or_else(|| InputFormat::for_input(input));
+    let unreadable = |e: std::io::Error| CliError::InputUnreadable(format!("{input}: {e}"));
+    let (name, source): (Arc<str>, BoxedReader) = if input == STDIN_INPUT {
+        let source = decompress(Box::new(tokio::io::stdin()), None)
+            .await
+            .map_err(unreadable)?;
+        let source = skip_to(source, position).await.map_err(unreadable)?;
+        (STDIN_NAME.into(), source)
+    } else {
+        let mut file = File::open(input).await.map_err(unreadable)?;
+        let source = if file_compression(&mut file, input)
+            .await
+            .map_err(unreadable)?
+            == Compression::None
+        {
+            seek_to(file, position).await.map_err(unreadable)?
+        } else {
+            let source = decompress(Box::new(file), Some(input))
+                .await
+                .map_err(unreadable)?;
+            skip_to(source, position).await.map_err(unreadable)?
+        };
+        (input.into(), source)
+    };
+    match input_format {
+        InputFormat::Csv => Ok(InputRows::Csv(
+            CsvRows::starting_at(name, source, Some(position)).await?,
+        )),
+        InputFormat::Jsonl => Ok(InputRows::Jsonl(JsonlRows::starting_at(
+            name,
+            source,
+            position.line,
+            position.offset,
+        ))),
+    }
+}
+
+fn input_too_short() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::UnexpectedEof,
+        "the input ends before the checkpoint position",
+    )
+}
+
+// The header row followed by the input after the position
+async fn seek_to(mut file: File, position: &InputPosition) -> std::io::Result<BoxedReader> {
+    if file.metadata().await?.len() < position.offset {
+        return Err(input_too_short());
+    }
+    let mut header = vec![0; position.header as usize];
+    file.read_exact(&mut header).await?;
+    file.seek(SeekFrom::Start(position.offset)).await?;
+    Ok(Box::new(Cursor::new(header).chain(file)))
+}
+
+async fn skip_to(
+    mut source: BoxedReader,
+    position: &InputPosition,
+) -> std::io::Result<BoxedReader> {
+    let mut header = vec![0; position.header as usize];
+    source.read_exact(&mut header).await?;
+    let skipped = position.offset.saturating_sub(position.header);
+    let read = tokio::io::copy(&mut (&mut source).take(skipped), &mut tokio::io::sink()).await?;
+    if read < skipped {
+        return Err(input_too_short());
+    }
+    Ok(Box::new(Cursor::new(header).chain(source)))
+}
+
+// why `read_data` stopped reading
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReadOutcome {
+    Finished,
+    // stopped by the shutdown, rows read so far are still sent
+    Interrupted,
+}
+
+// What the reader got to, rows read but not sent yet are in the reorder buffer state
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReaderState {
+    pub position: InputPosition,
+    pub reorder: ReorderState,
+}
+
+pub enum ReadEvent {
+    Row(TransactionLogEntry),
+    // behind rows already sent by more than the reordering tolerance, it isn't processed
+    Late(TransactionLogEntry),
+    // every row sent before it is part of the state, rows sent after it aren't
+    Checkpoint(ReaderState),
+}
+
+fn checkpoint<S: TransactionSource>(
+    source: &S,
+    reorder_buffer: &ReorderBuffer,
+) -> Option<ReadEvent> {
+    let state = ReaderState {
+        position: source.position()?,
+        reorder: reorder_buffer.state(),
+    };
+    Some(ReadEvent::Checkpoint(state))
+}
+
+// Sends the rows of `source` in timestamp order.
+// With `fail_fast` the first row that can't be deserialized stops reading and is returned as error.
+// With `checkpoint_interval` a checkpoint is sent every given rows read and when the shutdown stops
+// reading, before the reorder buffer is flushed
+pub async fn read_data<S: TransactionSource>(
+    mut source: S,
+    sender: Sender<ReadEvent>,
+    mut reorder_buffer: ReorderBuffer,
+    fail_fast: bool,
+    checkpoint_interval: Option<u64>,
+    mut shutdown: Shutdown,
+) -> Result<ReadOutcome, CliError> {
+    let mut outcome = ReadOutcome::Finished;
+    let mut rows_read = 0;
+    loop {
+        let row = tokio::select! {
+            biased;
+            _ = shutdown.wait() => {
+                info!("Stopped reading inputs");
+                outcome = ReadOutcome::Interrupted;
+                if checkpoint_interval.is_some()
+                    && let Some(event) = checkpoint(&source, &reorder_buffer)
+                {
+                    sender.send(event).await.ok();
+                }
+                break;
             }
+            row = source.next_row() => row,
+        };
+        let Some(row) = row else {
+            break;
+        };
+        match row {
+            Ok(transaction_entry) => match reorder_buffer.push(transaction_entry) {
+                Ok(ready_entries) => {
+                    for ready_entry in ready_entries {
+                        sender.send(ReadEvent::Row(ready_entry)).await.ok();
+                    }
+                }
+                Err(late_entry) => {
+                    sender.send(ReadEvent::Late(late_entry)).await.ok();
+                }
+            },
+            Err(rejected_row @ CliError::RejectedRow { .. }) if !fail_fast => {
+                error!("Can't deserialize data into TransactionLogEntry. {rejected_row}");
+            }
+            Err(e) => return Err(e),
+        }
+        rows_read += 1;
+        if checkpoint_interval.is_some_and(|interval| rows_read % interval == 0)
+            && let Some(event) = checkpoint(&source, &reorder_buffer)
+        {
+            sender.send(event).await.ok();
         }
     }
+    for ready_entry in reorder_buffer.flush() {
+        sender.send(ReadEvent::Row(ready_entry)).await.ok();
+    }
+    if reorder_buffer.late_records() > 0 {
+        warn!(
+            "{} rows arrived later than reordering tolerance and were rejected",
+            reorder_buffer.late_records()
+        );
+    }
+    Ok(outcome)
 }
 
-pub async fn output_data(transaction_processor: &InMemoryTransactionProcessor) {
-    let accounts_storage = transaction_processor.get_accounts_storage();
-    let account_logs = accounts_storage
+// Checks that every row can be parsed into a transaction, invalid rows are written to `output`.
+// Returns the number of invalid rows
+pub async fn validate_data<S: TransactionSource, W: AsyncWrite + Unpin>(
+    mut source: S,
+    output: W,
+    output_format: OutputFormat,
+) -> Result<usize, CliError> {
+    let mut row_count = 0;
+    let mut invalid_rows = Vec::new();
+    while let Some(row) = source.next_row().await {
+        row_count += 1;
+        let (origin, error) = match row {
+            Ok(transaction_entry) => match Transaction::from_entry(&transaction_entry, row_count) {
+                Ok(_) => continue,
+                Err(e) => (transaction_entry.origin, e.to_string()),
+            },
+            Err(CliError::RejectedRow { origin, error, .. }) => (origin, error),
+            Err(e) => return Err(e),
+        };
+        invalid_rows.push(CsvInvalidRow {
+            input: origin
+                .as_ref()
+                .map_or_else(String::new, |origin| origin.input.to_string()),
+            line: origin.map(|origin| origin.line),
+            error,
+        });
+    }
+    write_rows(output, output_format, &invalid_rows)
+        .await
+        .map_err(|e| CliError::OutputUnwritable(e.to_string()))?;
+    Ok(invalid_rows.len())
+}
+
+// Flushes the writer and shuts its output down, compressed outputs write their trailer on shutdown
+async fn finish<W: AsyncWrite + Unpin>(writer: AsyncSerializer<W>) -> Result<(), Box<dyn Error>> {
+    let mut output = writer.into_inner().await.map_err(|e| e.into_error())?;
+    output.shutdown().await?;
+    Ok(())
+}
+
+// Serializes `rows` as CSV with a header row, a JSON array or JSON Lines.
+// Decimals keep their exact representation as strings in JSON
+pub async fn write_rows<T: Serialize, W: AsyncWrite + Unpin>(
+    output: W,
+    format: OutputFormat,
+    rows: &[T],
+) -> Result<(), Box<dyn Error>> {
+    match format {
+        OutputFormat::Csv => {
+            let mut writer = csv_async::AsyncWriterBuilder::new().create_serializer(output);
+            for row in rows {
+                writer.serialize(row).await?;
+            }
+            finish(writer).await
+        }
+        OutputFormat::Json => write_json(output, rows).await,
+        OutputFormat::Jsonl => write_jsonl(output, rows).await,
+    }
+}
+
+pub fn account_summary(
+    accounts_storage: &InMemoryAccountsStorage,
+    client_id: ClientId,
+) -> Option<CsvAccountData> {
+    accounts_storage
+        .accounts
+        .read()
+        .unwrap()
+        .get(&client_id)
+        .map(|user_account| CsvAccountData {
+            client_id,
+            available: user_account.available_balance(),
+            held: user_account.held_balance(),
+            total: user_account.total_balance(),
+            state: user_account.status(),
+        })
+}
+
+pub fn account_summaries(accounts_storage: &InMemoryAccountsStorage) -> Vec<CsvAccountData> {
+    accounts_storage
         .accounts
         .read()
         .unwrap()
@@ -53,12 +691,133 @@ pub async fn output_data(transaction_processor: &InMemoryTransactionProcessor) {
             available: user_account.available_balance(),
             held: user_account.held_balance(),
             total: user_account.total_balance(),
-            locked: user_account.is_locked(),
+            state: user_account.status(),
         })
-        .collect::<Vec<CsvAccountData>>();
+        .collect()
+}
+
+pub async fn output_data<S: AccountSink>(
+    accounts_storage: &InMemoryAccountsStorage,
+    sink: &mut S,
+) -> Result<(), Box<dyn Error>> {
+    sink.write_accounts(&account_summaries(accounts_storage))
+        .await
+}
+
+pub async fn write_stats<W: AsyncWrite + Unpin>(
+    output: W,
+    format: OutputFormat,
+    stats: &[(String, String)],
+) -> Result<(), Box<dyn Error>> {
+    let rows = stats
+        .iter()
+        .map(|(metric, value)| CsvStatData {
+            metric: metric.clone(),
+            value: value.clone(),
+        })
+        .collect::<Vec<_>>();
+    write_rows(output, format, &rows).await
+}
+
+pub async fn write_dispute_report(
+    file_path: &str,
+    dispute_report: &DisputeReport,
+) -> Result<(), Box<dyn Error>> {
+    let file = open_output(Some(file_path)).await?;
+    let mut writer = csv_async::AsyncWriterBuilder::new().create_serializer(file);
+    for (reason, stats) in dispute_report.snapshot() {
+        writer
+            .serialize(CsvDisputeReasonData {
+                reason: reason_label(reason.as_ref()),
+                disputes: stats.disputes,
+                escalations: stats.escalations,
+                resolved: stats.resolved,
+                chargebacks: stats.chargebacks,
+                represented: stats.represented,
+                disputed_amount: stats.disputed_amount,
+            })
+            .await?;
+    }
+    finish(writer).await
+}
+
+pub async fn write_audit_trail<H: TransactionHistoryStorage>(
+    file_path: &str,
+    history: &H,
+) -> Result<(), Box<dyn Error>> {
+    let file = open_output(Some(file_path)).await?;
+    let mut writer = csv_async::AsyncWriterBuilder::new().create_serializer(file);
+    for status_change in history.status_changes(None)? {
+        writer
+            .serialize(CsvStatusChangeData {
+                transaction_id: status_change.transaction_id,
+                previous_status: status_change.previous_status,
+                new_status: status_change.new_status,
+                sequence_number: status_change.sequence_number,
+                timestamp: status_change.timestamp,
+            })
+            .await?;
+    }
+    finish(writer).await
+}
+
+pub async fn write_statements<H: TransactionHistoryStorage, W: AsyncWrite + Unpin>(
+    output: W,
+    format: OutputFormat,
+    history: &H,
+    client_ids: &[ClientId],
+) -> Result<(), Box<dyn Error>> {
+    let mut rows = Vec::new();
+    for client_id in client_ids {
+        for row in statement(history, *client_id)? {
+            rows.push(CsvStatementData {
+                client_id: row.client_id,
+                transaction_id: row.transaction_id,
+                sequence_number: row.sequence_number,
+                timestamp: row.timestamp,
+                transaction_type: row.transaction_type,
+                status: row.status,
+                amount: row.amount,
+                available: row.available,
+                held: row.held,
+            });
+        }
+    }
+    write_rows(output, format, &rows).await
+}
+
+pub async fn write_open_disputes<H: TransactionHistoryStorage>(
+    file_path: &str,
+    history: &H,
+    now: &TransactionMeta,
+) -> Result<(), Box<dyn Error>> {
+    let file = open_output(Some(file_path)).await?;
+    let mut writer = csv_async::AsyncWriterBuilder::new().create_serializer(file);
+    for held_transaction in held_transactions(history, now)? {
+        writer
+            .serialize(CsvOpenDisputeData::from(held_transaction))
+            .await?;
+    }
+    finish(writer).await
+}
 
-    let mut writer = csv_async::AsyncWriterBuilder::new().create_serializer(tokio::io::stdout());
-    for log in account_logs {
-        writer.serialize(log).await.ok();
+pub async fn write_held_funds<H: TransactionHistoryStorage>(
+    file_path: &str,
+    history: &H,
+    now: &TransactionMeta,
+) -> Result<(), Box<dyn Error>> {
+    let file = open_output(Some(file_path)).await?;
+    let mut writer = csv_async::AsyncWriterBuilder::new().create_serializer(file);
+    let held_transactions = held_transactions(history, now)?;
+    for (client_id, client) in held_funds_by_client(&held_transactions) {
+        writer
+            .serialize(CsvHeldFundsData {
+                client_id,
+                open_disputes: client.open_disputes,
+                held: client.held,
+                oldest_age_transactions: client.oldest_age_transactions,
+            })
+            .await?;
     }
+    finish(writer).await
 }
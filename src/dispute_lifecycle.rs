@@ -0,0 +1,179 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+
+use crate::{errors::TransactionHistoryError, transactions_processor::TransactionStatus};
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum HoldPolicy {
+    // disputed amount stays in held balance while the transaction is in this status
+    Hold,
+    // disputed amount goes back to the state it had before the dispute was opened
+    Release,
+}
+
+#[derive(Debug, Clone)]
+pub struct TransitionTable {
+    transitions: HashSet<(TransactionStatus, TransactionStatus)>,
+    hold_policies: HashMap<TransactionStatus, HoldPolicy>,
+}
+
+impl Default for TransitionTable {
+    fn default() -> Self {
+        use TransactionStatus::*;
+
+        let mut table = Self::empty()
+            .allow(WithoutDisputes, Disputed)
+            .allow(Chargebacked, Represented);
+        for stage in [Disputed, PreArbitration, Arbitration] {
+            table = table
+                .allow(stage, Resolved)
+                .allow(stage, Chargebacked)
+                .with_hold_policy(stage, HoldPolicy::Hold);
+        }
+        table
+            .allow(Disputed, PreArbitration)
+            .allow(PreArbitration, Arbitration)
+    }
+}
+
+impl TransitionTable {
+    pub fn empty() -> Self {
+        Self {
+            transitions: HashSet::new(),
+            hold_policies: HashMap::new(),
+        }
+    }
+
+    pub fn allow(mut self, from: TransactionStatus, to: TransactionStatus) -> Self {
+        self.transitions.insert((from, to));
+        self
+    }
+
+    pub fn forbid(mut self, from: TransactionStatus, to: TransactionStatus) -> Self {
+        self.transitions.remove(&(from, to));
+        self
+    }
+
+    pub fn with_hold_policy(mut self, status: TransactionStatus, hold_policy: HoldPolicy) -> Self {
+        self.hold_policies.insert(status, hold_policy);
+        self
+    }
+
+    pub fn is_transition_available(&self, from: TransactionStatus, to: TransactionStatus) -> bool {
+        self.transitions.contains(&(from, to))
+    }
+
+    pub fn make_transition(
+        &self,
+        from: TransactionStatus,
+        to: TransactionStatus,
+    ) -> Result<TransactionStatus, Box<dyn Error>> {
+        if self.is_transition_available(from, to) {
+            Ok(to)
+        } else {
+            Err(Box::new(TransactionHistoryError::InvalidStatusTransition))
+        }
+    }
+
+    pub fn holds_funds(&self, status: TransactionStatus) -> bool {
+        matches!(self.hold_policies.get(&status), Some(HoldPolicy::Hold))
+    }
+
+    // expired transactions can't be disputed anymore, everything else is kept
+    // while some resolution may still follow
+    pub fn is_droppable(&self, status: TransactionStatus) -> bool {
+        status == TransactionStatus::WithoutDisputes
+            || !self.transitions.iter().any(|(from, _)| *from == status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const ALL_STATUSES: [TransactionStatus; 7] = [
+        TransactionStatus::WithoutDisputes,
+        TransactionStatus::Disputed,
+        TransactionStatus::PreArbitration,
+        TransactionStatus::Arbitration,
+        TransactionStatus::Resolved,
+        TransactionStatus::Chargebacked,
+        TransactionStatus::Represented,
+    ];
+
+    #[rstest]
+    #[case(TransactionStatus::WithoutDisputes, &[TransactionStatus::Disputed])]
+    #[case(TransactionStatus::Disputed, &[TransactionStatus::PreArbitration, TransactionStatus::Resolved, TransactionStatus::Chargebacked])]
+    #[case(TransactionStatus::PreArbitration, &[TransactionStatus::Arbitration, TransactionStatus::Resolved, TransactionStatus::Chargebacked])]
+    #[case(TransactionStatus::Arbitration, &[TransactionStatus::Resolved, TransactionStatus::Chargebacked])]
+    #[case(TransactionStatus::Resolved, &[])]
+    #[case(TransactionStatus::Chargebacked, &[TransactionStatus::Represented])]
+    #[case(TransactionStatus::Represented, &[])]
+    fn test_default_transitions(
+        #[case] from: TransactionStatus,
+        #[case] available: &[TransactionStatus],
+    ) {
+        let table = TransitionTable::default();
+
+        for to in ALL_STATUSES {
+            let should_be_valid = available.contains(&to);
+            assert_eq!(table.is_transition_available(from, to), should_be_valid);
+
+            let result = table.make_transition(from, to);
+            if should_be_valid {
+                assert_eq!(result.unwrap(), to);
+            } else {
+                let error = result.unwrap_err();
+                let history_error = error.downcast_ref::<TransactionHistoryError>().unwrap();
+                assert_eq!(
+                    *history_error,
+                    TransactionHistoryError::InvalidStatusTransition
+                );
+            }
+        }
+    }
+
+    #[rstest]
+    #[case(TransactionStatus::WithoutDisputes, false, true)]
+    #[case(TransactionStatus::Disputed, true, false)]
+    #[case(TransactionStatus::PreArbitration, true, false)]
+    #[case(TransactionStatus::Arbitration, true, false)]
+    #[case(TransactionStatus::Resolved, false, true)]
+    #[case(TransactionStatus::Chargebacked, false, false)]
+    #[case(TransactionStatus::Represented, false, true)]
+    fn test_default_holds_and_droppable(
+        #[case] status: TransactionStatus,
+        #[case] holds_funds: bool,
+        #[case] is_droppable: bool,
+    ) {
+        let table = TransitionTable::default();
+
+        assert_eq!(table.holds_funds(status), holds_funds);
+        assert_eq!(table.is_droppable(status), is_droppable);
+    }
+
+    #[test]
+    fn test_extend_table() {
+        let table = TransitionTable::default()
+            .forbid(
+                TransactionStatus::Chargebacked,
+                TransactionStatus::Represented,
+            )
+            .allow(TransactionStatus::Resolved, TransactionStatus::Disputed)
+            .with_hold_policy(TransactionStatus::Arbitration, HoldPolicy::Release);
+
+        assert!(!table.is_transition_available(
+            TransactionStatus::Chargebacked,
+            TransactionStatus::Represented
+        ));
+        assert!(
+            table.is_transition_available(TransactionStatus::Resolved, TransactionStatus::Disputed)
+        );
+        assert!(!table.holds_funds(TransactionStatus::Arbitration));
+        assert!(table.is_droppable(TransactionStatus::Chargebacked));
+        assert!(!table.is_droppable(TransactionStatus::Resolved));
+    }
+}
@@ -0,0 +1,190 @@
+use std::{collections::BTreeMap, fmt, sync::RwLock};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::transactions_processor::{TransactionInfo, TransactionStatus};
+
+const FRAUD_VALUE: &str = "fraud";
+const NOT_RECEIVED_VALUE: &str = "not_received";
+const DUPLICATE_VALUE: &str = "duplicate";
+const NOT_AS_DESCRIBED_VALUE: &str = "not_as_described";
+const CANCELLED_VALUE: &str = "cancelled";
+const INCORRECT_AMOUNT_VALUE: &str = "incorrect_amount";
+const UNSPECIFIED_VALUE: &str = "unspecified";
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Serialize, Deserialize)]
+pub enum DisputeReason {
+    Fraud,
+    NotReceived,
+    Duplicate,
+    NotAsDescribed,
+    Cancelled,
+    IncorrectAmount,
+    // anything that doesn't match a known code is kept as written
+    Other(String),
+}
+
+impl From<&str> for DisputeReason {
+    fn from(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            FRAUD_VALUE => DisputeReason::Fraud,
+            NOT_RECEIVED_VALUE => DisputeReason::NotReceived,
+            DUPLICATE_VALUE => DisputeReason::Duplicate,
+            NOT_AS_DESCRIBED_VALUE => DisputeReason::NotAsDescribed,
+            CANCELLED_VALUE => DisputeReason::Cancelled,
+            INCORRECT_AMOUNT_VALUE => DisputeReason::IncorrectAmount,
+            _ => DisputeReason::Other(value.trim().to_string()),
+        }
+    }
+}
+
+impl fmt::Display for DisputeReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisputeReason::Fraud => write!(f, "{FRAUD_VALUE}"),
+            DisputeReason::NotReceived => write!(f, "{NOT_RECEIVED_VALUE}"),
+            DisputeReason::Duplicate => write!(f, "{DUPLICATE_VALUE}"),
+            DisputeReason::NotAsDescribed => write!(f, "{NOT_AS_DESCRIBED_VALUE}"),
+            DisputeReason::Cancelled => write!(f, "{CANCELLED_VALUE}"),
+            DisputeReason::IncorrectAmount => write!(f, "{INCORRECT_AMOUNT_VALUE}"),
+            DisputeReason::Other(text) => write!(f, "{text}"),
+        }
+    }
+}
+
+pub fn reason_label(reason: Option<&DisputeReason>) -> String {
+    reason.map_or(UNSPECIFIED_VALUE.to_string(), ToString::to_string)
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct DisputeReasonStats {
+    pub disputes: u64,
+    pub escalations: u64,
+    pub resolved: u64,
+    pub chargebacks: u64,
+    pub represented: u64,
+    pub disputed_amount: Decimal,
+}
+
+#[derive(Default)]
+pub struct DisputeReport {
+    stats: RwLock<BTreeMap<Option<DisputeReason>, DisputeReasonStats>>,
+}
+
+impl DisputeReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // called with the state of a transaction right after a dispute event moved it
+    pub fn record(&self, transaction_info: &TransactionInfo) {
+        let mut stats = self.stats.write().unwrap();
+        let reason_stats = stats
+            .entry(transaction_info.dispute_reason.clone())
+            .or_default();
+        match transaction_info.status {
+            TransactionStatus::Disputed => {
+                reason_stats.disputes += 1;
+                reason_stats.disputed_amount += transaction_info.amount;
+            }
+            TransactionStatus::PreArbitration | TransactionStatus::Arbitration => {
+                reason_stats.escalations += 1
+            }
+            TransactionStatus::Resolved => reason_stats.resolved += 1,
+            TransactionStatus::Chargebacked => reason_stats.chargebacks += 1,
+            TransactionStatus::Represented => reason_stats.represented += 1,
+            TransactionStatus::WithoutDisputes => {}
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<(Option<DisputeReason>, DisputeReasonStats)> {
+        let stats = self.stats.read().unwrap();
+        stats
+            .iter()
+            .map(|(reason, reason_stats)| (reason.clone(), reason_stats.clone()))
+            .collect()
+    }
+
+    pub fn restore(&self, snapshot: Vec<(Option<DisputeReason>, DisputeReasonStats)>) {
+        *self.stats.write().unwrap() = snapshot.into_iter().collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transactions_processor::TransactionInfoType;
+    use rstest::rstest;
+    use rust_decimal::dec;
+
+    fn disputed(status: TransactionStatus, reason: Option<DisputeReason>) -> TransactionInfo {
+        TransactionInfo {
+            client_id: 1,
+            transaction_id: 1,
+            transaction_type: TransactionInfoType::Deposit,
+            amount: dec!(10),
+            status,
+            sequence_number: 0,
+            timestamp: None,
+            dispute_reason: reason,
+        }
+    }
+
+    #[rstest]
+    #[case("fraud", DisputeReason::Fraud)]
+    #[case(" Not_Received ", DisputeReason::NotReceived)]
+    #[case("duplicate", DisputeReason::Duplicate)]
+    #[case("not_as_described", DisputeReason::NotAsDescribed)]
+    #[case("cancelled", DisputeReason::Cancelled)]
+    #[case("INCORRECT_AMOUNT", DisputeReason::IncorrectAmount)]
+    #[case(" item arrived broken", DisputeReason::Other("item arrived broken".to_string()))]
+    fn test_parse_reason(#[case] value: &str, #[case] expected: DisputeReason) {
+        let reason = DisputeReason::from(value);
+
+        assert_eq!(reason, expected);
+        assert_eq!(DisputeReason::from(reason.to_string().as_str()), reason);
+    }
+
+    #[test]
+    fn test_report_groups_events_by_reason() {
+        let report = DisputeReport::new();
+
+        for status in [
+            TransactionStatus::Disputed,
+            TransactionStatus::PreArbitration,
+            TransactionStatus::Chargebacked,
+            TransactionStatus::Represented,
+        ] {
+            report.record(&disputed(status, Some(DisputeReason::Fraud)));
+        }
+        report.record(&disputed(TransactionStatus::Disputed, None));
+        report.record(&disputed(TransactionStatus::Resolved, None));
+
+        assert_eq!(
+            report.snapshot(),
+            vec![
+                (
+                    None,
+                    DisputeReasonStats {
+                        disputes: 1,
+                        resolved: 1,
+                        disputed_amount: dec!(10),
+                        ..Default::default()
+                    }
+                ),
+                (
+                    Some(DisputeReason::Fraud),
+                    DisputeReasonStats {
+                        disputes: 1,
+                        escalations: 1,
+                        chargebacks: 1,
+                        represented: 1,
+                        disputed_amount: dec!(10),
+                        ..Default::default()
+                    }
+                ),
+            ]
+        );
+    }
+}
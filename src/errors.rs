@@ -1,11 +1,36 @@
 use std::fmt;
 
+use crate::transactions_processor::RowOrigin;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum AccountError {
     BalanceOverflow,
     InsufficientMoney,
     AccountLocked,
     AccountNotFound,
+    AccountFrozen,
+    AccountClosed,
+    AccountNotVerified,
+    AccountAlreadyExists,
+    InvalidStatusTransition,
+    NonZeroBalance,
+}
+
+impl AccountError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AccountError::BalanceOverflow => "BALANCE_OVERFLOW",
+            AccountError::InsufficientMoney => "INSUFFICIENT_MONEY",
+            AccountError::AccountLocked => "ACCOUNT_LOCKED",
+            AccountError::AccountNotFound => "ACCOUNT_NOT_FOUND",
+            AccountError::AccountFrozen => "ACCOUNT_FROZEN",
+            AccountError::AccountClosed => "ACCOUNT_CLOSED",
+            AccountError::AccountNotVerified => "ACCOUNT_NOT_VERIFIED",
+            AccountError::AccountAlreadyExists => "ACCOUNT_ALREADY_EXISTS",
+            AccountError::InvalidStatusTransition => "INVALID_STATUS_TRANSITION",
+            AccountError::NonZeroBalance => "NON_ZERO_BALANCE",
+        }
+    }
 }
 
 impl fmt::Display for AccountError {
@@ -15,6 +40,14 @@ impl fmt::Display for AccountError {
             AccountError::InsufficientMoney => write!(f, "Insufficient money"),
             AccountError::AccountLocked => write!(f, "Account is locked"),
             &AccountError::AccountNotFound => write!(f, "Account not found"),
+            AccountError::AccountFrozen => write!(f, "Account is frozen"),
+            AccountError::AccountClosed => write!(f, "Account is closed"),
+            AccountError::AccountNotVerified => write!(f, "Account is pending verification"),
+            AccountError::AccountAlreadyExists => write!(f, "Account already exists"),
+            AccountError::InvalidStatusTransition => {
+                write!(f, "Can't complete account status update")
+            }
+            AccountError::NonZeroBalance => write!(f, "Account balance is not zero"),
         }
     }
 }
@@ -28,6 +61,24 @@ pub enum TransactionError {
     TransactionNotDisputed,
     TransactionMultipleDispute,
     EmptyAmount,
+    DisputeWindowExpired,
+    TransactionNotChargebacked,
+    EscalationNotAllowed,
+}
+
+impl TransactionError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            TransactionError::NegativeAmount => "NEGATIVE_AMOUNT",
+            TransactionError::OriginTransactionNotFound => "ORIGIN_TRANSACTION_NOT_FOUND",
+            TransactionError::TransactionNotDisputed => "TRANSACTION_NOT_DISPUTED",
+            TransactionError::TransactionMultipleDispute => "TRANSACTION_MULTIPLE_DISPUTE",
+            TransactionError::EmptyAmount => "EMPTY_AMOUNT",
+            TransactionError::DisputeWindowExpired => "DISPUTE_WINDOW_EXPIRED",
+            TransactionError::TransactionNotChargebacked => "TRANSACTION_NOT_CHARGEBACKED",
+            TransactionError::EscalationNotAllowed => "ESCALATION_NOT_ALLOWED",
+        }
+    }
 }
 
 impl fmt::Display for TransactionError {
@@ -44,6 +95,15 @@ impl fmt::Display for TransactionError {
             TransactionError::EmptyAmount => {
                 write!(f, "Transaction goes with empty amount but it shouldn't")
             }
+            TransactionError::DisputeWindowExpired => {
+                write!(f, "Origin transaction is out of dispute window")
+            }
+            TransactionError::TransactionNotChargebacked => {
+                write!(f, "Transaction not charged back")
+            }
+            TransactionError::EscalationNotAllowed => {
+                write!(f, "Dispute can't be escalated to this stage")
+            }
         }
     }
 }
@@ -54,6 +114,17 @@ impl std::error::Error for TransactionError {}
 pub enum TransactionLogError {
     InvalidTransactionType,
     MissingAmount,
+    LateRow,
+}
+
+impl TransactionLogError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            TransactionLogError::InvalidTransactionType => "INVALID_TRANSACTION_TYPE",
+            TransactionLogError::MissingAmount => "MISSING_AMOUNT",
+            TransactionLogError::LateRow => "LATE_ROW",
+        }
+    }
 }
 
 impl fmt::Display for TransactionLogError {
@@ -63,6 +134,9 @@ impl fmt::Display for TransactionLogError {
                 write!(f, "Invalid transaction type in entry")
             }
             TransactionLogError::MissingAmount => write!(f, "Missing amount in entry"),
+            TransactionLogError::LateRow => {
+                write!(f, "Row is older than the reordering tolerance allows")
+            }
         }
     }
 }
@@ -74,6 +148,18 @@ pub enum TransactionHistoryError {
     TransactionAlreadyExists,
     UnknownTransaction,
     InvalidStatusTransition,
+    CorruptedRecord,
+}
+
+impl TransactionHistoryError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            TransactionHistoryError::TransactionAlreadyExists => "TRANSACTION_ALREADY_EXISTS",
+            TransactionHistoryError::UnknownTransaction => "UNKNOWN_TRANSACTION",
+            TransactionHistoryError::InvalidStatusTransition => "INVALID_STATUS_TRANSITION",
+            TransactionHistoryError::CorruptedRecord => "CORRUPTED_RECORD",
+        }
+    }
 }
 
 impl fmt::Display for TransactionHistoryError {
@@ -87,8 +173,110 @@ impl fmt::Display for TransactionHistoryError {
             TransactionHistoryError::InvalidStatusTransition => {
                 write!(f, "Can't complete transaction status update")
             }
+            TransactionHistoryError::CorruptedRecord => {
+                write!(f, "Stored transaction record is corrupted")
+            }
         }
     }
 }
 
 impl std::error::Error for TransactionHistoryError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CliError {
+    InputUnreadable(String),
+    OutputUnwritable(String),
+    InvalidRows(usize),
+    ServerUnavailable(String),
+    RejectedRow {
+        origin: Option<RowOrigin>,
+        record: String,
+        error: String,
+    },
+    // the inputs weren't read to the end, the written results only cover the processed rows
+    Interrupted {
+        rows: u64,
+        last_origin: Option<RowOrigin>,
+    },
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CliError::InvalidRows(_) => 1,
+            CliError::InputUnreadable(_) => 3,
+            CliError::OutputUnwritable(_) => 4,
+            CliError::RejectedRow { .. } => 5,
+            CliError::ServerUnavailable(_) => 6,
+            CliError::Interrupted { .. } => 7,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::InputUnreadable(reason) => write!(f, "Can't read input: {reason}"),
+            CliError::OutputUnwritable(reason) => write!(f, "Can't write output: {reason}"),
+            CliError::InvalidRows(count) => write!(f, "Input has {count} invalid rows"),
+            CliError::ServerUnavailable(reason) => write!(f, "Can't run server: {reason}"),
+            CliError::RejectedRow {
+                origin,
+                record,
+                error,
+            } => {
+                let origin = origin
+                    .as_ref()
+                    .map_or("unknown line".to_string(), ToString::to_string);
+                if record.is_empty() {
+                    write!(f, "Row rejected at {origin}: {error}")
+                } else {
+                    write!(f, "Row rejected at {origin}: `{record}`: {error}")
+                }
+            }
+            CliError::Interrupted { rows, last_origin } => {
+                let last_origin = last_origin
+                    .as_ref()
+                    .map_or("unknown line".to_string(), ToString::to_string);
+                write!(
+                    f,
+                    "Interrupted after {rows} rows, last processed row at {last_origin}, results are partial"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+// Stable code of the engine error for clients and metrics, e.g. `INSUFFICIENT_MONEY`
+pub fn error_code(error: &(dyn std::error::Error + 'static)) -> &'static str {
+    if let Some(e) = error.downcast_ref::<AccountError>() {
+        e.code()
+    } else if let Some(e) = error.downcast_ref::<TransactionError>() {
+        e.code()
+    } else if let Some(e) = error.downcast_ref::<TransactionLogError>() {
+        e.code()
+    } else if let Some(e) = error.downcast_ref::<TransactionHistoryError>() {
+        e.code()
+    } else {
+        "REJECTED"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(&AccountError::InsufficientMoney, "INSUFFICIENT_MONEY")]
+    #[case(&TransactionError::OriginTransactionNotFound, "ORIGIN_TRANSACTION_NOT_FOUND")]
+    #[case(&TransactionLogError::MissingAmount, "MISSING_AMOUNT")]
+    #[case(&TransactionLogError::LateRow, "LATE_ROW")]
+    #[case(&TransactionHistoryError::TransactionAlreadyExists, "TRANSACTION_ALREADY_EXISTS")]
+    #[case(&std::io::Error::other("boom"), "REJECTED")]
+    fn test_error_code(#[case] error: &(dyn std::error::Error + 'static), #[case] code: &str) {
+        assert_eq!(error_code(error), code);
+    }
+}
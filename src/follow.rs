@@ -0,0 +1,203 @@
+use std::{fs::Metadata, io, time::Duration};
+
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    time::{Instant, Interval, MissedTickBehavior},
+};
+use tracing::{error, info, warn};
+
+use crate::compression::BoxedReader;
+
+const PIPE_SIZE: usize = 64 * 1024;
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+// Reads a growing file like `tail -F`. Only complete lines are passed on, at the end of the file it is
+// polled for new data every `poll_interval`. A truncated or replaced file is reopened from the start,
+// its first line is skipped with `skip_header_on_reopen` so a rotated CSV doesn't repeat the header row.
+// The returned reader never ends on its own
+pub async fn follow_file(
+    path: &str,
+    poll_interval: Duration,
+    skip_header_on_reopen: bool,
+) -> io::Result<BoxedReader> {
+    let file = File::open(path).await?;
+    let (reader, writer) = tokio::io::simplex(PIPE_SIZE);
+    let follower = Follower {
+        path: path.to_string(),
+        poll_interval,
+        skip_header_on_reopen,
+    };
+    tokio::spawn(async move {
+        if let Err(e) = follower.run(file, writer).await {
+            error!("Stopped following {}: {e}", follower.path);
+        }
+    });
+    Ok(Box::new(reader))
+}
+
+struct Follower {
+    path: String,
+    poll_interval: Duration,
+    skip_header_on_reopen: bool,
+}
+
+impl Follower {
+    async fn run<W: AsyncWrite + Unpin>(&self, mut file: File, mut output: W) -> io::Result<()> {
+        let mut position = 0;
+        let mut pending = Vec::new();
+        let mut buffer = vec![0; READ_BUFFER_SIZE];
+        let mut skip_line = false;
+        loop {
+            let read = file.read(&mut buffer).await?;
+            if read == 0 {
+                if self.is_rotated(&file, position).await? {
+                    match File::open(&self.path).await {
+                        Ok(reopened) => {
+                            info!("{} was rotated, reading it from the start", self.path);
+                            if !pending.is_empty() {
+                                warn!("Dropped incomplete last line of rotated {}", self.path);
+                                pending.clear();
+                            }
+                            file = reopened;
+                            position = 0;
+                            skip_line = self.skip_header_on_reopen;
+                            continue;
+                        }
+                        // the new file isn't created yet
+                        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                tokio::time::sleep(self.poll_interval).await;
+                continue;
+            }
+            position += read as u64;
+            pending.extend_from_slice(&buffer[..read]);
+            let Some(last_line_end) = pending.iter().rposition(|byte| *byte == b'\n') else {
+                continue;
+            };
+            let mut lines = pending.drain(..=last_line_end).collect::<Vec<_>>();
+            if skip_line && let Some(first_line_end) = lines.iter().position(|byte| *byte == b'\n')
+            {
+                lines.drain(..=first_line_end);
+                skip_line = false;
+            }
+            if output.write_all(&lines).await.is_err() {
+                // nobody reads the rows anymore
+                return Ok(());
+            }
+        }
+    }
+
+    // the path points to another file or the file got shorter than what was already read,
+    // a file truncated and refilled beyond the read position between two polls isn't noticed
+    async fn is_rotated(&self, file: &File, position: u64) -> io::Result<bool> {
+        let current = file.metadata().await?;
+        let at_path = match tokio::fs::metadata(&self.path).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(true),
+            Err(e) => return Err(e),
+        };
+        Ok(!is_same_file(&current, &at_path) || at_path.len() < position)
+    }
+}
+
+#[cfg(unix)]
+fn is_same_file(first: &Metadata, second: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    (first.dev(), first.ino()) == (second.dev(), second.ino())
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_first: &Metadata, _second: &Metadata) -> bool {
+    true
+}
+
+// Fires on every snapshot interval and on SIGUSR1 where signals are available
+pub struct SnapshotTrigger {
+    interval: Option<Interval>,
+    #[cfg(unix)]
+    signal: tokio::signal::unix::Signal,
+}
+
+impl SnapshotTrigger {
+    // installs the signal handler right away, so SIGUSR1 doesn't terminate the process later on
+    pub fn new(interval: Option<Duration>) -> io::Result<Self> {
+        let interval = interval.map(|period| {
+            let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            interval
+        });
+        Ok(Self {
+            interval,
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1())?,
+        })
+    }
+
+    pub async fn wait(&mut self) {
+        let tick = async {
+            match &mut self.interval {
+                Some(interval) => {
+                    interval.tick().await;
+                }
+                None => std::future::pending().await,
+            }
+        };
+        #[cfg(unix)]
+        tokio::select! {
+            _ = tick => {}
+            _ = self.signal.recv() => {}
+        }
+        #[cfg(not(unix))]
+        tick.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    fn append(path: &std::path::Path, content: &str) {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_follow_partial_lines_and_rotation() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("gateway.csv");
+        append(&path, "type,client,tx,amount\ndeposit,1,1,1\ndeposit,1,2");
+        let reader = follow_file(path.to_str().unwrap(), POLL_INTERVAL, true)
+            .await
+            .unwrap();
+        let mut lines = BufReader::new(reader).lines();
+
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            "type,client,tx,amount"
+        );
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "deposit,1,1,1");
+        append(&path, ",2\n");
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "deposit,1,2,2");
+
+        std::fs::rename(&path, directory.path().join("gateway.csv.1")).unwrap();
+        append(&path, "type,client,tx,amount\ndeposit,2,3,3\n");
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "deposit,2,3,3");
+
+        // truncation is noticed while the file is shorter than what was read
+        std::fs::write(&path, "type,client,tx,amount\n").unwrap();
+        tokio::time::sleep(POLL_INTERVAL * 5).await;
+        append(&path, "deposit,3,4,4\n");
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "deposit,3,4,4");
+    }
+}
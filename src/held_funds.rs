@@ -0,0 +1,311 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dispute_reasons::DisputeReason,
+    history::TransactionHistoryStorage,
+    storage::ClientId,
+    transactions::{SequenceNumber, Timestamp, TransactionId, TransactionMeta},
+    transactions_processor::{TransactionInfoType, TransactionStatus},
+};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct HeldTransaction {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub transaction_type: TransactionInfoType,
+    pub status: TransactionStatus,
+    pub amount: Decimal,
+    pub dispute_reason: Option<DisputeReason>,
+    // row that opened the dispute, escalations keep the original one
+    pub disputed_at: TransactionMeta,
+    pub age_transactions: SequenceNumber,
+    pub age_seconds: Option<Timestamp>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ClientHeldFunds {
+    pub open_disputes: u64,
+    pub held: Decimal,
+    pub oldest_age_transactions: SequenceNumber,
+}
+
+// Totals over the transactions holding funds, kept up to date by the processor
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct HeldDisputes {
+    pub transactions: u64,
+    pub amount: Decimal,
+}
+
+impl HeldDisputes {
+    // applies a status change of a transaction with `amount` that held funds before and after
+    // as told by `held_before` and `held_after`
+    pub fn update(&mut self, amount: Decimal, held_before: bool, held_after: bool) {
+        match (held_before, held_after) {
+            (false, true) => {
+                self.transactions += 1;
+                self.amount += amount;
+            }
+            (true, false) => {
+                self.transactions = self.transactions.saturating_sub(1);
+                self.amount -= amount;
+            }
+            _ => {}
+        }
+    }
+}
+
+// Every transaction whose current status keeps its amount in held balance, oldest dispute first.
+// Ages are counted up to `now`, usually the position of the last processed row
+pub fn held_transactions<H: TransactionHistoryStorage>(
+    history: &H,
+    now: &TransactionMeta,
+) -> Result<Vec<HeldTransaction>, Box<dyn Error>> {
+    let transitions = history.transitions();
+    let mut holds = HashMap::new();
+    for status_change in history.status_changes(None)? {
+        if !transitions.holds_funds(status_change.new_status) {
+            holds.remove(&status_change.transaction_id);
+        } else if !transitions.holds_funds(status_change.previous_status) {
+            holds.insert(
+                status_change.transaction_id,
+                TransactionMeta {
+                    sequence_number: status_change.sequence_number,
+                    timestamp: status_change.timestamp,
+                },
+            );
+        }
+    }
+
+    let mut held_transactions = Vec::with_capacity(holds.len());
+    for (transaction_id, disputed_at) in holds {
+        let Some(transaction_info) = history.find_transaction(transaction_id) else {
+            continue;
+        };
+        held_transactions.push(HeldTransaction {
+            client_id: transaction_info.client_id,
+            transaction_id,
+            transaction_type: transaction_info.transaction_type,
+            status: transaction_info.status,
+            amount: transaction_info.amount,
+            dispute_reason: transaction_info.dispute_reason,
+            disputed_at,
+            age_transactions: now
+                .sequence_number
+                .saturating_sub(disputed_at.sequence_number),
+            age_seconds: now
+                .timestamp
+                .zip(disputed_at.timestamp)
+                .map(|(now, disputed_at)| now.saturating_sub(disputed_at)),
+        });
+    }
+    held_transactions.sort_by_key(|held_transaction| held_transaction.disputed_at.sequence_number);
+    Ok(held_transactions)
+}
+
+// per-client totals, `held` matches the held balance of the account
+pub fn held_funds_by_client(
+    held_transactions: &[HeldTransaction],
+) -> BTreeMap<ClientId, ClientHeldFunds> {
+    let mut clients = BTreeMap::<ClientId, ClientHeldFunds>::new();
+    for held_transaction in held_transactions {
+        let client = clients.entry(held_transaction.client_id).or_default();
+        client.open_disputes += 1;
+        client.held += held_transaction.amount;
+        client.oldest_age_transactions = client
+            .oldest_age_transactions
+            .max(held_transaction.age_transactions);
+    }
+    clients
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dispute_lifecycle::{HoldPolicy, TransitionTable},
+        history::InMemoryTransactionStorage,
+        storage::InMemoryAccountsStorage,
+        tiered_history::TieredTransactionStorage,
+        transactions_processor::{
+            InMemoryTransactionProcessor, TransactionLogEntry, TransactionProcessor,
+        },
+    };
+    use rstest::rstest;
+    use rust_decimal::dec;
+
+    fn process_all<H: TransactionHistoryStorage>(
+        processor: &InMemoryTransactionProcessor<H>,
+        rows: &[(&str, ClientId, TransactionId, Option<Decimal>, Timestamp)],
+    ) {
+        for (transaction_type, client_id, transaction_id, amount, timestamp) in rows {
+            let _result = processor.process(TransactionLogEntry {
+                transaction_type: transaction_type.to_string(),
+                client_id: *client_id,
+                transaction_id: *transaction_id,
+                amount: *amount,
+                timestamp: Some(*timestamp),
+                reason: None,
+                origin: None,
+            });
+        }
+    }
+
+    const ROWS: [(&str, ClientId, TransactionId, Option<Decimal>, Timestamp); 11] = [
+        ("deposit", 1, 1, Some(dec!(10)), 100),
+        ("deposit", 1, 2, Some(dec!(5)), 110),
+        ("deposit", 2, 3, Some(dec!(7)), 120),
+        ("withdrawal", 2, 4, Some(dec!(2)), 130),
+        ("dispute", 1, 1, None, 200),
+        ("dispute", 1, 2, None, 210),
+        ("dispute", 2, 4, None, 220),
+        ("resolve", 1, 2, None, 300),
+        ("prearbitration", 1, 1, None, 310),
+        ("deposit", 2, 5, Some(dec!(1)), 320),
+        ("dispute", 2, 5, None, 400),
+    ];
+
+    #[test]
+    fn test_held_transactions_ages() {
+        let processor = InMemoryTransactionProcessor::new();
+        process_all(&processor, &ROWS);
+
+        let held = held_transactions(processor.get_history(), &processor.current_meta()).unwrap();
+
+        assert_eq!(
+            held,
+            vec![
+                HeldTransaction {
+                    client_id: 1,
+                    transaction_id: 1,
+                    transaction_type: TransactionInfoType::Deposit,
+                    status: TransactionStatus::PreArbitration,
+                    amount: dec!(10),
+                    dispute_reason: None,
+                    disputed_at: TransactionMeta {
+                        sequence_number: 5,
+                        timestamp: Some(200),
+                    },
+                    age_transactions: 6,
+                    age_seconds: Some(200),
+                },
+                HeldTransaction {
+                    client_id: 2,
+                    transaction_id: 4,
+                    transaction_type: TransactionInfoType::Withdrawal,
+                    status: TransactionStatus::Disputed,
+                    amount: dec!(2),
+                    dispute_reason: None,
+                    disputed_at: TransactionMeta {
+                        sequence_number: 7,
+                        timestamp: Some(220),
+                    },
+                    age_transactions: 4,
+                    age_seconds: Some(180),
+                },
+                HeldTransaction {
+                    client_id: 2,
+                    transaction_id: 5,
+                    transaction_type: TransactionInfoType::Deposit,
+                    status: TransactionStatus::Disputed,
+                    amount: dec!(1),
+                    dispute_reason: None,
+                    disputed_at: TransactionMeta {
+                        sequence_number: 11,
+                        timestamp: Some(400),
+                    },
+                    age_transactions: 0,
+                    age_seconds: Some(0),
+                },
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case(HoldPolicy::Hold)]
+    #[case(HoldPolicy::Release)]
+    fn test_held_funds_reconcile_with_accounts(#[case] hold_policy: HoldPolicy) {
+        let transitions = TransitionTable::default()
+            .with_hold_policy(TransactionStatus::PreArbitration, hold_policy);
+        let processor = InMemoryTransactionProcessor::with_storages(
+            InMemoryAccountsStorage::new(),
+            TieredTransactionStorage::new(0)
+                .unwrap()
+                .with_transitions(transitions),
+        );
+        process_all(&processor, &ROWS);
+
+        let held = held_transactions(processor.get_history(), &processor.current_meta()).unwrap();
+        let clients = held_funds_by_client(&held);
+
+        let accounts = processor.get_accounts_storage().accounts.read().unwrap();
+        for (client_id, account) in accounts.iter() {
+            let client_held = clients
+                .get(client_id)
+                .map_or(Decimal::ZERO, |client| client.held);
+            assert_eq!(client_held, account.held_balance());
+        }
+        assert!(
+            clients
+                .keys()
+                .all(|client_id| accounts.contains_key(client_id))
+        );
+        assert_eq!(
+            processor.held_disputes(),
+            HeldDisputes {
+                transactions: held.len() as u64,
+                amount: held
+                    .iter()
+                    .map(|held_transaction| held_transaction.amount)
+                    .sum(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_held_funds_by_client() {
+        let processor = InMemoryTransactionProcessor::new();
+        process_all(&processor, &ROWS);
+        process_all(&processor, &[("deposit", 1, 6, Some(dec!(1)), 500)]);
+
+        let held = held_transactions(processor.get_history(), &processor.current_meta()).unwrap();
+
+        assert_eq!(
+            held_funds_by_client(&held),
+            BTreeMap::from([
+                (
+                    1,
+                    ClientHeldFunds {
+                        open_disputes: 1,
+                        held: dec!(10),
+                        oldest_age_transactions: 7,
+                    }
+                ),
+                (
+                    2,
+                    ClientHeldFunds {
+                        open_disputes: 2,
+                        held: dec!(3),
+                        oldest_age_transactions: 5,
+                    }
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_no_held_transactions() {
+        let history = InMemoryTransactionStorage::new();
+
+        assert!(
+            held_transactions(&history, &TransactionMeta::default())
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -1,29 +1,209 @@
 use std::{
-    collections::{HashMap, hash_map::Entry},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque, hash_map::Entry},
     error::Error,
+    str::FromStr,
     sync::RwLock,
 };
 
+use enum_dispatch::enum_dispatch;
+use serde::{Deserialize, Serialize};
 use tracing::warn;
 
 use crate::{
+    dispute_lifecycle::TransitionTable,
+    dispute_reasons::DisputeReason,
     errors::TransactionHistoryError,
-    transactions::TransactionId,
+    storage::ClientId,
+    tiered_history::TieredTransactionStorage,
+    transactions::{SequenceNumber, Timestamp, TransactionId, TransactionMeta},
     transactions_processor::{TransactionInfo, TransactionStatus},
 };
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DisputeWindow {
+    Transactions(u64),
+    Seconds(u64),
+}
+
+impl DisputeWindow {
+    pub fn is_open(&self, transaction_info: &TransactionInfo, now: &TransactionMeta) -> bool {
+        self.is_open_since(
+            transaction_info.sequence_number,
+            transaction_info.timestamp,
+            now,
+        )
+    }
+
+    pub fn is_open_since(
+        &self,
+        sequence_number: SequenceNumber,
+        timestamp: Option<Timestamp>,
+        now: &TransactionMeta,
+    ) -> bool {
+        match self {
+            DisputeWindow::Transactions(limit) => {
+                now.sequence_number.saturating_sub(sequence_number) <= *limit
+            }
+            DisputeWindow::Seconds(limit) => match (timestamp, now.timestamp) {
+                (Some(timestamp), Some(now)) => now.saturating_sub(timestamp) <= *limit,
+                // elapsed time is unknown without timestamps on both rows
+                _ => true,
+            },
+        }
+    }
+}
+
+// `<N>` rows or `<N>s` seconds, as given on the command line
+impl FromStr for DisputeWindow {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (limit, window): (&str, fn(u64) -> Self) = match value.strip_suffix('s') {
+            Some(seconds) => (seconds, DisputeWindow::Seconds),
+            None => (value, DisputeWindow::Transactions),
+        };
+        limit
+            .parse()
+            .map(window)
+            .map_err(|_| format!("expected <ROWS> or <SECONDS>s, got `{value}`"))
+    }
+}
+
+pub type ExpiryQueue = VecDeque<(SequenceNumber, Option<Timestamp>, TransactionId)>;
+
+// Queues a transaction again after it was passed over while it could still change. The queue is
+// kept in sequence number order, the entry mostly goes near the front where inserting is cheap
+pub fn requeue_expiry(
+    expiry_queue: &mut ExpiryQueue,
+    sequence_number: SequenceNumber,
+    timestamp: Option<Timestamp>,
+    transaction_id: TransactionId,
+) {
+    let position = expiry_queue.partition_point(|(queued, ..)| *queued <= sequence_number);
+    expiry_queue.insert(position, (sequence_number, timestamp, transaction_id));
+}
+
+// Ids of the transactions dropped after leaving the dispute window, kept as ranges of consecutive
+// ids since ids mostly grow by one
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ExpiredIds {
+    // first id of each range to its last id
+    ranges: BTreeMap<TransactionId, TransactionId>,
+}
+
+impl ExpiredIds {
+    pub fn insert(&mut self, transaction_id: TransactionId) {
+        let mut start = transaction_id;
+        let mut end = transaction_id;
+        if let Some((&previous_start, &previous_end)) =
+            self.ranges.range(..=transaction_id).next_back()
+        {
+            if previous_end >= transaction_id {
+                return;
+            }
+            if previous_end + 1 == transaction_id {
+                start = previous_start;
+            }
+        }
+        if let Some(next_start) = transaction_id.checked_add(1)
+            && let Some(next_end) = self.ranges.remove(&next_start)
+        {
+            end = next_end;
+        }
+        self.ranges.insert(start, end);
+    }
+
+    pub fn contains(&self, transaction_id: TransactionId) -> bool {
+        self.ranges
+            .range(..=transaction_id)
+            .next_back()
+            .is_some_and(|(_, &end)| end >= transaction_id)
+    }
+
+    pub fn ranges(&self) -> usize {
+        self.ranges.len()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub transaction_id: TransactionId,
+    pub previous_status: TransactionStatus,
+    pub new_status: TransactionStatus,
+    // sequence number and timestamp of the row that caused the change
+    pub sequence_number: SequenceNumber,
+    pub timestamp: Option<Timestamp>,
+}
+
+// Everything kept by `InMemoryTransactionStorage` besides its configuration
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct HistorySnapshot {
+    // ordered by sequence number, so the per-client order can be rebuilt
+    pub transactions: Vec<TransactionInfo>,
+    pub status_changes: Vec<StatusChange>,
+    pub expiry_queue: Vec<(SequenceNumber, Option<Timestamp>, TransactionId)>,
+    #[serde(default)]
+    pub expired: ExpiredIds,
+}
+
+#[enum_dispatch]
 pub trait TransactionHistoryStorage {
     fn add_transaction(&self, transaction_info: TransactionInfo) -> Result<(), Box<dyn Error>>;
     fn find_transaction(&self, transaction_id: TransactionId) -> Option<TransactionInfo>;
+    // transactions of one client, oldest first
+    fn client_transactions(
+        &self,
+        client_id: ClientId,
+    ) -> Result<Vec<TransactionInfo>, Box<dyn Error>>;
     fn update_transaction_status(
         &self,
         transaction_id: TransactionId,
         new_status: TransactionStatus,
+        cause: &TransactionMeta,
     ) -> Result<(), Box<dyn Error>>;
+    // status changes of one transaction, or of all transactions when no id is given, oldest first
+    fn status_changes(
+        &self,
+        transaction_id: Option<TransactionId>,
+    ) -> Result<Vec<StatusChange>, Box<dyn Error>>;
+    fn set_dispute_reason(
+        &self,
+        transaction_id: TransactionId,
+        dispute_reason: Option<DisputeReason>,
+    ) -> Result<(), Box<dyn Error>>;
+    fn dispute_window(&self) -> Option<DisputeWindow>;
+    fn transitions(&self) -> &TransitionTable;
+    // Drops the records that left the dispute window and can't change anymore, their ids are
+    // remembered so they can't be reused and disputes of them are told apart from unknown ids
+    fn drop_expired(&self, now: &TransactionMeta) -> Result<usize, Box<dyn Error>>;
+    fn is_expired(&self, transaction_id: TransactionId) -> bool;
+}
+
+// The history picked on the command line, the tiered one keeps memory use under a budget
+#[enum_dispatch(TransactionHistoryStorage)]
+pub enum HistoryStorage {
+    InMemoryTransactionStorage,
+    TieredTransactionStorage,
+}
+
+impl HistoryStorage {
+    // only the in-memory history can be captured by a checkpoint
+    pub fn in_memory(&self) -> Option<&InMemoryTransactionStorage> {
+        match self {
+            HistoryStorage::InMemoryTransactionStorage(history) => Some(history),
+            HistoryStorage::TieredTransactionStorage(_) => None,
+        }
+    }
 }
 
 pub struct InMemoryTransactionStorage {
     storage: RwLock<HashMap<TransactionId, TransactionInfo>>,
+    dispute_window: Option<DisputeWindow>,
+    transitions: TransitionTable,
+    expiry_queue: RwLock<ExpiryQueue>,
+    audit_trail: RwLock<HashMap<TransactionId, Vec<StatusChange>>>,
+    client_index: RwLock<HashMap<ClientId, Vec<TransactionId>>>,
+    expired: RwLock<ExpiredIds>,
 }
 
 impl Default for InMemoryTransactionStorage {
@@ -36,20 +216,111 @@ impl InMemoryTransactionStorage {
     pub fn new() -> Self {
         Self {
             storage: RwLock::new(HashMap::new()),
+            dispute_window: None,
+            transitions: TransitionTable::default(),
+            expiry_queue: RwLock::new(VecDeque::new()),
+            audit_trail: RwLock::new(HashMap::new()),
+            client_index: RwLock::new(HashMap::new()),
+            expired: RwLock::new(ExpiredIds::default()),
+        }
+    }
+
+    pub fn with_dispute_window(mut self, dispute_window: DisputeWindow) -> Self {
+        self.dispute_window = Some(dispute_window);
+        self
+    }
+
+    pub fn with_transitions(mut self, transitions: TransitionTable) -> Self {
+        self.transitions = transitions;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.storage.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn snapshot(&self) -> HistorySnapshot {
+        let storage = self.storage.read().unwrap();
+        let mut transactions = storage.values().cloned().collect::<Vec<_>>();
+        transactions.sort_by_key(|transaction_info| transaction_info.sequence_number);
+        let mut status_changes = self
+            .audit_trail
+            .read()
+            .unwrap()
+            .values()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        // the sort is stable, changes of one transaction stay in the order they were made
+        status_changes.sort_by_key(|status_change| {
+            (status_change.sequence_number, status_change.transaction_id)
+        });
+        let expiry_queue = self.expiry_queue.read().unwrap().iter().copied().collect();
+        HistorySnapshot {
+            transactions,
+            status_changes,
+            expiry_queue,
+            expired: self.expired.read().unwrap().clone(),
         }
     }
+
+    // replaces the stored transactions, the dispute window and transitions are kept
+    pub fn restore(&self, snapshot: HistorySnapshot) {
+        let mut storage = self.storage.write().unwrap();
+        let mut client_index = self.client_index.write().unwrap();
+        let mut audit_trail = self.audit_trail.write().unwrap();
+        storage.clear();
+        client_index.clear();
+        audit_trail.clear();
+        for transaction_info in snapshot.transactions {
+            client_index
+                .entry(transaction_info.client_id)
+                .or_default()
+                .push(transaction_info.transaction_id);
+            storage.insert(transaction_info.transaction_id, transaction_info);
+        }
+        for status_change in snapshot.status_changes {
+            audit_trail
+                .entry(status_change.transaction_id)
+                .or_default()
+                .push(status_change);
+        }
+        *self.expiry_queue.write().unwrap() = snapshot.expiry_queue.into();
+        *self.expired.write().unwrap() = snapshot.expired;
+    }
 }
 
 impl TransactionHistoryStorage for InMemoryTransactionStorage {
     fn add_transaction(&self, transaction_info: TransactionInfo) -> Result<(), Box<dyn Error>> {
         let mut storage = self.storage.write().unwrap();
-        match storage.entry(transaction_info.transaction_id) {
+        let sequence_number = transaction_info.sequence_number;
+        let timestamp = transaction_info.timestamp;
+        let transaction_id = transaction_info.transaction_id;
+        let client_id = transaction_info.client_id;
+        if self.is_expired(transaction_id) {
+            warn!("Attempt to add transaction, that was dropped from history storage");
+            return Err(Box::new(TransactionHistoryError::TransactionAlreadyExists));
+        }
+        match storage.entry(transaction_id) {
             Entry::Vacant(entry) => entry.insert(transaction_info),
             Entry::Occupied(_) => {
                 warn!("Attempt to add transaction, that already exists in history storage");
                 return Err(Box::new(TransactionHistoryError::TransactionAlreadyExists));
             }
         };
+        if self.dispute_window.is_some() {
+            let mut expiry_queue = self.expiry_queue.write().unwrap();
+            expiry_queue.push_back((sequence_number, timestamp, transaction_id));
+        }
+        let mut client_index = self.client_index.write().unwrap();
+        client_index
+            .entry(client_id)
+            .or_default()
+            .push(transaction_id);
         Ok(())
     }
 
@@ -58,32 +329,173 @@ impl TransactionHistoryStorage for InMemoryTransactionStorage {
         storage.get(&transaction_id).cloned()
     }
 
+    fn client_transactions(
+        &self,
+        client_id: ClientId,
+    ) -> Result<Vec<TransactionInfo>, Box<dyn Error>> {
+        let storage = self.storage.read().unwrap();
+        let client_index = self.client_index.read().unwrap();
+        let mut transactions = client_index
+            .get(&client_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|transaction_id| storage.get(transaction_id).cloned())
+            .collect::<Vec<_>>();
+        transactions.sort_by_key(|transaction_info| transaction_info.sequence_number);
+        Ok(transactions)
+    }
+
     fn update_transaction_status(
         &self,
         transaction_id: TransactionId,
         new_status: TransactionStatus,
+        cause: &TransactionMeta,
     ) -> Result<(), Box<dyn Error>> {
         let mut storage = self.storage.write().unwrap();
-        match storage.entry(transaction_id) {
+        let previous_status = match storage.entry(transaction_id) {
             Entry::Vacant(_) => {
                 warn!("Attempt to update unknown transaction");
                 return Err(Box::new(TransactionHistoryError::UnknownTransaction));
             }
             Entry::Occupied(entry) => {
-                let current_status = entry.get().status;
-                entry.into_mut().status = current_status.make_transition(new_status)?;
+                let transaction_info = entry.into_mut();
+                let current_status = transaction_info.status;
+                transaction_info.status = self
+                    .transitions
+                    .make_transition(current_status, new_status)?;
+                // a transaction kept past the window while its dispute was open is dropped once
+                // it's closed
+                if self.dispute_window.is_some()
+                    && !self.transitions.is_droppable(current_status)
+                    && self.transitions.is_droppable(transaction_info.status)
+                {
+                    requeue_expiry(
+                        &mut self.expiry_queue.write().unwrap(),
+                        transaction_info.sequence_number,
+                        transaction_info.timestamp,
+                        transaction_id,
+                    );
+                }
+                current_status
             }
         };
+        let mut audit_trail = self.audit_trail.write().unwrap();
+        audit_trail
+            .entry(transaction_id)
+            .or_default()
+            .push(StatusChange {
+                transaction_id,
+                previous_status,
+                new_status,
+                sequence_number: cause.sequence_number,
+                timestamp: cause.timestamp,
+            });
         Ok(())
     }
+
+    fn status_changes(
+        &self,
+        transaction_id: Option<TransactionId>,
+    ) -> Result<Vec<StatusChange>, Box<dyn Error>> {
+        let audit_trail = self.audit_trail.read().unwrap();
+        let mut status_changes = match transaction_id {
+            Some(transaction_id) => audit_trail
+                .get(&transaction_id)
+                .cloned()
+                .unwrap_or_default(),
+            None => audit_trail.values().flatten().copied().collect(),
+        };
+        status_changes.sort_by_key(|status_change| status_change.sequence_number);
+        Ok(status_changes)
+    }
+
+    fn set_dispute_reason(
+        &self,
+        transaction_id: TransactionId,
+        dispute_reason: Option<DisputeReason>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut storage = self.storage.write().unwrap();
+        match storage.get_mut(&transaction_id) {
+            Some(transaction_info) => transaction_info.dispute_reason = dispute_reason,
+            None => {
+                warn!("Attempt to set dispute reason of unknown transaction");
+                return Err(Box::new(TransactionHistoryError::UnknownTransaction));
+            }
+        };
+        Ok(())
+    }
+
+    fn dispute_window(&self) -> Option<DisputeWindow> {
+        self.dispute_window
+    }
+
+    fn transitions(&self) -> &TransitionTable {
+        &self.transitions
+    }
+
+    fn drop_expired(&self, now: &TransactionMeta) -> Result<usize, Box<dyn Error>> {
+        let Some(dispute_window) = self.dispute_window else {
+            return Ok(0);
+        };
+        let mut storage = self.storage.write().unwrap();
+        let mut expiry_queue = self.expiry_queue.write().unwrap();
+        let mut client_index = self.client_index.write().unwrap();
+        let mut expired = self.expired.write().unwrap();
+        let mut dropped = HashMap::<ClientId, HashSet<TransactionId>>::new();
+        while let Some((sequence_number, timestamp, transaction_id)) = expiry_queue.front().copied()
+        {
+            if dispute_window.is_open_since(sequence_number, timestamp, now) {
+                break;
+            }
+            expiry_queue.pop_front();
+            // transactions that can still change are queued again when their status allows it
+            if let Entry::Occupied(entry) = storage.entry(transaction_id)
+                && self.transitions.is_droppable(entry.get().status)
+            {
+                let transaction_info = entry.remove();
+                dropped
+                    .entry(transaction_info.client_id)
+                    .or_default()
+                    .insert(transaction_id);
+                expired.insert(transaction_id);
+            }
+        }
+        for (client_id, dropped_ids) in &dropped {
+            if let Some(transaction_ids) = client_index.get_mut(client_id) {
+                transaction_ids.retain(|id| !dropped_ids.contains(id));
+            }
+        }
+        Ok(dropped.values().map(HashSet::len).sum())
+    }
+
+    fn is_expired(&self, transaction_id: TransactionId) -> bool {
+        self.expired.read().unwrap().contains(transaction_id)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::transactions_processor::{TransactionInfo, TransactionInfoType, TransactionStatus};
+    use rstest::rstest;
     use rust_decimal::dec;
 
+    fn transaction_at(
+        sequence_number: SequenceNumber,
+        status: TransactionStatus,
+    ) -> TransactionInfo {
+        TransactionInfo {
+            client_id: 1,
+            transaction_id: sequence_number,
+            amount: dec!(10),
+            transaction_type: TransactionInfoType::Deposit,
+            status,
+            sequence_number,
+            timestamp: Some(1_000 + sequence_number * 60),
+            dispute_reason: None,
+        }
+    }
+
     #[test]
     fn test_add_transaction_successful() {
         let storage = InMemoryTransactionStorage::new();
@@ -93,6 +505,9 @@ mod tests {
             amount: dec!(50.00),
             transaction_type: TransactionInfoType::Deposit,
             status: TransactionStatus::WithoutDisputes,
+            sequence_number: 1,
+            timestamp: None,
+            dispute_reason: None,
         };
 
         let result = storage.add_transaction(transaction_info.clone());
@@ -120,6 +535,9 @@ mod tests {
             amount: dec!(50.00),
             transaction_type: TransactionInfoType::Deposit,
             status: TransactionStatus::WithoutDisputes,
+            sequence_number: 1,
+            timestamp: None,
+            dispute_reason: None,
         };
 
         let second_transaction = TransactionInfo {
@@ -128,6 +546,9 @@ mod tests {
             amount: dec!(75.00),
             transaction_type: TransactionInfoType::Deposit,
             status: TransactionStatus::Disputed,
+            sequence_number: 2,
+            timestamp: None,
+            dispute_reason: None,
         };
 
         let result1 = storage.add_transaction(first_transaction.clone());
@@ -147,4 +568,283 @@ mod tests {
         assert_eq!(stored_transaction.client_id, first_transaction.client_id);
         assert_eq!(stored_transaction.amount, first_transaction.amount);
     }
+
+    #[test]
+    fn test_status_changes_are_appended() {
+        let storage = InMemoryTransactionStorage::new();
+        storage
+            .add_transaction(transaction_at(1, TransactionStatus::WithoutDisputes))
+            .unwrap();
+        storage
+            .add_transaction(transaction_at(2, TransactionStatus::WithoutDisputes))
+            .unwrap();
+        let cause = |sequence_number| TransactionMeta {
+            sequence_number,
+            timestamp: Some(5_000 + sequence_number),
+        };
+
+        storage
+            .update_transaction_status(2, TransactionStatus::Disputed, &cause(4))
+            .unwrap();
+        storage
+            .update_transaction_status(1, TransactionStatus::Disputed, &cause(3))
+            .unwrap();
+        storage
+            .update_transaction_status(1, TransactionStatus::Chargebacked, &cause(7))
+            .unwrap();
+        // rejected transitions leave no trace
+        storage
+            .update_transaction_status(1, TransactionStatus::Resolved, &cause(8))
+            .unwrap_err();
+
+        let status_changes = storage.status_changes(Some(1)).unwrap();
+        assert_eq!(status_changes.len(), 2);
+        assert_eq!(
+            status_changes[1],
+            StatusChange {
+                transaction_id: 1,
+                previous_status: TransactionStatus::Disputed,
+                new_status: TransactionStatus::Chargebacked,
+                sequence_number: 7,
+                timestamp: Some(5_007),
+            }
+        );
+        let all_changes = storage.status_changes(None).unwrap();
+        assert_eq!(
+            all_changes
+                .iter()
+                .map(|status_change| (status_change.transaction_id, status_change.sequence_number))
+                .collect::<Vec<_>>(),
+            vec![(1, 3), (2, 4), (1, 7)]
+        );
+        assert!(storage.status_changes(Some(100)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_set_dispute_reason() {
+        let storage = InMemoryTransactionStorage::new();
+        storage
+            .add_transaction(transaction_at(1, TransactionStatus::Disputed))
+            .unwrap();
+
+        storage
+            .set_dispute_reason(1, Some(DisputeReason::Duplicate))
+            .unwrap();
+        let result = storage.set_dispute_reason(2, None);
+
+        assert_eq!(
+            storage.find_transaction(1).unwrap().dispute_reason,
+            Some(DisputeReason::Duplicate)
+        );
+        let error = result.unwrap_err();
+        let history_error = error.downcast_ref::<TransactionHistoryError>().unwrap();
+        assert_eq!(*history_error, TransactionHistoryError::UnknownTransaction);
+    }
+
+    #[rstest]
+    #[case(DisputeWindow::Transactions(3), 10, 13, true)]
+    #[case(DisputeWindow::Transactions(3), 10, 14, false)]
+    #[case(DisputeWindow::Transactions(0), 10, 10, true)]
+    #[case(DisputeWindow::Transactions(0), 10, 11, false)]
+    #[case(DisputeWindow::Seconds(120), 10, 12, true)]
+    #[case(DisputeWindow::Seconds(120), 10, 13, false)]
+    fn test_dispute_window_is_open(
+        #[case] dispute_window: DisputeWindow,
+        #[case] transaction_sequence_number: SequenceNumber,
+        #[case] now_sequence_number: SequenceNumber,
+        #[case] expected: bool,
+    ) {
+        let transaction_info = transaction_at(
+            transaction_sequence_number,
+            TransactionStatus::WithoutDisputes,
+        );
+        let now = TransactionMeta {
+            sequence_number: now_sequence_number,
+            timestamp: Some(1_000 + now_sequence_number * 60),
+        };
+
+        assert_eq!(dispute_window.is_open(&transaction_info, &now), expected);
+    }
+
+    #[test]
+    fn test_dispute_window_in_seconds_without_timestamps_is_open() {
+        let mut transaction_info = transaction_at(1, TransactionStatus::WithoutDisputes);
+        transaction_info.timestamp = None;
+        let now = TransactionMeta {
+            sequence_number: 1_000_000,
+            timestamp: Some(u64::MAX),
+        };
+
+        assert!(DisputeWindow::Seconds(1).is_open(&transaction_info, &now));
+    }
+
+    #[test]
+    fn test_drop_expired_without_window_keeps_everything() {
+        let storage = InMemoryTransactionStorage::new();
+        storage
+            .add_transaction(transaction_at(1, TransactionStatus::WithoutDisputes))
+            .unwrap();
+
+        let dropped = storage
+            .drop_expired(&TransactionMeta {
+                sequence_number: 1000,
+                timestamp: None,
+            })
+            .unwrap();
+
+        assert_eq!(dropped, 0);
+        assert!(storage.find_transaction(1).is_some());
+    }
+
+    #[test]
+    fn test_drop_expired_keeps_open_disputes() {
+        let storage =
+            InMemoryTransactionStorage::new().with_dispute_window(DisputeWindow::Transactions(5));
+        storage
+            .add_transaction(transaction_at(1, TransactionStatus::WithoutDisputes))
+            .unwrap();
+        storage
+            .add_transaction(transaction_at(2, TransactionStatus::Disputed))
+            .unwrap();
+        storage
+            .add_transaction(transaction_at(3, TransactionStatus::Resolved))
+            .unwrap();
+        storage
+            .add_transaction(transaction_at(4, TransactionStatus::Chargebacked))
+            .unwrap();
+        storage
+            .add_transaction(transaction_at(8, TransactionStatus::WithoutDisputes))
+            .unwrap();
+
+        let dropped = storage
+            .drop_expired(&TransactionMeta {
+                sequence_number: 9,
+                timestamp: None,
+            })
+            .unwrap();
+
+        assert_eq!(dropped, 2);
+        assert!(storage.find_transaction(1).is_none());
+        assert!(storage.find_transaction(2).is_some());
+        assert!(storage.find_transaction(3).is_none());
+        assert!(storage.find_transaction(4).is_some());
+        assert!(storage.find_transaction(8).is_some());
+        assert_eq!(storage.len(), 3);
+        assert!(storage.is_expired(1));
+        assert!(!storage.is_expired(2));
+        assert!(storage.is_expired(3));
+        let error = storage
+            .add_transaction(transaction_at(3, TransactionStatus::WithoutDisputes))
+            .unwrap_err();
+        assert_eq!(
+            *error.downcast_ref::<TransactionHistoryError>().unwrap(),
+            TransactionHistoryError::TransactionAlreadyExists
+        );
+    }
+
+    #[test]
+    fn test_drop_expired_once_dispute_is_closed() {
+        let storage =
+            InMemoryTransactionStorage::new().with_dispute_window(DisputeWindow::Transactions(5));
+        for sequence_number in 1..=3 {
+            storage
+                .add_transaction(transaction_at(
+                    sequence_number,
+                    TransactionStatus::WithoutDisputes,
+                ))
+                .unwrap();
+        }
+        let at = |sequence_number| TransactionMeta {
+            sequence_number,
+            timestamp: None,
+        };
+        storage
+            .update_transaction_status(2, TransactionStatus::Disputed, &at(4))
+            .unwrap();
+        assert_eq!(storage.drop_expired(&at(9)).unwrap(), 2);
+
+        storage
+            .update_transaction_status(2, TransactionStatus::Resolved, &at(10))
+            .unwrap();
+        let dropped = storage.drop_expired(&at(11)).unwrap();
+
+        assert_eq!(dropped, 1);
+        assert!(storage.is_empty());
+        assert!(storage.is_expired(2));
+        assert!(storage.client_transactions(1).unwrap().is_empty());
+        assert!(storage.expiry_queue.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_expired_ids_merge_into_ranges() {
+        let mut expired = ExpiredIds::default();
+        for transaction_id in [5, 1, 3, 2, 7, 4, 3, u64::MAX] {
+            expired.insert(transaction_id);
+        }
+
+        assert_eq!(expired.ranges(), 3);
+        for transaction_id in [1, 2, 3, 4, 5, 7, u64::MAX] {
+            assert!(expired.contains(transaction_id));
+        }
+        for transaction_id in [0, 6, 8, u64::MAX - 1] {
+            assert!(!expired.contains(transaction_id));
+        }
+    }
+
+    #[rstest]
+    #[case("100", Ok(DisputeWindow::Transactions(100)))]
+    #[case("30s", Ok(DisputeWindow::Seconds(30)))]
+    #[case("0", Ok(DisputeWindow::Transactions(0)))]
+    #[case("s", Err(()))]
+    #[case("10m", Err(()))]
+    #[case("-1", Err(()))]
+    fn test_dispute_window_from_str(
+        #[case] value: &str,
+        #[case] expected: Result<DisputeWindow, ()>,
+    ) {
+        assert_eq!(value.parse::<DisputeWindow>().map_err(|_| ()), expected);
+    }
+
+    #[test]
+    fn test_client_transactions_follow_drops() {
+        let storage =
+            InMemoryTransactionStorage::new().with_dispute_window(DisputeWindow::Transactions(5));
+        for (sequence_number, status) in [
+            (3, TransactionStatus::WithoutDisputes),
+            (1, TransactionStatus::Disputed),
+            (8, TransactionStatus::WithoutDisputes),
+        ] {
+            storage
+                .add_transaction(transaction_at(sequence_number, status))
+                .unwrap();
+        }
+        storage
+            .add_transaction(TransactionInfo {
+                client_id: 2,
+                ..transaction_at(4, TransactionStatus::WithoutDisputes)
+            })
+            .unwrap();
+
+        let ids = |client_id| {
+            storage
+                .client_transactions(client_id)
+                .unwrap()
+                .iter()
+                .map(|transaction_info| transaction_info.transaction_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(1), vec![1, 3, 8]);
+        assert_eq!(ids(2), vec![4]);
+        assert!(ids(3).is_empty());
+
+        storage
+            .drop_expired(&TransactionMeta {
+                sequence_number: 9,
+                timestamp: None,
+            })
+            .unwrap();
+
+        assert_eq!(ids(1), vec![1, 8]);
+        assert_eq!(ids(2), vec![4]);
+    }
 }
@@ -0,0 +1,503 @@
+use std::{error::Error, str::FromStr, sync::Arc};
+
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    csv_utils::{self, CsvAccountData, CsvOpenDisputeData},
+    errors::{
+        AccountError, TransactionError, TransactionHistoryError, TransactionLogError, error_code,
+    },
+    held_funds::held_transactions,
+    history::TransactionHistoryStorage,
+    metrics,
+    storage::ClientId,
+    transactions::{SequenceNumber, Timestamp, TransactionId},
+    transactions_processor::{
+        InMemoryTransactionProcessor, RowOrigin, TransactionInfo, TransactionInfoType,
+        TransactionLogEntry, TransactionProcessor, TransactionStatus,
+    },
+};
+
+const HTTP_INPUT_NAME: &str = "<http>";
+const OPEN_DISPUTES: &str = "open";
+
+type SharedProcessor<H> = Arc<InMemoryTransactionProcessor<H>>;
+
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    status: StatusCode,
+    code: String,
+    error: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, code: &str, error: impl ToString) -> Self {
+        Self {
+            status,
+            code: code.to_string(),
+            error: error.to_string(),
+        }
+    }
+
+    fn from_engine(error: &(dyn Error + 'static)) -> Self {
+        Self {
+            status: http_status(error),
+            code: error_code(error).to_string(),
+            error: error.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self)).into_response()
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionOutcome {
+    Accepted,
+    Rejected,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TransactionResult {
+    tx: TransactionId,
+    status: TransactionOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TransactionData {
+    #[serde(rename = "client")]
+    client_id: ClientId,
+    #[serde(rename = "tx")]
+    transaction_id: TransactionId,
+    #[serde(rename = "type")]
+    transaction_type: TransactionInfoType,
+    amount: Decimal,
+    status: TransactionStatus,
+    sequence_number: SequenceNumber,
+    timestamp: Option<Timestamp>,
+    reason: Option<String>,
+}
+
+impl From<TransactionInfo> for TransactionData {
+    fn from(transaction_info: TransactionInfo) -> Self {
+        Self {
+            client_id: transaction_info.client_id,
+            transaction_id: transaction_info.transaction_id,
+            transaction_type: transaction_info.transaction_type,
+            amount: transaction_info.amount,
+            status: transaction_info.status,
+            sequence_number: transaction_info.sequence_number,
+            timestamp: transaction_info.timestamp,
+            reason: transaction_info
+                .dispute_reason
+                .map(|reason| reason.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DisputesQuery {
+    status: Option<String>,
+}
+
+// Rejections map to 404 for unknown accounts and transactions, 409 for operations the current account or
+// transaction state doesn't allow and 422 for rows that can never be processed
+pub fn http_status(error: &(dyn Error + 'static)) -> StatusCode {
+    if let Some(e) = error.downcast_ref::<AccountError>() {
+        return match e {
+            AccountError::AccountNotFound => StatusCode::NOT_FOUND,
+            AccountError::BalanceOverflow => StatusCode::UNPROCESSABLE_ENTITY,
+            AccountError::InsufficientMoney
+            | AccountError::AccountLocked
+            | AccountError::AccountFrozen
+            | AccountError::AccountClosed
+            | AccountError::AccountNotVerified
+            | AccountError::AccountAlreadyExists
+            | AccountError::InvalidStatusTransition
+            | AccountError::NonZeroBalance => StatusCode::CONFLICT,
+        };
+    }
+    if let Some(e) = error.downcast_ref::<TransactionError>() {
+        return match e {
+            TransactionError::OriginTransactionNotFound => StatusCode::NOT_FOUND,
+            TransactionError::NegativeAmount | TransactionError::EmptyAmount => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            TransactionError::TransactionNotDisputed
+            | TransactionError::TransactionMultipleDispute
+            | TransactionError::DisputeWindowExpired
+            | TransactionError::TransactionNotChargebacked
+            | TransactionError::EscalationNotAllowed => StatusCode::CONFLICT,
+        };
+    }
+    if let Some(e) = error.downcast_ref::<TransactionLogError>() {
+        return match e {
+            TransactionLogError::InvalidTransactionType
+            | TransactionLogError::MissingAmount
+            | TransactionLogError::LateRow => StatusCode::UNPROCESSABLE_ENTITY,
+        };
+    }
+    if let Some(e) = error.downcast_ref::<TransactionHistoryError>() {
+        return match e {
+            TransactionHistoryError::UnknownTransaction => StatusCode::NOT_FOUND,
+            TransactionHistoryError::TransactionAlreadyExists
+            | TransactionHistoryError::InvalidStatusTransition => StatusCode::CONFLICT,
+            TransactionHistoryError::CorruptedRecord => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+    }
+    StatusCode::UNPROCESSABLE_ENTITY
+}
+
+pub fn router<H: TransactionHistoryStorage + Send + Sync + 'static>(
+    processor: SharedProcessor<H>,
+) -> Router {
+    Router::new()
+        .route("/transactions", post(post_transactions::<H>))
+        .route("/transactions/{tx}", get(get_transaction::<H>))
+        .route("/accounts", get(get_accounts::<H>))
+        .route("/accounts/{client}", get(get_account::<H>))
+        .route("/disputes", get(get_disputes::<H>))
+        .with_state(processor.clone())
+        .merge(metrics::router(processor))
+}
+
+fn parse_id<T: FromStr>(value: &str, name: &str) -> Result<T, ApiError> {
+    value.parse().map_err(|_| {
+        ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "INVALID_PATH",
+            format!("Invalid {name} `{value}`"),
+        )
+    })
+}
+
+fn with_origin(transaction_entry: TransactionLogEntry, line: u64) -> TransactionLogEntry {
+    TransactionLogEntry {
+        origin: Some(RowOrigin {
+            input: HTTP_INPUT_NAME.into(),
+            line,
+        }),
+        ..transaction_entry
+    }
+}
+
+// A JSON object is processed as one transaction and answered with its error status when rejected.
+// A JSON array is processed in order, without rows of other requests in between, and answered with
+// one result per transaction
+async fn post_transactions<H: TransactionHistoryStorage>(
+    State(processor): State<SharedProcessor<H>>,
+    body: Bytes,
+) -> Result<Response, ApiError> {
+    let invalid_body =
+        |e: serde_json::Error| ApiError::new(StatusCode::BAD_REQUEST, "INVALID_BODY", e);
+    let is_batch = body.iter().find(|byte| !byte.is_ascii_whitespace()) == Some(&b'[');
+    if !is_batch {
+        let transaction_entry =
+            serde_json::from_slice::<TransactionLogEntry>(&body).map_err(invalid_body)?;
+        let transaction_id = transaction_entry.transaction_id;
+        processor
+            .process(with_origin(transaction_entry, 1))
+            .map_err(|e| ApiError::from_engine(e.as_ref()))?;
+        return Ok(Json(TransactionResult {
+            tx: transaction_id,
+            status: TransactionOutcome::Accepted,
+            code: None,
+            error: None,
+        })
+        .into_response());
+    }
+
+    let transaction_entries =
+        serde_json::from_slice::<Vec<TransactionLogEntry>>(&body).map_err(invalid_body)?;
+    let transaction_ids = transaction_entries
+        .iter()
+        .map(|transaction_entry| transaction_entry.transaction_id)
+        .collect::<Vec<_>>();
+    let transaction_entries = (1..)
+        .zip(transaction_entries)
+        .map(|(line, transaction_entry)| with_origin(transaction_entry, line))
+        .collect();
+    let results = transaction_ids
+        .into_iter()
+        .zip(processor.process_batch(transaction_entries))
+        .map(|(transaction_id, result)| match result {
+            Ok(()) => TransactionResult {
+                tx: transaction_id,
+                status: TransactionOutcome::Accepted,
+                code: None,
+                error: None,
+            },
+            Err(e) => {
+                let e = ApiError::from_engine(e.as_ref());
+                TransactionResult {
+                    tx: transaction_id,
+                    status: TransactionOutcome::Rejected,
+                    code: Some(e.code),
+                    error: Some(e.error),
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(Json(results).into_response())
+}
+
+async fn get_transaction<H: TransactionHistoryStorage>(
+    State(processor): State<SharedProcessor<H>>,
+    Path(transaction_id): Path<String>,
+) -> Result<Json<TransactionData>, ApiError> {
+    let transaction_id = parse_id::<TransactionId>(&transaction_id, "transaction")?;
+    processor
+        .get_history()
+        .find_transaction(transaction_id)
+        .map(|transaction_info| Json(transaction_info.into()))
+        .ok_or_else(|| ApiError::from_engine(&TransactionHistoryError::UnknownTransaction))
+}
+
+async fn get_accounts<H: TransactionHistoryStorage>(
+    State(processor): State<SharedProcessor<H>>,
+) -> Json<Vec<CsvAccountData>> {
+    let mut accounts = csv_utils::account_summaries(processor.get_accounts_storage());
+    accounts.sort_by_key(|account| account.client_id);
+    Json(accounts)
+}
+
+async fn get_account<H: TransactionHistoryStorage>(
+    State(processor): State<SharedProcessor<H>>,
+    Path(client_id): Path<String>,
+) -> Result<Json<CsvAccountData>, ApiError> {
+    let client_id = parse_id::<ClientId>(&client_id, "client")?;
+    csv_utils::account_summary(processor.get_accounts_storage(), client_id)
+        .map(Json)
+        .ok_or_else(|| ApiError::from_engine(&AccountError::AccountNotFound))
+}
+
+// Only open disputes, i.e. transactions currently holding funds, are listed
+async fn get_disputes<H: TransactionHistoryStorage>(
+    State(processor): State<SharedProcessor<H>>,
+    Query(query): Query<DisputesQuery>,
+) -> Result<Json<Vec<CsvOpenDisputeData>>, ApiError> {
+    if let Some(status) = query.status.filter(|status| status != OPEN_DISPUTES) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "INVALID_QUERY",
+            format!("Unsupported dispute status `{status}`, only `{OPEN_DISPUTES}` is listed"),
+        ));
+    }
+    let held_transactions = held_transactions(processor.get_history(), &processor.current_meta())
+        .map_err(|e| {
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "HISTORY_UNAVAILABLE", e)
+    })?;
+    Ok(Json(
+        held_transactions
+            .into_iter()
+            .map(CsvOpenDisputeData::from)
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt;
+    use rstest::rstest;
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    async fn call(router: &Router, method: &str, uri: &str, body: &str) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_transactions_and_queries() {
+        let router = router(Arc::new(InMemoryTransactionProcessor::new()));
+
+        let (status, body) = call(
+            &router,
+            "POST",
+            "/transactions",
+            r#"{"type":"deposit","client":1,"tx":1,"amount":10.5}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({"tx": 1, "status": "accepted"}));
+
+        let (status, body) = call(
+            &router,
+            "POST",
+            "/transactions",
+            r#"[
+                {"type":"deposit","client":2,"tx":2,"amount":"0.0001"},
+                {"type":"withdrawal","client":2,"tx":3,"amount":5},
+                {"type":"dispute","client":1,"tx":1,"reason":"fraud"}
+            ]"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            json!([
+                {"tx": 2, "status": "accepted"},
+                {"tx": 3, "status": "rejected", "code": "INSUFFICIENT_MONEY", "error": "Insufficient money"},
+                {"tx": 1, "status": "accepted"},
+            ])
+        );
+
+        let (_, body) = call(&router, "GET", "/accounts", "").await;
+        assert_eq!(
+            body,
+            json!([
+                {"client": 1, "available": "0.0", "held": "10.5", "total": "10.5", "state": "active"},
+                {"client": 2, "available": "0.0001", "held": "0", "total": "0.0001", "state": "active"},
+            ])
+        );
+        let (_, body) = call(&router, "GET", "/accounts/2", "").await;
+        assert_eq!(body["available"], "0.0001");
+
+        let (status, body) = call(&router, "GET", "/transactions/1", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "disputed");
+        assert_eq!(body["reason"], "fraud");
+
+        let (status, body) = call(&router, "GET", "/disputes?status=open", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        assert_eq!(body[0]["tx"], 1);
+        assert_eq!(body[0]["age_transactions"], 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_batches_are_not_interleaved() {
+        let processor = Arc::new(InMemoryTransactionProcessor::new());
+        let router = router(processor.clone());
+
+        let requests = (0..8)
+            .map(|batch| {
+                let router = router.clone();
+                let body = (0..50)
+                    .map(|row| json!({"type": "deposit", "client": 1, "tx": batch * 50 + row, "amount": 1}))
+                    .collect::<Vec<_>>();
+                tokio::spawn(async move {
+                    call(&router, "POST", "/transactions", &Value::from(body).to_string()).await
+                })
+            })
+            .collect::<Vec<_>>();
+        for request in requests {
+            assert_eq!(request.await.unwrap().0, StatusCode::OK);
+        }
+
+        for batch in 0..8 {
+            let sequence_numbers = (0..50)
+                .map(|row| {
+                    processor
+                        .get_history()
+                        .find_transaction(batch * 50 + row)
+                        .unwrap()
+                        .sequence_number
+                })
+                .collect::<Vec<_>>();
+            let first = sequence_numbers[0];
+            assert_eq!(sequence_numbers, (first..first + 50).collect::<Vec<_>>());
+        }
+    }
+
+    #[rstest]
+    #[case(
+        "POST",
+        "/transactions",
+        r#"{"type":"withdrawal","client":1,"tx":9,"amount":1}"#,
+        StatusCode::NOT_FOUND,
+        "ACCOUNT_NOT_FOUND"
+    )]
+    #[case(
+        "POST",
+        "/transactions",
+        r#"{"type":"dispute","client":1,"tx":9}"#,
+        StatusCode::NOT_FOUND,
+        "ORIGIN_TRANSACTION_NOT_FOUND"
+    )]
+    #[case(
+        "POST",
+        "/transactions",
+        r#"{"type":"deposit","client":1,"tx":9}"#,
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "MISSING_AMOUNT"
+    )]
+    #[case(
+        "POST",
+        "/transactions",
+        r#"{"type":"deposit""#,
+        StatusCode::BAD_REQUEST,
+        "INVALID_BODY"
+    )]
+    #[case("GET", "/accounts/7", "", StatusCode::NOT_FOUND, "ACCOUNT_NOT_FOUND")]
+    #[case("GET", "/accounts/x", "", StatusCode::BAD_REQUEST, "INVALID_PATH")]
+    #[case(
+        "GET",
+        "/transactions/7",
+        "",
+        StatusCode::NOT_FOUND,
+        "UNKNOWN_TRANSACTION"
+    )]
+    #[case(
+        "GET",
+        "/disputes?status=closed",
+        "",
+        StatusCode::BAD_REQUEST,
+        "INVALID_QUERY"
+    )]
+    #[tokio::test]
+    async fn test_errors(
+        #[case] method: &str,
+        #[case] uri: &str,
+        #[case] body: &str,
+        #[case] status: StatusCode,
+        #[case] code: &str,
+    ) {
+        let router = router(Arc::new(InMemoryTransactionProcessor::new()));
+
+        let (response_status, response) = call(&router, method, uri, body).await;
+
+        assert_eq!(response_status, status);
+        assert_eq!(response["code"], code);
+    }
+
+    #[test]
+    fn test_http_status() {
+        assert_eq!(
+            http_status(&AccountError::AccountLocked),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            http_status(&TransactionHistoryError::CorruptedRecord),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
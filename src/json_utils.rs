@@ -0,0 +1,218 @@
+use std::{error::Error, sync::Arc};
+
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+use crate::{
+    errors::CliError,
+    pipeline::{InputPosition, TransactionSource},
+    transactions_processor::{RowOrigin, TransactionLogEntry},
+};
+
+// One JSON object per line with the same fields as the CSV input, blank lines are skipped
+pub struct JsonlRows {
+    name: Arc<str>,
+    reader: BufReader<Box<dyn AsyncRead + Unpin + Send>>,
+    line: u64,
+    offset: u64,
+}
+
+impl JsonlRows {
+    pub fn new(name: Arc<str>, source: Box<dyn AsyncRead + Unpin + Send>) -> Self {
+        Self::starting_at(name, source, 0, 0)
+    }
+
+    // `source` continues an input after `line` lines and `offset` bytes
+    pub fn starting_at(
+        name: Arc<str>,
+        source: Box<dyn AsyncRead + Unpin + Send>,
+        line: u64,
+        offset: u64,
+    ) -> Self {
+        Self {
+            name,
+            reader: BufReader::new(source),
+            line,
+            offset,
+        }
+    }
+
+    pub fn position(&self) -> InputPosition {
+        InputPosition {
+            line: self.line,
+            offset: self.offset,
+            ..InputPosition::default()
+        }
+    }
+}
+
+impl TransactionSource for JsonlRows {
+    async fn next_row(&mut self) -> Option<Result<TransactionLogEntry, CliError>> {
+        let mut text = String::new();
+        loop {
+            text.clear();
+            match self.reader.read_line(&mut text).await {
+                Ok(0) => return None,
+                Ok(read) => self.offset += read as u64,
+                Err(e) => {
+                    return Some(Err(CliError::InputUnreadable(format!(
+                        "{}: {e}",
+                        self.name
+                    ))));
+                }
+            }
+            self.line += 1;
+            let record = text.trim();
+            if record.is_empty() {
+                continue;
+            }
+            let origin = Some(RowOrigin {
+                input: self.name.clone(),
+                line: self.line,
+            });
+            return Some(
+                serde_json::from_str::<TransactionLogEntry>(record)
+                    .map(|transaction_entry| TransactionLogEntry {
+                        origin: origin.clone(),
+                        ..transaction_entry
+                    })
+                    .map_err(|e| CliError::RejectedRow {
+                        origin,
+                        record: record.to_string(),
+                        error: e.to_string(),
+                    }),
+            );
+        }
+    }
+}
+
+pub async fn write_json<T: Serialize, W: AsyncWrite + Unpin>(
+    mut output: W,
+    rows: &[T],
+) -> Result<(), Box<dyn Error>> {
+    let mut buffer = serde_json::to_vec(rows)?;
+    buffer.push(b'\n');
+    output.write_all(&buffer).await?;
+    output.shutdown().await?;
+    Ok(())
+}
+
+pub async fn write_jsonl<T: Serialize, W: AsyncWrite + Unpin>(
+    mut output: W,
+    rows: &[T],
+) -> Result<(), Box<dyn Error>> {
+    let mut buffer = Vec::new();
+    for row in rows {
+        serde_json::to_writer(&mut buffer, row)?;
+        buffer.push(b'\n');
+    }
+    output.write_all(&buffer).await?;
+    output.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::{Decimal, dec};
+
+    async fn read_all(content: &'static str) -> Vec<Result<TransactionLogEntry, CliError>> {
+        let mut rows = JsonlRows::new("feed.jsonl".into(), Box::new(content.as_bytes()));
+        let mut result = Vec::new();
+        while let Some(row) = rows.next_row().await {
+            result.push(row);
+        }
+        result
+    }
+
+    #[tokio::test]
+    async fn test_read_rows_keep_exact_amounts() {
+        let rows = read_all(
+            r#"{"type":"deposit","client":1,"tx":1,"amount":0.1234,"timestamp":100}
+
+{"type":"withdrawal","client":1,"tx":2,"amount":"79228162514264337593543950335"}
+{"type":"dispute","client":1,"tx":1,"reason":"fraud"}
+"#,
+        )
+        .await;
+
+        let rows = rows.into_iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(rows[0].amount, Some(dec!(0.1234)));
+        assert_eq!(rows[0].timestamp, Some(100));
+        assert_eq!(rows[1].amount, Some(Decimal::MAX));
+        assert_eq!(rows[2].amount, None);
+        assert_eq!(rows[2].reason.as_deref(), Some("fraud"));
+        let lines = rows
+            .iter()
+            .map(|row| row.origin.as_ref().unwrap().line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![1, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_read_rows_reject_invalid_line() {
+        let rows = read_all(
+            "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":1}\n{\"type\":\"deposit\"\n",
+        )
+        .await;
+
+        assert!(rows[0].is_ok());
+        let CliError::RejectedRow { origin, record, .. } = rows[1].as_ref().unwrap_err() else {
+            panic!("expected rejected row");
+        };
+        assert_eq!(origin.as_ref().unwrap().line, 2);
+        assert_eq!(record, "{\"type\":\"deposit\"");
+    }
+
+    #[tokio::test]
+    async fn test_rows_continue_at_position() {
+        let content = "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":1}\r\n\n{\"type\":\"deposit\",\"client\":1,\"tx\":2,\"amount\":2}\n";
+        let mut rows = JsonlRows::new("feed.jsonl".into(), Box::new(content.as_bytes()));
+        rows.next_row().await.unwrap().unwrap();
+        let position = rows.position();
+        assert_eq!((position.line, position.offset), (1, 49));
+
+        let rest = &content.as_bytes()[position.offset as usize..];
+        let mut resumed = JsonlRows::starting_at(
+            "feed.jsonl".into(),
+            Box::new(rest),
+            position.line,
+            position.offset,
+        );
+        let row = resumed.next_row().await.unwrap().unwrap();
+
+        assert_eq!(row.transaction_id, 2);
+        assert_eq!(row.origin.unwrap().line, 3);
+        assert!(resumed.next_row().await.is_none());
+        assert_eq!(resumed.position().offset, content.len() as u64);
+    }
+
+    #[derive(Serialize)]
+    struct Row {
+        amount: Decimal,
+    }
+
+    #[tokio::test]
+    async fn test_write_decimals_as_strings() {
+        let rows = [
+            Row { amount: dec!(1.10) },
+            Row {
+                amount: dec!(-0.0001),
+            },
+        ];
+        let mut json = Vec::new();
+        let mut jsonl = Vec::new();
+
+        write_json(&mut json, &rows).await.unwrap();
+        write_jsonl(&mut jsonl, &rows).await.unwrap();
+
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "[{\"amount\":\"1.10\"},{\"amount\":\"-0.0001\"}]\n"
+        );
+        assert_eq!(
+            String::from_utf8(jsonl).unwrap(),
+            "{\"amount\":\"1.10\"}\n{\"amount\":\"-0.0001\"}\n"
+        );
+    }
+}
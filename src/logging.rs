@@ -0,0 +1,128 @@
+use std::{fs::OpenOptions, io::IsTerminal, sync::Mutex};
+
+use tracing::Subscriber;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+
+use crate::{
+    cli::{Cli, LogFormat, LogLevel},
+    errors::CliError,
+};
+
+pub fn subscriber(
+    level: LogLevel,
+    format: LogFormat,
+    writer: BoxMakeWriter,
+    ansi: bool,
+) -> Box<dyn Subscriber + Send + Sync> {
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::from(level))
+        .with_writer(writer)
+        .with_ansi(ansi);
+    match format {
+        LogFormat::Text => Box::new(builder.finish()),
+        LogFormat::Json => Box::new(builder.json().finish()),
+        LogFormat::Pretty => Box::new(builder.pretty().finish()),
+    }
+}
+
+// Installs the global subscriber, the log goes to stderr unless a file is given. A log file is
+// appended to, so several runs can share it
+pub fn init(cli: &Cli) -> Result<(), CliError> {
+    let (writer, ansi) = match &cli.log_file {
+        Some(log_path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log_path)
+                .map_err(|e| CliError::OutputUnwritable(format!("{log_path}: {e}")))?;
+            (BoxMakeWriter::new(Mutex::new(file)), false)
+        }
+        None => (
+            BoxMakeWriter::new(std::io::stderr),
+            std::io::stderr().is_terminal(),
+        ),
+    };
+    tracing::subscriber::set_global_default(subscriber(cli.log_level, cli.log_format, writer, ansi))
+        .map_err(|e| CliError::OutputUnwritable(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transactions_processor::{
+        InMemoryTransactionProcessor, RowOrigin, TransactionLogEntry, TransactionProcessor,
+    };
+    use rust_decimal::dec;
+    use serde_json::Value;
+    use std::{io, sync::Arc};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn log_rejected_withdrawal(format: LogFormat) -> String {
+        let buffer = Buffer::default();
+        let writer = {
+            let buffer = buffer.clone();
+            BoxMakeWriter::new(move || buffer.clone())
+        };
+        let processor = InMemoryTransactionProcessor::new();
+        let entry = TransactionLogEntry {
+            transaction_type: "withdrawal".to_string(),
+            client_id: 7,
+            transaction_id: 3,
+            amount: Some(dec!(1)),
+            timestamp: None,
+            reason: None,
+            origin: Some(RowOrigin {
+                input: "input.csv".into(),
+                line: 4,
+            }),
+        };
+
+        tracing::subscriber::with_default(
+            subscriber(LogLevel::Warn, format, writer, false),
+            || {
+                processor.process(entry).unwrap_err();
+            },
+        );
+
+        String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap()
+    }
+
+    #[test]
+    fn test_json_log_carries_row_fields() {
+        let log = log_rejected_withdrawal(LogFormat::Json);
+
+        let line = serde_json::from_str::<Value>(log.lines().next().unwrap()).unwrap();
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["span"]["name"], "process");
+        assert_eq!(line["span"]["client"], 7);
+        assert_eq!(line["span"]["tx"], 3);
+        assert_eq!(line["span"]["type"], "withdrawal");
+        assert_eq!(line["span"]["input"], "input.csv");
+        assert_eq!(line["span"]["line"], 4);
+    }
+
+    #[test]
+    fn test_text_log_carries_row_fields() {
+        let log = log_rejected_withdrawal(LogFormat::Text);
+
+        let line = log.lines().next().unwrap();
+        assert!(line.contains("WARN"), "{line}");
+        assert!(
+            line.contains("process{client=7 tx=3 type=withdrawal input=\"input.csv\" line=4}"),
+            "{line}"
+        );
+    }
+}
@@ -1,30 +1,50 @@
+pub mod checkpoint;
+pub mod cli;
+pub mod commands;
+pub mod compression;
 pub mod csv_utils;
+pub mod dispute_lifecycle;
+pub mod dispute_reasons;
 pub mod errors;
+pub mod follow;
+pub mod held_funds;
 pub mod history;
+pub mod http_api;
+pub mod json_utils;
+pub mod logging;
+pub mod metrics;
+pub mod pipeline;
+pub mod reorder;
+pub mod rpc;
+pub mod server;
+pub mod shutdown;
+pub mod statement;
 pub mod storage;
+pub mod tiered_history;
 pub mod transactions;
 pub mod transactions_processor;
 
-use transactions_processor::InMemoryTransactionProcessor;
+use std::process::ExitCode;
 
-use crate::transactions_processor::TransactionProcessor;
+use clap::Parser;
+use tracing::error;
 
-const CHANNEL_SIZE: usize = 4096;
+use crate::cli::Cli;
 
 #[tokio::main]
-async fn main() {
-    let file_path = std::env::args()
-        .nth(1)
-        .expect("Usage: cargo run -- <input.csv> > <output.csv>");
-
-    let (sender, mut receiver) = tokio::sync::mpsc::channel(CHANNEL_SIZE);
-
-    tokio::spawn(csv_utils::read_data(file_path, sender));
-
-    let transactions_processor = InMemoryTransactionProcessor::new();
-    while let Some(tx) = receiver.recv().await {
-        transactions_processor.process(tx).ok();
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if let Err(e) = logging::init(&cli) {
+        eprintln!("{e}");
+        return ExitCode::from(e.exit_code());
     }
 
-    csv_utils::output_data(&transactions_processor).await;
+    match commands::run(cli.into_command()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{e}");
+            eprintln!("{e}");
+            ExitCode::from(e.exit_code())
+        }
+    }
 }
@@ -0,0 +1,297 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{Router, extract::State, http::header, response::IntoResponse, routing::get};
+use rust_decimal::Decimal;
+
+use crate::{
+    errors::{TransactionLogError, error_code},
+    history::TransactionHistoryStorage,
+    storage::AccountStatus,
+    transactions_processor::InMemoryTransactionProcessor,
+};
+
+// upper bounds in seconds, processing a row usually takes a few microseconds
+const LATENCY_BUCKETS: [f64; 10] = [
+    0.000_001, 0.000_005, 0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.1,
+];
+// rows with an unknown type are counted under one label, so input can't create new series
+const INVALID_TYPE_LABEL: &str = "invalid";
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+#[derive(Debug, Default)]
+struct LatencyHistogram {
+    // not cumulative, every observation is counted in its first matching bucket only
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_nanoseconds: AtomicU64,
+}
+
+impl LatencyHistogram {
+    fn observe(&self, latency: Duration) {
+        let seconds = latency.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanoseconds.fetch_add(
+            u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+}
+
+// Counters recorded by the processor for every row, gauges are read from the processor when rendered
+#[derive(Debug, Default)]
+pub struct Metrics {
+    rows_by_type: Mutex<BTreeMap<String, u64>>,
+    rejections_by_error: Mutex<BTreeMap<String, u64>>,
+    latency: LatencyHistogram,
+}
+
+impl Metrics {
+    pub fn record(
+        &self,
+        transaction_type: &str,
+        error: Option<&(dyn Error + 'static)>,
+        latency: Duration,
+    ) {
+        let is_invalid_type = error
+            .and_then(|e| e.downcast_ref::<TransactionLogError>())
+            .is_some_and(|e| *e == TransactionLogError::InvalidTransactionType);
+        let type_label = if is_invalid_type {
+            INVALID_TYPE_LABEL
+        } else {
+            transaction_type
+        };
+        increment(&self.rows_by_type, type_label);
+        if let Some(e) = error {
+            increment(&self.rejections_by_error, error_code(e));
+        }
+        self.latency.observe(latency);
+    }
+
+    pub fn rows_by_type(&self) -> BTreeMap<String, u64> {
+        self.rows_by_type.lock().unwrap().clone()
+    }
+
+    pub fn rejections_by_error(&self) -> BTreeMap<String, u64> {
+        self.rejections_by_error.lock().unwrap().clone()
+    }
+}
+
+fn increment(counters: &Mutex<BTreeMap<String, u64>>, label: &str) {
+    let mut counters = counters.lock().unwrap();
+    match counters.get_mut(label) {
+        Some(count) => *count += 1,
+        None => {
+            counters.insert(label.to_string(), 1);
+        }
+    }
+}
+
+fn push_header(text: &mut String, name: &str, kind: &str, help: &str) {
+    text.push_str(&format!("# HELP {name} {help}\n# TYPE {name} {kind}\n"));
+}
+
+// Prometheus text exposition format of the processor metrics
+pub fn render<H: TransactionHistoryStorage>(processor: &InMemoryTransactionProcessor<H>) -> String {
+    let metrics = processor.get_metrics();
+    let mut text = String::new();
+
+    push_header(
+        &mut text,
+        "transaction_rows_total",
+        "counter",
+        "Processed rows by transaction type",
+    );
+    for (transaction_type, count) in metrics.rows_by_type() {
+        text.push_str(&format!(
+            "transaction_rows_total{{type=\"{transaction_type}\"}} {count}\n"
+        ));
+    }
+    push_header(
+        &mut text,
+        "transaction_rejections_total",
+        "counter",
+        "Rejected rows by error",
+    );
+    for (error, count) in metrics.rejections_by_error() {
+        text.push_str(&format!(
+            "transaction_rejections_total{{error=\"{error}\"}} {count}\n"
+        ));
+    }
+
+    push_header(
+        &mut text,
+        "transaction_processing_seconds",
+        "histogram",
+        "Time to process one row",
+    );
+    let mut cumulative = 0;
+    for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&metrics.latency.buckets) {
+        cumulative += bucket.load(Ordering::Relaxed);
+        text.push_str(&format!(
+            "transaction_processing_seconds_bucket{{le=\"{bound}\"}} {cumulative}\n"
+        ));
+    }
+    let count = metrics.latency.count.load(Ordering::Relaxed);
+    let sum = Duration::from_nanos(metrics.latency.sum_nanoseconds.load(Ordering::Relaxed));
+    text.push_str(&format!(
+        "transaction_processing_seconds_bucket{{le=\"+Inf\"}} {count}\n\
+         transaction_processing_seconds_sum {}\n\
+         transaction_processing_seconds_count {count}\n",
+        sum.as_secs_f64()
+    ));
+
+    let (accounts, locked_accounts, held_funds) = {
+        let accounts = processor.get_accounts_storage().accounts.read().unwrap();
+        let locked_accounts = accounts
+            .values()
+            .filter(|account| account.status() == AccountStatus::Locked)
+            .count();
+        let held_funds = accounts
+            .values()
+            .map(|account| account.held_balance())
+            .sum::<Decimal>();
+        (accounts.len(), locked_accounts, held_funds)
+    };
+    push_header(&mut text, "accounts", "gauge", "Client accounts");
+    text.push_str(&format!("accounts {accounts}\n"));
+    push_header(
+        &mut text,
+        "locked_accounts",
+        "gauge",
+        "Locked client accounts",
+    );
+    text.push_str(&format!("locked_accounts {locked_accounts}\n"));
+    push_header(
+        &mut text,
+        "held_funds",
+        "gauge",
+        "Funds held by disputes over all accounts",
+    );
+    text.push_str(&format!("held_funds {held_funds}\n"));
+    let held_disputes = processor.held_disputes();
+    push_header(
+        &mut text,
+        "open_disputes",
+        "gauge",
+        "Disputed transactions holding funds",
+    );
+    text.push_str(&format!("open_disputes {}\n", held_disputes.transactions));
+    push_header(
+        &mut text,
+        "open_disputes_amount",
+        "gauge",
+        "Amount of the disputed transactions holding funds",
+    );
+    text.push_str(&format!("open_disputes_amount {}\n", held_disputes.amount));
+    text
+}
+
+// `GET /metrics` in Prometheus text format
+pub fn router<H: TransactionHistoryStorage + Send + Sync + 'static>(
+    processor: Arc<InMemoryTransactionProcessor<H>>,
+) -> Router {
+    Router::new()
+        .route("/metrics", get(get_metrics::<H>))
+        .with_state(processor)
+}
+
+async fn get_metrics<H: TransactionHistoryStorage>(
+    State(processor): State<Arc<InMemoryTransactionProcessor<H>>>,
+) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], render(&processor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transactions_processor::{TransactionLogEntry, TransactionProcessor};
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt;
+    use rust_decimal::dec;
+    use tower::ServiceExt;
+
+    fn entry(
+        transaction_type: &str,
+        client_id: u16,
+        transaction_id: u64,
+        amount: Option<Decimal>,
+    ) -> TransactionLogEntry {
+        TransactionLogEntry {
+            transaction_type: transaction_type.to_string(),
+            client_id,
+            transaction_id,
+            amount,
+            timestamp: None,
+            reason: None,
+            origin: None,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let processor = InMemoryTransactionProcessor::new();
+        for transaction_entry in [
+            entry("deposit", 1, 1, Some(dec!(2.5))),
+            entry("deposit", 2, 2, Some(dec!(1))),
+            entry("withdrawal", 2, 3, Some(dec!(5))),
+            entry("dispute", 1, 1, None),
+            entry("refund", 1, 4, None),
+            entry("chargeback", 2, 9, None),
+        ] {
+            let _ = processor.process(transaction_entry);
+        }
+
+        let text = render(&processor);
+
+        for line in [
+            "transaction_rows_total{type=\"chargeback\"} 1",
+            "transaction_rows_total{type=\"deposit\"} 2",
+            "transaction_rows_total{type=\"invalid\"} 1",
+            "transaction_rejections_total{error=\"INSUFFICIENT_MONEY\"} 1",
+            "transaction_rejections_total{error=\"INVALID_TRANSACTION_TYPE\"} 1",
+            "transaction_rejections_total{error=\"ORIGIN_TRANSACTION_NOT_FOUND\"} 1",
+            "transaction_processing_seconds_bucket{le=\"+Inf\"} 6",
+            "transaction_processing_seconds_count 6",
+            "accounts 2",
+            "locked_accounts 0",
+            "held_funds 2.5",
+            "open_disputes 1",
+            "open_disputes_amount 2.5",
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "missing `{line}` in\n{text}"
+            );
+        }
+        assert!(!text.contains("refund"));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint() {
+        let processor = Arc::new(InMemoryTransactionProcessor::new());
+        processor
+            .process(entry("deposit", 1, 1, Some(dec!(1))))
+            .unwrap();
+
+        let response = router(processor)
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.headers()[header::CONTENT_TYPE], CONTENT_TYPE);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(text.contains("transaction_rows_total{type=\"deposit\"} 1\n"));
+    }
+}
@@ -0,0 +1,329 @@
+use std::{collections::VecDeque, error::Error, future::Future, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWrite;
+
+use crate::{
+    cli::{InputFormat, OutputFormat},
+    csv_utils::{self, CsvAccountData, InputRows},
+    errors::CliError,
+    transactions_processor::{RowOrigin, TransactionLogEntry},
+};
+
+const IN_MEMORY_NAME: &str = "<memory>";
+
+// Where reading got to in an input list. `line` and `offset` count the lines and bytes of the
+// input read so far, bytes of compressed inputs are counted after decompression. `header` is the
+// size of the CSV header row, which is read again when the input is reopened
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputPosition {
+    pub input_index: usize,
+    pub input: String,
+    pub header: u64,
+    pub line: u64,
+    pub offset: u64,
+}
+
+// Stream of input rows, every row carries the position it was read from in `origin`.
+// Rows that can't be parsed are returned as `CliError::RejectedRow`, other errors end the source
+pub trait TransactionSource: Send {
+    fn next_row(
+        &mut self,
+    ) -> impl Future<Output = Option<Result<TransactionLogEntry, CliError>>> + Send;
+
+    // position right after the last returned row, none for sources that can't be read again
+    fn position(&self) -> Option<InputPosition> {
+        None
+    }
+}
+
+// Receives the final account balances
+pub trait AccountSink {
+    fn write_accounts(
+        &mut self,
+        accounts: &[CsvAccountData],
+    ) -> impl Future<Output = Result<(), Box<dyn Error>>>;
+}
+
+// Files and stdin (`-`) read one after another, an input is opened once the previous one is exhausted.
+// Without an explicit format each input's format is taken from its extension
+pub struct InputsSource {
+    inputs: VecDeque<String>,
+    input_format: Option<InputFormat>,
+    follow: Option<Duration>,
+    current: Option<InputRows>,
+    // index in the input list and name of the input `current` reads
+    current_input: (usize, String),
+    opened: usize,
+    resume_at: Option<InputPosition>,
+}
+
+impl InputsSource {
+    pub fn new(inputs: Vec<String>, input_format: Option<InputFormat>) -> Self {
+        Self {
+            inputs: inputs.into(),
+            input_format,
+            follow: None,
+            current: None,
+            current_input: (0, String::new()),
+            opened: 0,
+            resume_at: None,
+        }
+    }
+
+    // inputs before the position are skipped and its input is read from the position on
+    pub fn with_resume(mut self, position: InputPosition) -> Self {
+        let skipped = position.input_index.min(self.inputs.len());
+        self.inputs.drain(..skipped);
+        self.opened = skipped;
+        self.resume_at = Some(position);
+        self
+    }
+
+    // the last input is followed for appended rows, polled every `poll_interval`, and never ends
+    pub fn with_follow(mut self, poll_interval: Duration) -> Self {
+        self.follow = Some(poll_interval);
+        self
+    }
+}
+
+impl TransactionSource for InputsSource {
+    async fn next_row(&mut self) -> Option<Result<TransactionLogEntry, CliError>> {
+        loop {
+            if let Some(rows) = &mut self.current {
+                match rows.next_row().await {
+                    Some(row) => return Some(row),
+                    None => self.current = None,
+                }
+            }
+            let Some(input) = self.inputs.pop_front() else {
+                return self.resume_at.take().map(|position| {
+                    Err(CliError::InputUnreadable(format!(
+                        "{}: not among the inputs, it was input {} when the checkpoint was taken",
+                        position.input,
+                        position.input_index + 1
+                    )))
+                });
+            };
+            let follow = self.follow.filter(|_| self.inputs.is_empty());
+            let opened = match self.resume_at.take() {
+                Some(position) => {
+                    csv_utils::reopen_input(&input, self.input_format, &position).await
+                }
+                None => csv_utils::open_input(&input, self.input_format, follow).await,
+            };
+            match opened {
+                Ok(rows) => {
+                    self.current = Some(rows);
+                    self.current_input = (self.opened, input);
+                    self.opened += 1;
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+
+    fn position(&self) -> Option<InputPosition> {
+        let (input_index, input) = &self.current_input;
+        Some(InputPosition {
+            input_index: *input_index,
+            input: input.clone(),
+            ..self.current.as_ref()?.position()
+        })
+    }
+}
+
+// Rows kept in memory, rows without an origin are numbered from 1 in the order they are returned
+pub struct InMemorySource {
+    name: Arc<str>,
+    rows: VecDeque<TransactionLogEntry>,
+    line: u64,
+}
+
+impl InMemorySource {
+    pub fn new(rows: impl IntoIterator<Item = TransactionLogEntry>) -> Self {
+        Self {
+            name: IN_MEMORY_NAME.into(),
+            rows: rows.into_iter().collect(),
+            line: 0,
+        }
+    }
+}
+
+impl TransactionSource for InMemorySource {
+    async fn next_row(&mut self) -> Option<Result<TransactionLogEntry, CliError>> {
+        let transaction_entry = self.rows.pop_front()?;
+        self.line += 1;
+        let origin = transaction_entry.origin.or_else(|| {
+            Some(RowOrigin {
+                input: self.name.clone(),
+                line: self.line,
+            })
+        });
+        Some(Ok(TransactionLogEntry {
+            origin,
+            ..transaction_entry
+        }))
+    }
+}
+
+// Writes accounts as CSV, JSON or JSON Lines to stdout, a file or any other writer
+pub struct WriterSink<W> {
+    output: W,
+    format: OutputFormat,
+}
+
+impl<W: AsyncWrite + Unpin> WriterSink<W> {
+    pub fn new(output: W, format: OutputFormat) -> Self {
+        Self { output, format }
+    }
+}
+
+impl WriterSink<Box<dyn AsyncWrite + Unpin + Send>> {
+    // writes to the file or to stdout without a path
+    pub async fn open(file_path: Option<&str>, format: OutputFormat) -> Result<Self, CliError> {
+        let output = csv_utils::open_output(file_path)
+            .await
+            .map_err(|e| CliError::OutputUnwritable(e.to_string()))?;
+        Ok(Self::new(output, format))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AccountSink for WriterSink<W> {
+    async fn write_accounts(&mut self, accounts: &[CsvAccountData]) -> Result<(), Box<dyn Error>> {
+        csv_utils::write_rows(&mut self.output, self.format, accounts).await
+    }
+}
+
+// Keeps the accounts of every write, mostly for embedding and tests
+#[derive(Debug, Default)]
+pub struct InMemorySink {
+    pub accounts: Vec<CsvAccountData>,
+}
+
+impl AccountSink for InMemorySink {
+    async fn write_accounts(&mut self, accounts: &[CsvAccountData]) -> Result<(), Box<dyn Error>> {
+        self.accounts.extend_from_slice(accounts);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        storage::AccountStatus,
+        transactions::TransactionId,
+        transactions_processor::{InMemoryTransactionProcessor, TransactionProcessor},
+    };
+    use rust_decimal::dec;
+
+    fn entry(transaction_type: &str, transaction_id: TransactionId) -> TransactionLogEntry {
+        TransactionLogEntry {
+            transaction_type: transaction_type.to_string(),
+            client_id: 1,
+            transaction_id,
+            amount: Some(dec!(2.5)),
+            timestamp: None,
+            reason: None,
+            origin: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_pipeline() {
+        let mut source = InMemorySource::new([entry("deposit", 1), entry("withdrawal", 2)]);
+        let processor = InMemoryTransactionProcessor::new();
+        let mut lines = Vec::new();
+        while let Some(row) = source.next_row().await {
+            let row = row.unwrap();
+            lines.push(row.origin.as_ref().unwrap().to_string());
+            processor.process(row).unwrap();
+        }
+        let mut sink = InMemorySink::default();
+
+        csv_utils::output_data(processor.get_accounts_storage(), &mut sink)
+            .await
+            .unwrap();
+
+        assert_eq!(lines, vec!["<memory>:1", "<memory>:2"]);
+        assert_eq!(
+            sink.accounts,
+            vec![CsvAccountData {
+                client_id: 1,
+                available: dec!(0.0),
+                held: dec!(0),
+                total: dec!(0.0),
+                state: AccountStatus::Active,
+            }]
+        );
+    }
+
+    // transaction id, origin line and position after every row
+    async fn read_all(mut source: InputsSource) -> Vec<(TransactionId, u64, InputPosition)> {
+        let mut rows = Vec::new();
+        while let Some(row) = source.next_row().await {
+            let row = row.unwrap();
+            let position = source.position().unwrap();
+            rows.push((row.transaction_id, row.origin.unwrap().line, position));
+        }
+        rows
+    }
+
+    #[tokio::test]
+    async fn test_inputs_resume_at_position() {
+        let directory = tempfile::tempdir().unwrap();
+        let first = directory.path().join("first.csv");
+        let second = directory.path().join("second.csv");
+        std::fs::write(&first, "type,client,tx,amount\ndeposit,1,1,1\n").unwrap();
+        std::fs::write(
+            &second,
+            "type, client,tx,amount\r\ndeposit,1,2,1\r\n\r\n\"deposit\",1,3,1\r\ndeposit,1,4,1",
+        )
+        .unwrap();
+        let inputs = [first, second].map(|path| path.to_str().unwrap().to_string());
+        let rows = read_all(InputsSource::new(inputs.to_vec(), None)).await;
+        let position = rows[2].2.clone();
+        assert_eq!((position.input_index, &position.input), (1, &inputs[1]));
+
+        let resumed =
+            read_all(InputsSource::new(inputs.to_vec(), None).with_resume(position.clone())).await;
+
+        assert_eq!(resumed, rows[3..]);
+        for inputs in [
+            vec![inputs[0].clone(), inputs[0].clone()],
+            vec![inputs[0].clone()],
+        ] {
+            let mut source = InputsSource::new(inputs, None).with_resume(position.clone());
+            assert!(matches!(
+                source.next_row().await,
+                Some(Err(CliError::InputUnreadable(_)))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_writer_sink_formats() {
+        let accounts = [CsvAccountData {
+            client_id: 2,
+            available: dec!(1.5),
+            held: dec!(0.25),
+            total: dec!(1.75),
+            state: AccountStatus::Locked,
+        }];
+        let mut csv = WriterSink::new(Vec::new(), OutputFormat::Csv);
+        let mut jsonl = WriterSink::new(Vec::new(), OutputFormat::Jsonl);
+
+        csv.write_accounts(&accounts).await.unwrap();
+        jsonl.write_accounts(&accounts).await.unwrap();
+
+        assert_eq!(
+            String::from_utf8(csv.output).unwrap(),
+            "client,available,held,total,state\n2,1.5,0.25,1.75,locked\n"
+        );
+        assert_eq!(
+            String::from_utf8(jsonl.output).unwrap(),
+            "{\"client\":2,\"available\":\"1.5\",\"held\":\"0.25\",\"total\":\"1.75\",\"state\":\"locked\"}\n"
+        );
+    }
+}
@@ -0,0 +1,322 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    transactions::Timestamp,
+    transactions_processor::{RowOrigin, TransactionLogEntry},
+};
+
+struct BufferedEntry {
+    timestamp: Timestamp,
+    arrival: u64,
+    entry: TransactionLogEntry,
+}
+
+impl PartialEq for BufferedEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for BufferedEntry {}
+
+impl PartialOrd for BufferedEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BufferedEntry {
+    // rows with equal timestamps keep their arrival order
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.timestamp, self.arrival).cmp(&(other.timestamp, other.arrival))
+    }
+}
+
+// Rows waiting in a buffer, in arrival order, with what the buffer has seen so far.
+// The origin is kept next to the row as rows don't serialize it
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReorderState {
+    pending: Vec<(TransactionLogEntry, Option<RowOrigin>)>,
+    latest_timestamp: Option<Timestamp>,
+    last_emitted_timestamp: Option<Timestamp>,
+    late_records: u64,
+}
+
+pub const DEFAULT_TOLERANCE_SECONDS: Timestamp = 10;
+pub const DEFAULT_CAPACITY: usize = 65536;
+
+pub struct ReorderBuffer {
+    tolerance: Timestamp,
+    capacity: usize,
+    buffer: BinaryHeap<Reverse<BufferedEntry>>,
+    arrivals: u64,
+    latest_timestamp: Option<Timestamp>,
+    last_emitted_timestamp: Option<Timestamp>,
+    late_records: u64,
+}
+
+impl Default for ReorderBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_TOLERANCE_SECONDS, DEFAULT_CAPACITY)
+    }
+}
+
+impl ReorderBuffer {
+    pub fn new(tolerance: Timestamp, capacity: usize) -> Self {
+        Self {
+            tolerance,
+            capacity: capacity.max(1),
+            buffer: BinaryHeap::new(),
+            arrivals: 0,
+            latest_timestamp: None,
+            last_emitted_timestamp: None,
+            late_records: 0,
+        }
+    }
+
+    pub fn state(&self) -> ReorderState {
+        let mut pending = self
+            .buffer
+            .iter()
+            .map(|Reverse(buffered)| buffered)
+            .collect::<Vec<_>>();
+        pending.sort_by_key(|buffered| buffered.arrival);
+        ReorderState {
+            pending: pending
+                .into_iter()
+                .map(|buffered| (buffered.entry.clone(), buffered.entry.origin.clone()))
+                .collect(),
+            latest_timestamp: self.latest_timestamp,
+            last_emitted_timestamp: self.last_emitted_timestamp,
+            late_records: self.late_records,
+        }
+    }
+
+    // continues with the rows and timestamps of a buffer's earlier state
+    pub fn restore(&mut self, state: ReorderState) {
+        self.buffer.clear();
+        for (entry, origin) in state.pending {
+            // only rows with a timestamp are ever buffered
+            let Some(timestamp) = entry.timestamp else {
+                continue;
+            };
+            self.arrivals += 1;
+            self.buffer.push(Reverse(BufferedEntry {
+                timestamp,
+                arrival: self.arrivals,
+                entry: TransactionLogEntry { origin, ..entry },
+            }));
+        }
+        self.latest_timestamp = state.latest_timestamp;
+        self.last_emitted_timestamp = state.last_emitted_timestamp;
+        self.late_records = state.late_records;
+    }
+
+    pub fn late_records(&self) -> u64 {
+        self.late_records
+    }
+
+    // Returns the rows that are ready in timestamp order. A row older than a row already returned
+    // can't be put in order anymore and is given back as error
+    pub fn push(
+        &mut self,
+        entry: TransactionLogEntry,
+    ) -> Result<Vec<TransactionLogEntry>, TransactionLogEntry> {
+        let Some(timestamp) = entry.timestamp else {
+            // nothing to order by, keep it behind everything that came before
+            let mut ready = self.flush();
+            ready.push(entry);
+            return Ok(ready);
+        };
+        if let Some(last_emitted) = self.last_emitted_timestamp
+            && timestamp < last_emitted
+        {
+            warn!(
+                "Transaction {} is {}s behind already processed rows, out of reordering tolerance",
+                entry.transaction_id,
+                last_emitted - timestamp
+            );
+            self.late_records += 1;
+            return Err(entry);
+        }
+
+        self.arrivals += 1;
+        self.buffer.push(Reverse(BufferedEntry {
+            timestamp,
+            arrival: self.arrivals,
+            entry,
+        }));
+        let latest = self
+            .latest_timestamp
+            .map_or(timestamp, |t| t.max(timestamp));
+        self.latest_timestamp = Some(latest);
+
+        let mut ready = Vec::new();
+        while let Some(Reverse(oldest)) = self.buffer.peek() {
+            let is_settled = oldest.timestamp.saturating_add(self.tolerance) <= latest;
+            if !is_settled && self.buffer.len() <= self.capacity {
+                break;
+            }
+            ready.push(self.pop());
+        }
+        Ok(ready)
+    }
+
+    pub fn flush(&mut self) -> Vec<TransactionLogEntry> {
+        let mut ready = Vec::with_capacity(self.buffer.len());
+        while !self.buffer.is_empty() {
+            ready.push(self.pop());
+        }
+        ready
+    }
+
+    fn pop(&mut self) -> TransactionLogEntry {
+        let Reverse(oldest) = self.buffer.pop().unwrap();
+        self.last_emitted_timestamp = Some(oldest.timestamp);
+        oldest.entry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transactions::TransactionId;
+    use rust_decimal::dec;
+
+    fn entry(transaction_id: u64, timestamp: Option<Timestamp>) -> TransactionLogEntry {
+        TransactionLogEntry {
+            transaction_type: "deposit".to_string(),
+            client_id: 1,
+            transaction_id,
+            amount: Some(dec!(1)),
+            timestamp,
+            reason: None,
+            origin: None,
+        }
+    }
+
+    fn push_all(
+        buffer: &mut ReorderBuffer,
+        entries: Vec<TransactionLogEntry>,
+    ) -> Vec<TransactionId> {
+        let mut emitted = entries
+            .into_iter()
+            .flat_map(|entry| buffer.push(entry).unwrap_or_default())
+            .collect::<Vec<_>>();
+        emitted.extend(buffer.flush());
+        emitted
+            .into_iter()
+            .map(|entry| entry.transaction_id)
+            .collect()
+    }
+
+    #[test]
+    fn test_reorders_within_tolerance() {
+        let mut buffer = ReorderBuffer::new(5, 100);
+
+        let emitted = push_all(
+            &mut buffer,
+            vec![
+                entry(1, Some(100)),
+                entry(3, Some(103)),
+                entry(2, Some(101)),
+                entry(4, Some(103)),
+                entry(5, Some(110)),
+            ],
+        );
+
+        assert_eq!(emitted, vec![1, 2, 3, 4, 5]);
+        assert_eq!(buffer.late_records(), 0);
+    }
+
+    #[test]
+    fn test_emits_settled_entries_before_end() {
+        let mut buffer = ReorderBuffer::new(5, 100);
+
+        assert!(buffer.push(entry(1, Some(100))).unwrap().is_empty());
+        assert!(buffer.push(entry(2, Some(104))).unwrap().is_empty());
+        let ready = buffer.push(entry(3, Some(106))).unwrap();
+
+        assert_eq!(ready, vec![entry(1, Some(100))]);
+    }
+
+    #[test]
+    fn test_late_record_is_given_back() {
+        let mut buffer = ReorderBuffer::new(5, 100);
+        assert!(buffer.push(entry(1, Some(100))).unwrap().is_empty());
+        assert_eq!(
+            buffer.push(entry(2, Some(110))).unwrap(),
+            vec![entry(1, Some(100))]
+        );
+
+        let late = buffer.push(entry(3, Some(99)));
+        let emitted = push_all(&mut buffer, vec![entry(4, Some(111))]);
+
+        assert_eq!(late, Err(entry(3, Some(99))));
+        assert_eq!(emitted, vec![2, 4]);
+        assert_eq!(buffer.late_records(), 1);
+    }
+
+    #[test]
+    fn test_capacity_bounds_buffer() {
+        let mut buffer = ReorderBuffer::new(1000, 2);
+
+        assert!(buffer.push(entry(1, Some(102))).unwrap().is_empty());
+        assert!(buffer.push(entry(2, Some(101))).unwrap().is_empty());
+        let ready = buffer.push(entry(3, Some(103))).unwrap();
+
+        assert_eq!(ready, vec![entry(2, Some(101))]);
+    }
+
+    #[test]
+    fn test_restored_state_emits_like_the_original() {
+        let rows = vec![
+            entry(1, Some(100)),
+            entry(3, Some(103)),
+            entry(2, Some(101)),
+            entry(4, Some(99)),
+            entry(5, Some(110)),
+            entry(6, Some(104)),
+        ];
+        let mut original = ReorderBuffer::new(5, 100);
+        let emitted = push_all(&mut original, rows.clone());
+
+        let mut buffer = ReorderBuffer::new(5, 100);
+        let mut resumed_emitted = rows[..3]
+            .iter()
+            .flat_map(|row| buffer.push(row.clone()).unwrap_or_default())
+            .map(|entry| entry.transaction_id)
+            .collect::<Vec<_>>();
+        let state = serde_json::to_string(&buffer.state()).unwrap();
+        let mut resumed = ReorderBuffer::new(5, 100);
+        resumed.restore(serde_json::from_str(&state).unwrap());
+        resumed_emitted.extend(push_all(&mut resumed, rows[3..].to_vec()));
+
+        assert_eq!(resumed_emitted, emitted);
+        assert_eq!(resumed.late_records(), original.late_records());
+    }
+
+    #[test]
+    fn test_entry_without_timestamp_flushes_buffer() {
+        let mut buffer = ReorderBuffer::new(5, 100);
+
+        let emitted = push_all(
+            &mut buffer,
+            vec![
+                entry(2, Some(101)),
+                entry(1, Some(100)),
+                entry(3, None),
+                entry(4, Some(102)),
+            ],
+        );
+
+        assert_eq!(emitted, vec![1, 2, 3, 4]);
+        assert_eq!(buffer.late_records(), 0);
+    }
+}
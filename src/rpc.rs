@@ -0,0 +1,359 @@
+use std::io;
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::debug;
+
+use crate::{
+    cli::OutputArgs,
+    csv_utils::{self, CsvAccountData},
+    errors::{AccountError, TransactionHistoryError, error_code},
+    history::TransactionHistoryStorage,
+    http_api::TransactionData,
+    pipeline::{AccountSink, WriterSink},
+    shutdown::Shutdown,
+    storage::ClientId,
+    transactions::TransactionId,
+    transactions_processor::{
+        InMemoryTransactionProcessor, RowOrigin, TransactionLogEntry, TransactionProcessor,
+    },
+};
+
+const JSONRPC_VERSION: &str = "2.0";
+const RPC_INPUT_NAME: &str = "<rpc>";
+
+// error codes of the JSON-RPC 2.0 specification, engine rejections use the server error range
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const REJECTED: i64 = -32000;
+
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    // requests without an id are notifications and get no response
+    #[serde(default)]
+    id: Option<Value>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct RpcError {
+    code: i64,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+            data: None,
+        }
+    }
+
+    // the engine error code, e.g. `INSUFFICIENT_MONEY`, is passed on in `data`
+    fn rejected(code: &str, message: impl ToString) -> Self {
+        Self {
+            code: REJECTED,
+            message: message.to_string(),
+            data: Some(json!({ "code": code })),
+        }
+    }
+
+    fn from_engine(error: &(dyn std::error::Error + 'static)) -> Self {
+        Self::rejected(error_code(error), error)
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct RpcResponse {
+    jsonrpc: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+    id: Value,
+}
+
+impl RpcResponse {
+    fn new(id: Value, result: Result<Value, RpcError>) -> Self {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self {
+            jsonrpc: JSONRPC_VERSION,
+            result,
+            error,
+            id,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AccountParams {
+    client: ClientId,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransactionParams {
+    tx: TransactionId,
+}
+
+#[derive(Debug, Deserialize)]
+struct SnapshotParams {
+    output: Option<String>,
+}
+
+// Answers every request line with one response line until the input ends or the shutdown, the
+// processor keeps its state between requests. Responses are flushed right away so the embedding process can wait for them
+pub async fn serve<H, R, W>(
+    processor: &InMemoryTransactionProcessor<H>,
+    input: R,
+    mut output: W,
+    mut shutdown: Shutdown,
+) -> io::Result<()>
+where
+    H: TransactionHistoryStorage,
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut lines = input.lines();
+    let mut line_number = 0;
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => line?,
+            _ = shutdown.wait() => break,
+        };
+        let Some(line) = line else {
+            break;
+        };
+        line_number += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some(response) = handle_request(processor, &line, line_number).await else {
+            continue;
+        };
+        let mut response_line = serde_json::to_vec(&response)?;
+        response_line.push(b'\n');
+        output.write_all(&response_line).await?;
+        output.flush().await?;
+    }
+    Ok(())
+}
+
+// One request line, notifications are processed without a response
+pub async fn handle_request<H: TransactionHistoryStorage>(
+    processor: &InMemoryTransactionProcessor<H>,
+    line: &str,
+    line_number: u64,
+) -> Option<RpcResponse> {
+    let value = match serde_json::from_str::<Value>(line) {
+        Ok(value) => value,
+        Err(e) => {
+            return Some(RpcResponse::new(
+                Value::Null,
+                Err(RpcError::new(PARSE_ERROR, e)),
+            ));
+        }
+    };
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    let request = match serde_json::from_value::<RpcRequest>(value) {
+        Ok(request) if request.jsonrpc == JSONRPC_VERSION => request,
+        Ok(request) => {
+            return Some(RpcResponse::new(
+                id,
+                Err(RpcError::new(
+                    INVALID_REQUEST,
+                    format!("Unsupported JSON-RPC version `{}`", request.jsonrpc),
+                )),
+            ));
+        }
+        Err(e) => {
+            return Some(RpcResponse::new(id, Err(RpcError::new(INVALID_REQUEST, e))));
+        }
+    };
+    debug!("RPC request `{}` on line {line_number}", request.method);
+    let result = call(processor, &request.method, request.params, line_number).await;
+    request.id.map(|id| RpcResponse::new(id, result))
+}
+
+async fn call<H: TransactionHistoryStorage>(
+    processor: &InMemoryTransactionProcessor<H>,
+    method: &str,
+    params: Value,
+    line_number: u64,
+) -> Result<Value, RpcError> {
+    match method {
+        "submit" => {
+            let transaction_entry = TransactionLogEntry {
+                origin: Some(RowOrigin {
+                    input: RPC_INPUT_NAME.into(),
+                    line: line_number,
+                }),
+                ..parse_params::<TransactionLogEntry>(params)?
+            };
+            let transaction_id = transaction_entry.transaction_id;
+            processor
+                .process(transaction_entry)
+                .map_err(|e| RpcError::from_engine(e.as_ref()))?;
+            Ok(json!({ "tx": transaction_id, "status": "accepted" }))
+        }
+        "account" => {
+            let AccountParams { client } = parse_params(params)?;
+            csv_utils::account_summary(processor.get_accounts_storage(), client)
+                .map(to_value)
+                .ok_or_else(|| RpcError::from_engine(&AccountError::AccountNotFound))?
+        }
+        "accounts" => to_value(sorted_accounts(processor)),
+        "transaction" => {
+            let TransactionParams { tx } = parse_params(params)?;
+            processor
+                .get_history()
+                .find_transaction(tx)
+                .map(|transaction_info| to_value(TransactionData::from(transaction_info)))
+                .ok_or_else(|| {
+                    RpcError::from_engine(&TransactionHistoryError::UnknownTransaction)
+                })?
+        }
+        "snapshot" => {
+            let output = parse_params::<Option<SnapshotParams>>(params)?.and_then(|p| p.output);
+            snapshot(processor, output).await
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Unknown method `{method}`"),
+        )),
+    }
+}
+
+// Without an output the accounts are returned, stdout is taken by the responses
+async fn snapshot<H: TransactionHistoryStorage>(
+    processor: &InMemoryTransactionProcessor<H>,
+    output: Option<String>,
+) -> Result<Value, RpcError> {
+    let accounts = sorted_accounts(processor);
+    let Some(output) = output else {
+        return to_value(accounts);
+    };
+    let output_args = OutputArgs {
+        output: Some(output),
+        format: None,
+    };
+    let mut sink = WriterSink::open(output_args.output.as_deref(), output_args.format())
+        .await
+        .map_err(|e| RpcError::rejected("OUTPUT_UNWRITABLE", e))?;
+    sink.write_accounts(&accounts)
+        .await
+        .map_err(|e| RpcError::rejected("OUTPUT_UNWRITABLE", e))?;
+    Ok(json!({ "output": output_args.output, "accounts": accounts.len() }))
+}
+
+fn sorted_accounts<H: TransactionHistoryStorage>(
+    processor: &InMemoryTransactionProcessor<H>,
+) -> Vec<CsvAccountData> {
+    let mut accounts = csv_utils::account_summaries(processor.get_accounts_storage());
+    accounts.sort_by_key(|account| account.client_id);
+    accounts
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e))
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::new(REJECTED, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use tempfile::NamedTempFile;
+
+    async fn request(processor: &InMemoryTransactionProcessor, line: &str) -> Value {
+        serde_json::to_value(handle_request(processor, line, 1).await.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_session() {
+        let output = NamedTempFile::with_suffix(".json").unwrap();
+        let output_path = output.path().to_str().unwrap();
+        let snapshot_request = format!(
+            r#"{{"jsonrpc":"2.0","method":"snapshot","params":{{"output":"{output_path}"}},"id":4}}"#
+        );
+        let requests = [
+            r#"{"jsonrpc":"2.0","method":"submit","params":{"type":"deposit","client":1,"tx":1,"amount":"1.25"},"id":1}"#,
+            r#"{"jsonrpc":"2.0","method":"submit","params":{"type":"dispute","client":1,"tx":1}}"#,
+            r#"{"jsonrpc":"2.0","method":"submit","params":{"type":"withdrawal","client":1,"tx":2,"amount":1},"id":"w"}"#,
+            "",
+            r#"{"jsonrpc":"2.0","method":"account","params":{"client":1},"id":2}"#,
+            r#"{"jsonrpc":"2.0","method":"transaction","params":{"tx":1},"id":3}"#,
+            &snapshot_request,
+        ]
+        .join("\n");
+        let processor = InMemoryTransactionProcessor::new();
+        let mut responses = Vec::new();
+
+        serve(
+            &processor,
+            requests.as_bytes(),
+            &mut responses,
+            Shutdown::never(),
+        )
+        .await
+        .unwrap();
+
+        let responses = String::from_utf8(responses).unwrap();
+        let responses = responses
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            responses,
+            vec![
+                json!({"jsonrpc": "2.0", "result": {"tx": 1, "status": "accepted"}, "id": 1}),
+                json!({"jsonrpc": "2.0", "error": {"code": REJECTED, "message": "Insufficient money", "data": {"code": "INSUFFICIENT_MONEY"}}, "id": "w"}),
+                json!({"jsonrpc": "2.0", "result": {"client": 1, "available": "0.00", "held": "1.25", "total": "1.25", "state": "active"}, "id": 2}),
+                json!({"jsonrpc": "2.0", "result": {"client": 1, "tx": 1, "type": "deposit", "amount": "1.25", "status": "disputed", "sequence_number": 1, "timestamp": null, "reason": null}, "id": 3}),
+                json!({"jsonrpc": "2.0", "result": {"output": output_path, "accounts": 1}, "id": 4}),
+            ]
+        );
+        let snapshot = std::fs::read_to_string(output.path()).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&snapshot).unwrap(),
+            json!([{"client": 1, "available": "0.00", "held": "1.25", "total": "1.25", "state": "active"}])
+        );
+    }
+
+    #[rstest]
+    #[case("{", PARSE_ERROR)]
+    #[case(r#"{"jsonrpc":"1.0","method":"accounts","id":1}"#, INVALID_REQUEST)]
+    #[case(r#"{"jsonrpc":"2.0","id":1}"#, INVALID_REQUEST)]
+    #[case(r#"{"jsonrpc":"2.0","method":"reset","id":1}"#, METHOD_NOT_FOUND)]
+    #[case(
+        r#"{"jsonrpc":"2.0","method":"account","params":{"client":"x"},"id":1}"#,
+        INVALID_PARAMS
+    )]
+    #[case(
+        r#"{"jsonrpc":"2.0","method":"transaction","params":{"tx":7},"id":1}"#,
+        REJECTED
+    )]
+    #[tokio::test]
+    async fn test_errors(#[case] line: &str, #[case] code: i64) {
+        let processor = InMemoryTransactionProcessor::new();
+
+        let response = request(&processor, line).await;
+
+        assert_eq!(response["error"]["code"], code);
+        assert!(response.get("result").is_none());
+    }
+}
@@ -0,0 +1,380 @@
+use std::{error::Error, io, net::SocketAddr, sync::Arc};
+
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{info, warn};
+
+use crate::{
+    cli::InputFormat,
+    csv_utils::{self, CsvAccountData},
+    errors::{AccountError, error_code},
+    history::TransactionHistoryStorage,
+    storage::ClientId,
+    transactions_processor::{
+        InMemoryTransactionProcessor, RowOrigin, TransactionLogEntry, TransactionProcessor,
+    },
+};
+
+const QUERY_COMMAND: &str = "QUERY";
+// longer lines are skipped and rejected with LINE_TOO_LONG, so a client can't grow the line buffer unbounded
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+const LINE_TOO_LONG: &str = "LINE_TOO_LONG";
+// column order of CSV lines, they are sent without a header row
+const CSV_COLUMNS: [&str; 6] = ["type", "client", "tx", "amount", "timestamp", "reason"];
+
+#[derive(Debug, Clone, Default)]
+pub struct ServerOptions {
+    // lines starting with `{` are JSON, others CSV, unless the format is given
+    pub line_format: Option<InputFormat>,
+    // the connection is closed after the first rejected line
+    pub fail_fast: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Response {
+    Accepted,
+    Account(CsvAccountData),
+    Rejected { code: String, message: String },
+}
+
+impl Response {
+    fn rejected(code: &str, message: impl ToString) -> Self {
+        Response::Rejected {
+            code: code.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    fn to_line(&self) -> Result<String, Box<dyn Error>> {
+        match self {
+            Response::Accepted => Ok("OK\n".to_string()),
+            // same columns as the CSV output, without the header row
+            Response::Account(account) => {
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(b"ACCOUNT ".to_vec());
+                writer.serialize(account)?;
+                Ok(String::from_utf8(writer.into_inner()?)?)
+            }
+            Response::Rejected { code, message } => Ok(format!("ERR {code} {message}\n")),
+        }
+    }
+}
+
+// Accepts connections until the listener fails, every connection is served by its own task.
+// All connections feed the same processor, so rows are sequenced in the order they arrive
+pub async fn serve<H: TransactionHistoryStorage + Send + Sync + 'static>(
+    listener: TcpListener,
+    processor: Arc<InMemoryTransactionProcessor<H>>,
+    options: ServerOptions,
+) -> io::Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        info!("Connection from {peer}");
+        let processor = processor.clone();
+        let options = options.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, peer, &processor, &options).await {
+                warn!("Connection from {peer} failed: {e}");
+            }
+            info!("Connection from {peer} closed");
+        });
+    }
+}
+
+async fn handle_connection<H: TransactionHistoryStorage>(
+    stream: TcpStream,
+    peer: SocketAddr,
+    processor: &InMemoryTransactionProcessor<H>,
+    options: &ServerOptions,
+) -> io::Result<()> {
+    let peer_name = Arc::<str>::from(peer.to_string());
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut buffer = Vec::new();
+    let mut line_number = 0;
+    loop {
+        buffer.clear();
+        // one byte over the limit tells a line of exactly MAX_LINE_LENGTH from a longer one
+        let read = (&mut reader)
+            .take(MAX_LINE_LENGTH as u64 + 1)
+            .read_until(b'\n', &mut buffer)
+            .await?;
+        if read == 0 {
+            break;
+        }
+        line_number += 1;
+        if buffer.last() == Some(&b'\n') {
+            buffer.pop();
+        } else if buffer.len() > MAX_LINE_LENGTH {
+            skip_line(&mut reader).await?;
+            let response = Response::rejected(
+                LINE_TOO_LONG,
+                format!("Line is longer than {MAX_LINE_LENGTH} bytes"),
+            );
+            writer
+                .write_all(response_line(&response)?.as_bytes())
+                .await?;
+            if options.fail_fast {
+                break;
+            }
+            continue;
+        }
+        let line = std::str::from_utf8(&buffer)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let origin = RowOrigin {
+            input: peer_name.clone(),
+            line: line_number,
+        };
+        let response = handle_line(processor, line, origin, options);
+        writer
+            .write_all(response_line(&response)?.as_bytes())
+            .await?;
+        if options.fail_fast && matches!(response, Response::Rejected { .. }) {
+            break;
+        }
+    }
+    writer.shutdown().await
+}
+
+// Drops the rest of a too long line without buffering it
+async fn skip_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> io::Result<()> {
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            return Ok(());
+        }
+        match available.iter().position(|byte| *byte == b'\n') {
+            Some(end) => {
+                reader.consume(end + 1);
+                return Ok(());
+            }
+            None => {
+                let skipped = available.len();
+                reader.consume(skipped);
+            }
+        }
+    }
+}
+
+fn response_line(response: &Response) -> io::Result<String> {
+    response
+        .to_line()
+        .map_err(|e| io::Error::other(e.to_string()))
+}
+
+// One request line: `QUERY <client>` or a transaction as CSV or JSON
+pub fn handle_line<H: TransactionHistoryStorage>(
+    processor: &InMemoryTransactionProcessor<H>,
+    line: &str,
+    origin: RowOrigin,
+    options: &ServerOptions,
+) -> Response {
+    let line = line.trim();
+    if let Some(client) = line.strip_prefix(QUERY_COMMAND) {
+        let Ok(client_id) = client.trim().parse::<ClientId>() else {
+            return Response::rejected(
+                "INVALID_LINE",
+                format!("Invalid client `{}`", client.trim()),
+            );
+        };
+        return match csv_utils::account_summary(processor.get_accounts_storage(), client_id) {
+            Some(account) => Response::Account(account),
+            None => Response::rejected(
+                error_code(&AccountError::AccountNotFound),
+                AccountError::AccountNotFound,
+            ),
+        };
+    }
+
+    let line_format = options.line_format.unwrap_or(if line.starts_with('{') {
+        InputFormat::Jsonl
+    } else {
+        InputFormat::Csv
+    });
+    let transaction_entry = match parse_line(line, line_format) {
+        Ok(transaction_entry) => TransactionLogEntry {
+            origin: Some(origin),
+            ..transaction_entry
+        },
+        Err(e) => return Response::rejected("INVALID_LINE", e),
+    };
+    match processor.process(transaction_entry) {
+        Ok(()) => Response::Accepted,
+        Err(e) => Response::rejected(error_code(e.as_ref()), e),
+    }
+}
+
+fn parse_line(line: &str, line_format: InputFormat) -> Result<TransactionLogEntry, Box<dyn Error>> {
+    match line_format {
+        InputFormat::Jsonl => Ok(serde_json::from_str(line)?),
+        InputFormat::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .trim(csv::Trim::All)
+                .from_reader(line.as_bytes());
+            let headers = csv::StringRecord::from(&CSV_COLUMNS[..]);
+            match reader.records().next() {
+                Some(record) => Ok(record?.deserialize(Some(&headers))?),
+                None => Err("Empty line".into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_concurrent_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let processor = Arc::new(InMemoryTransactionProcessor::new());
+        tokio::spawn(serve(listener, processor, ServerOptions::default()));
+
+        let send = |requests: &'static str| async move {
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            stream.write_all(requests.as_bytes()).await.unwrap();
+            stream.shutdown().await.unwrap();
+            let mut lines = BufReader::new(stream).lines();
+            let mut responses = Vec::new();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                responses.push(line);
+            }
+            responses
+        };
+
+        let (first, second) = tokio::join!(
+            send("deposit,1,1,10\nwithdrawal,1,2,50\n\ndeposit,x,3,1\n"),
+            send("{\"type\":\"deposit\",\"client\":2,\"tx\":3,\"amount\":\"2.5\"}\nQUERY x\n"),
+        );
+        assert_eq!(
+            first,
+            vec![
+                "OK",
+                "ERR INSUFFICIENT_MONEY Insufficient money",
+                "ERR INVALID_LINE CSV deserialize error: record 0 (line: 1, byte: 0): field 1: invalid digit found in string",
+            ]
+        );
+        assert_eq!(second, vec!["OK", "ERR INVALID_LINE Invalid client `x`"]);
+
+        let queries = send("QUERY 1\nQUERY 2\nQUERY 3\n").await;
+        assert_eq!(
+            queries,
+            vec![
+                "ACCOUNT 1,10,0,10,active",
+                "ACCOUNT 2,2.5,0,2.5,active",
+                "ERR ACCOUNT_NOT_FOUND Account not found",
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_disputes_of_same_transaction() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let processor = Arc::new(InMemoryTransactionProcessor::new());
+        for transaction_id in 1..=200 {
+            processor
+                .process(TransactionLogEntry {
+                    transaction_type: "deposit".to_string(),
+                    client_id: 1,
+                    transaction_id,
+                    amount: Some(rust_decimal::Decimal::ONE),
+                    timestamp: None,
+                    reason: None,
+                    origin: None,
+                })
+                .unwrap();
+        }
+        tokio::spawn(serve(listener, processor.clone(), ServerOptions::default()));
+        let disputes = (1..=200)
+            .map(|transaction_id| format!("dispute,1,{transaction_id},\n"))
+            .collect::<String>();
+
+        let send = |requests: String| async move {
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            stream.write_all(requests.as_bytes()).await.unwrap();
+            stream.shutdown().await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response.lines().filter(|line| *line == "OK").count()
+        };
+        let connections = (0..8)
+            .map(|_| tokio::spawn(send(disputes.clone())))
+            .collect::<Vec<_>>();
+        let mut accepted = 0;
+        for connection in connections {
+            accepted += connection.await.unwrap();
+        }
+
+        assert_eq!(accepted, 200);
+        let accounts = processor.get_accounts_storage().accounts.read().unwrap();
+        assert_eq!(
+            accounts[&1].held_balance(),
+            rust_decimal::Decimal::from(200)
+        );
+        assert_eq!(
+            accounts[&1].available_balance(),
+            rust_decimal::Decimal::ZERO
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fail_fast_closes_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let options = ServerOptions {
+            line_format: Some(InputFormat::Csv),
+            fail_fast: true,
+        };
+        tokio::spawn(serve(
+            listener,
+            Arc::new(InMemoryTransactionProcessor::new()),
+            options,
+        ));
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(b"{\"type\":\"deposit\"}\ndeposit,1,1,1\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("ERR INVALID_LINE "));
+        assert_eq!(response.lines().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_too_long_line_is_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(
+            listener,
+            Arc::new(InMemoryTransactionProcessor::new()),
+            ServerOptions::default(),
+        ));
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let mut requests = format!("deposit,1,1,1{}\n", " ".repeat(MAX_LINE_LENGTH - 13));
+        requests.push_str(&"x".repeat(MAX_LINE_LENGTH + 1));
+        requests.push_str("\ndeposit,1,2,1\n");
+        stream.write_all(requests.as_bytes()).await.unwrap();
+        stream.shutdown().await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert_eq!(
+            response,
+            format!("OK\nERR LINE_TOO_LONG Line is longer than {MAX_LINE_LENGTH} bytes\nOK\n")
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use tokio::sync::watch;
+use tracing::{error, warn};
+
+// Exit code of a process killed by SIGINT, used when a second signal ends the process right away
+const FORCED_EXIT_CODE: i32 = 130;
+
+// Fires once on the first SIGINT or SIGTERM, clones share the same state
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    receiver: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    // The sender triggers the shutdown, dropping it without sending means it never happens
+    pub fn new() -> (watch::Sender<bool>, Self) {
+        let (sender, receiver) = watch::channel(false);
+        (sender, Self { receiver })
+    }
+
+    pub fn never() -> Self {
+        Self::new().1
+    }
+
+    // Installs the signal handlers right away, a second signal exits without waiting for the shutdown.
+    // Without signal handlers the shutdown never happens and signals keep their default behaviour
+    pub fn on_signals() -> Self {
+        let (sender, shutdown) = Self::new();
+        let mut signals = match Signals::new() {
+            Ok(signals) => signals,
+            Err(e) => {
+                warn!("Can't install signal handlers, interrupting stops without output: {e}");
+                return shutdown;
+            }
+        };
+        tokio::spawn(async move {
+            signals.recv().await;
+            warn!("Shutting down, interrupt again to exit right away");
+            sender.send_replace(true);
+            signals.recv().await;
+            error!("Interrupted again, exiting without output");
+            std::process::exit(FORCED_EXIT_CODE);
+        });
+        shutdown
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    pub async fn wait(&mut self) {
+        if self
+            .receiver
+            .wait_for(|triggered| *triggered)
+            .await
+            .is_err()
+        {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+#[cfg(unix)]
+struct Signals {
+    interrupt: tokio::signal::unix::Signal,
+    terminate: tokio::signal::unix::Signal,
+}
+
+#[cfg(unix)]
+impl Signals {
+    fn new() -> std::io::Result<Self> {
+        use tokio::signal::unix::{SignalKind, signal};
+        Ok(Self {
+            interrupt: signal(SignalKind::interrupt())?,
+            terminate: signal(SignalKind::terminate())?,
+        })
+    }
+
+    async fn recv(&mut self) {
+        tokio::select! {
+            _ = self.interrupt.recv() => {}
+            _ = self.terminate.recv() => {}
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct Signals;
+
+#[cfg(not(unix))]
+impl Signals {
+    fn new() -> std::io::Result<Self> {
+        Ok(Self)
+    }
+
+    async fn recv(&mut self) {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_wait() {
+        let (sender, mut shutdown) = Shutdown::new();
+        let mut clone = shutdown.clone();
+        assert!(!shutdown.is_triggered());
+
+        sender.send_replace(true);
+        shutdown.wait().await;
+        clone.wait().await;
+        assert!(clone.is_triggered());
+
+        let mut never = Shutdown::never();
+        let waited = tokio::time::timeout(Duration::from_millis(20), never.wait()).await;
+        assert!(waited.is_err());
+    }
+}
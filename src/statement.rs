@@ -0,0 +1,242 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+
+use rust_decimal::Decimal;
+
+use crate::{
+    dispute_lifecycle::TransitionTable,
+    history::TransactionHistoryStorage,
+    storage::ClientId,
+    transactions::{SequenceNumber, Timestamp, TransactionId},
+    transactions_processor::{TransactionInfo, TransactionInfoType, TransactionStatus},
+};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct StatementRow {
+    pub client_id: ClientId,
+    pub transaction_id: TransactionId,
+    pub sequence_number: SequenceNumber,
+    pub timestamp: Option<Timestamp>,
+    pub transaction_type: TransactionInfoType,
+    // status of the transaction after this row, `WithoutDisputes` for the deposit or withdrawal itself
+    pub status: TransactionStatus,
+    pub amount: Decimal,
+    // balances right after this row
+    pub available: Decimal,
+    pub held: Decimal,
+}
+
+// what a transaction in a given status adds to (available, held), no matter how it got there
+fn balance_contribution(
+    transaction_info: &TransactionInfo,
+    status: TransactionStatus,
+    transitions: &TransitionTable,
+) -> (Decimal, Decimal) {
+    let amount = transaction_info.amount;
+    let is_held = transitions.holds_funds(status);
+    match (transaction_info.transaction_type, status, is_held) {
+        (TransactionInfoType::Deposit, _, true) => (Decimal::ZERO, amount),
+        (TransactionInfoType::Deposit, TransactionStatus::Chargebacked, false) => {
+            (Decimal::ZERO, Decimal::ZERO)
+        }
+        (TransactionInfoType::Deposit, _, false) => (amount, Decimal::ZERO),
+        (TransactionInfoType::Withdrawal, _, true) => (-amount, amount),
+        (
+            TransactionInfoType::Withdrawal,
+            TransactionStatus::Resolved | TransactionStatus::Represented,
+            false,
+        ) => (Decimal::ZERO, Decimal::ZERO),
+        (TransactionInfoType::Withdrawal, _, false) => (-amount, Decimal::ZERO),
+    }
+}
+
+// Rebuilds client balances from the history alone, rows that were rejected or already
+// dropped from the history are not part of the statement
+pub fn statement<H: TransactionHistoryStorage>(
+    history: &H,
+    client_id: ClientId,
+) -> Result<Vec<StatementRow>, Box<dyn Error>> {
+    let transactions = history.client_transactions(client_id)?;
+    let transaction_ids = transactions
+        .iter()
+        .map(|transaction_info| transaction_info.transaction_id)
+        .collect::<HashSet<_>>();
+    let status_changes = history
+        .status_changes(None)?
+        .into_iter()
+        .filter(|status_change| transaction_ids.contains(&status_change.transaction_id));
+
+    let mut events = transactions
+        .iter()
+        .map(|transaction_info| {
+            (
+                transaction_info.sequence_number,
+                transaction_info.timestamp,
+                transaction_info.transaction_id,
+                TransactionStatus::WithoutDisputes,
+            )
+        })
+        .chain(status_changes.map(|status_change| {
+            (
+                status_change.sequence_number,
+                status_change.timestamp,
+                status_change.transaction_id,
+                status_change.new_status,
+            )
+        }))
+        .collect::<Vec<_>>();
+    events.sort_by_key(|(sequence_number, ..)| *sequence_number);
+
+    let transactions = transactions
+        .into_iter()
+        .map(|transaction_info| (transaction_info.transaction_id, transaction_info))
+        .collect::<HashMap<_, _>>();
+    let transitions = history.transitions();
+    let mut statuses = HashMap::new();
+    let (mut available, mut held) = (Decimal::ZERO, Decimal::ZERO);
+    let mut rows = Vec::with_capacity(events.len());
+    for (sequence_number, timestamp, transaction_id, new_status) in events {
+        let transaction_info = &transactions[&transaction_id];
+        if let Some(previous_status) = statuses.insert(transaction_id, new_status) {
+            let (previous_available, previous_held) =
+                balance_contribution(transaction_info, previous_status, transitions);
+            available -= previous_available;
+            held -= previous_held;
+        }
+        let (new_available, new_held) =
+            balance_contribution(transaction_info, new_status, transitions);
+        available += new_available;
+        held += new_held;
+        rows.push(StatementRow {
+            client_id,
+            transaction_id,
+            sequence_number,
+            timestamp,
+            transaction_type: transaction_info.transaction_type,
+            status: new_status,
+            amount: transaction_info.amount,
+            available,
+            held,
+        });
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dispute_lifecycle::HoldPolicy,
+        history::InMemoryTransactionStorage,
+        storage::InMemoryAccountsStorage,
+        tiered_history::TieredTransactionStorage,
+        transactions_processor::{
+            InMemoryTransactionProcessor, TransactionLogEntry, TransactionProcessor,
+        },
+    };
+    use rstest::rstest;
+    use rust_decimal::dec;
+
+    fn process_all<H: TransactionHistoryStorage>(
+        processor: &InMemoryTransactionProcessor<H>,
+        rows: &[(&str, ClientId, TransactionId, Option<Decimal>)],
+    ) {
+        for (transaction_type, client_id, transaction_id, amount) in rows {
+            let _result = processor.process(TransactionLogEntry {
+                transaction_type: transaction_type.to_string(),
+                client_id: *client_id,
+                transaction_id: *transaction_id,
+                amount: *amount,
+                timestamp: None,
+                reason: None,
+                origin: None,
+            });
+        }
+    }
+
+    const ROWS: [(&str, ClientId, TransactionId, Option<Decimal>); 9] = [
+        ("deposit", 1, 1, Some(dec!(10))),
+        ("deposit", 2, 2, Some(dec!(7))),
+        ("withdrawal", 1, 3, Some(dec!(4))),
+        ("deposit", 1, 4, Some(dec!(5))),
+        ("dispute", 1, 1, None),
+        ("dispute", 1, 3, None),
+        ("resolve", 1, 3, None),
+        ("prearbitration", 1, 1, None),
+        ("chargeback", 1, 1, None),
+    ];
+
+    #[test]
+    fn test_statement_running_balances() {
+        let processor = InMemoryTransactionProcessor::new();
+        process_all(&processor, &ROWS);
+
+        let rows = statement(processor.get_history(), 1).unwrap();
+
+        let lines = rows
+            .iter()
+            .map(|row| (row.transaction_id, row.status, row.available, row.held))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                (1, TransactionStatus::WithoutDisputes, dec!(10), dec!(0)),
+                (3, TransactionStatus::WithoutDisputes, dec!(6), dec!(0)),
+                (4, TransactionStatus::WithoutDisputes, dec!(11), dec!(0)),
+                (1, TransactionStatus::Disputed, dec!(1), dec!(10)),
+                (3, TransactionStatus::Disputed, dec!(1), dec!(14)),
+                (3, TransactionStatus::Resolved, dec!(5), dec!(10)),
+                (1, TransactionStatus::PreArbitration, dec!(5), dec!(10)),
+                (1, TransactionStatus::Chargebacked, dec!(5), dec!(0)),
+            ]
+        );
+        assert!(rows.iter().all(|row| row.client_id == 1));
+        assert_eq!(rows[1].transaction_type, TransactionInfoType::Withdrawal);
+        let account = processor.get_accounts_storage();
+        assert_eq!(account.get_balance(1), Some(rows.last().unwrap().available));
+    }
+
+    #[rstest]
+    #[case(HoldPolicy::Hold)]
+    #[case(HoldPolicy::Release)]
+    fn test_statement_matches_account_balances(#[case] hold_policy: HoldPolicy) {
+        let transitions = TransitionTable::default()
+            .with_hold_policy(TransactionStatus::PreArbitration, hold_policy);
+        let processor = InMemoryTransactionProcessor::with_storages(
+            InMemoryAccountsStorage::new(),
+            TieredTransactionStorage::new(0)
+                .unwrap()
+                .with_transitions(transitions),
+        );
+        process_all(&processor, &ROWS);
+        process_all(
+            &processor,
+            &[
+                ("withdrawal", 2, 5, Some(dec!(3))),
+                ("dispute", 2, 2, None),
+                ("prearbitration", 2, 2, None),
+                ("dispute", 2, 5, None),
+                ("prearbitration", 2, 5, None),
+                ("arbitration", 2, 5, None),
+            ],
+        );
+
+        for client_id in [1, 2] {
+            let rows = statement(processor.get_history(), client_id).unwrap();
+            let last_row = rows.last().unwrap();
+            let accounts = processor.get_accounts_storage().accounts.read().unwrap();
+            let account = accounts.get(&client_id).unwrap();
+            assert_eq!(last_row.available, account.available_balance());
+            assert_eq!(last_row.held, account.held_balance());
+        }
+    }
+
+    #[test]
+    fn test_statement_of_unknown_client_is_empty() {
+        let history = InMemoryTransactionStorage::new();
+
+        assert!(statement(&history, 1).unwrap().is_empty());
+    }
+}
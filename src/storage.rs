@@ -4,15 +4,90 @@ use std::{collections::HashMap, error::Error};
 use tracing::{error, warn};
 
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 
 use crate::errors::AccountError;
 
 pub type ClientId = u16;
 
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountStatus {
+    PendingVerification,
+    Active,
+    Frozen,
+    Locked,
+    Closed,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum AccountOperation {
+    Deposit,
+    Withdraw,
+    // moving funds into held balance when a dispute is opened
+    Hold,
+    // moving funds out of held balance when a dispute is resolved or charged back
+    Release,
+    // returning charged back funds after a successful representment
+    Restore,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum RepresentmentPolicy {
+    #[default]
+    KeepLocked,
+    Unlock,
+}
+
+impl AccountStatus {
+    fn is_transition_available(self, new_status: &AccountStatus) -> bool {
+        matches!(
+            (self, new_status),
+            (
+                AccountStatus::PendingVerification,
+                AccountStatus::Active | AccountStatus::Locked | AccountStatus::Closed
+            ) | (
+                AccountStatus::Active,
+                AccountStatus::Frozen | AccountStatus::Locked | AccountStatus::Closed
+            ) | (
+                AccountStatus::Frozen,
+                AccountStatus::Active | AccountStatus::Locked
+            )
+        )
+    }
+
+    pub fn make_transition(self, new_status: AccountStatus) -> Result<AccountStatus, AccountError> {
+        if self.is_transition_available(&new_status) {
+            Ok(new_status)
+        } else {
+            Err(AccountError::InvalidStatusTransition)
+        }
+    }
+
+    pub fn check_operation(self, operation: AccountOperation) -> Result<(), AccountError> {
+        match (self, operation) {
+            (AccountStatus::Active, _) => Ok(()),
+            (
+                AccountStatus::PendingVerification | AccountStatus::Frozen | AccountStatus::Locked,
+                AccountOperation::Restore,
+            ) => Ok(()),
+            (AccountStatus::PendingVerification, AccountOperation::Withdraw) => {
+                Err(AccountError::AccountNotVerified)
+            }
+            (AccountStatus::PendingVerification, _) => Ok(()),
+            (AccountStatus::Frozen, AccountOperation::Hold | AccountOperation::Release) => Ok(()),
+            (AccountStatus::Frozen, _) => Err(AccountError::AccountFrozen),
+            (AccountStatus::Locked, _) => Err(AccountError::AccountLocked),
+            (AccountStatus::Closed, _) => Err(AccountError::AccountClosed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserAccount {
     available_amount: Decimal,
     held_amount: Decimal,
-    locked: bool,
+    status: AccountStatus,
 }
 
 impl UserAccount {
@@ -29,7 +104,22 @@ impl UserAccount {
     }
 
     pub fn is_locked(&self) -> bool {
-        self.locked
+        self.status == AccountStatus::Locked
+    }
+
+    pub fn status(&self) -> AccountStatus {
+        self.status
+    }
+
+    fn check_operation(&self, operation: AccountOperation) -> Result<(), Box<dyn Error>> {
+        if let Err(e) = self.status.check_operation(operation) {
+            warn!(
+                "Trying to perform {operation:?} on account in {:?} status",
+                self.status
+            );
+            return Err(Box::new(e));
+        }
+        Ok(())
     }
 }
 
@@ -38,7 +128,7 @@ impl Default for UserAccount {
         UserAccount {
             available_amount: Decimal::ZERO,
             held_amount: Decimal::ZERO,
-            locked: false,
+            status: AccountStatus::Active,
         }
     }
 }
@@ -50,10 +140,21 @@ pub trait AccountStorage {
     fn hold_money(&self, user_id: ClientId, amount: Decimal) -> Result<(), Box<dyn Error>>;
     fn unhold_money(&self, user_id: ClientId, amount: Decimal) -> Result<(), Box<dyn Error>>;
     fn block_account(&self, user_id: ClientId) -> Result<(), Box<dyn Error>>;
+    fn open_account(&self, user_id: ClientId) -> Result<(), Box<dyn Error>>;
+    fn update_account_status(
+        &self,
+        user_id: ClientId,
+        new_status: AccountStatus,
+    ) -> Result<(), Box<dyn Error>>;
+    fn add_held_money(&self, user_id: ClientId, amount: Decimal) -> Result<(), Box<dyn Error>>;
+    fn charge_back_money(&self, user_id: ClientId, amount: Decimal) -> Result<(), Box<dyn Error>>;
+    fn reverse_chargeback(&self, user_id: ClientId, amount: Decimal) -> Result<(), Box<dyn Error>>;
 }
 
 pub struct InMemoryAccountsStorage {
     pub accounts: RwLock<HashMap<ClientId, UserAccount>>,
+    strict_opening: bool,
+    representment_policy: RepresentmentPolicy,
 }
 
 impl Default for InMemoryAccountsStorage {
@@ -66,13 +167,39 @@ impl InMemoryAccountsStorage {
     pub fn new() -> Self {
         Self {
             accounts: RwLock::new(HashMap::new()),
+            strict_opening: false,
+            representment_policy: RepresentmentPolicy::default(),
         }
     }
 
+    pub fn with_representment_policy(mut self, representment_policy: RepresentmentPolicy) -> Self {
+        self.representment_policy = representment_policy;
+        self
+    }
+
+    // accounts have to be opened or created up front, deposits don't create them implicitly
+    pub fn with_strict_opening(mut self) -> Self {
+        self.strict_opening = true;
+        self
+    }
+
+    pub fn snapshot(&self) -> Vec<(ClientId, UserAccount)> {
+        let storage = self.accounts.read().unwrap();
+        storage
+            .iter()
+            .map(|(client_id, account)| (*client_id, account.clone()))
+            .collect()
+    }
+
+    // replaces all accounts, e.g. with a snapshot taken by an earlier run
+    pub fn restore(&self, accounts: Vec<(ClientId, UserAccount)>) {
+        *self.accounts.write().unwrap() = accounts.into_iter().collect();
+    }
+
     pub fn is_locked(&self, user_id: ClientId) -> Option<bool> {
         let storage = self.accounts.read().unwrap();
         match storage.get(&user_id) {
-            Some(account) => Some(account.locked),
+            Some(account) => Some(account.is_locked()),
             None => {
                 warn!("Unknown account");
                 None
@@ -84,7 +211,7 @@ impl InMemoryAccountsStorage {
         let storage = self.accounts.read().unwrap();
         match storage.get(&user_id) {
             Some(account) => {
-                if account.locked {
+                if account.is_locked() {
                     warn!("Looking blocked account balance");
                 }
                 Some(account.available_amount)
@@ -95,6 +222,11 @@ impl InMemoryAccountsStorage {
             }
         }
     }
+
+    pub fn get_status(&self, user_id: ClientId) -> Option<AccountStatus> {
+        let storage = self.accounts.read().unwrap();
+        storage.get(&user_id).map(|account| account.status)
+    }
 }
 
 impl AccountStorage for InMemoryAccountsStorage {
@@ -111,19 +243,19 @@ impl AccountStorage for InMemoryAccountsStorage {
     fn add_money(&self, user_id: ClientId, amount: Decimal) -> Result<(), Box<dyn Error>> {
         let mut storage = self.accounts.write().unwrap();
         match storage.entry(user_id) {
+            Entry::Vacant(_entry) if self.strict_opening => {
+                warn!("Trying to add money to unknown account");
+                return Err(Box::new(AccountError::AccountNotFound));
+            }
             Entry::Vacant(entry) => {
                 entry.insert(UserAccount {
                     available_amount: amount,
-                    held_amount: Decimal::ZERO,
-                    locked: false,
+                    ..UserAccount::default()
                 });
             }
             Entry::Occupied(mut entry) => {
                 let account = entry.get_mut();
-                if account.locked {
-                    warn!("Trying to add money to locked account");
-                    return Err(Box::new(AccountError::AccountLocked));
-                }
+                account.check_operation(AccountOperation::Deposit)?;
                 match account.available_amount.checked_add(amount) {
                     Some(new_balance) => account.available_amount = new_balance,
                     None => {
@@ -147,10 +279,7 @@ impl AccountStorage for InMemoryAccountsStorage {
             }
             Entry::Occupied(mut entry) => {
                 let account = entry.get_mut();
-                if account.locked {
-                    warn!("Trying to withdraw money from locked account");
-                    return Err(Box::new(AccountError::AccountLocked));
-                }
+                account.check_operation(AccountOperation::Withdraw)?;
                 if account.available_amount < amount {
                     warn!("Trying to withdraw more money then account has");
                     return Err(Box::new(AccountError::InsufficientMoney));
@@ -179,10 +308,7 @@ impl AccountStorage for InMemoryAccountsStorage {
             }
             Entry::Occupied(mut entry) => {
                 let account = entry.get_mut();
-                if account.locked {
-                    warn!("Trying to hold money from locked account");
-                    return Err(Box::new(AccountError::AccountLocked));
-                }
+                account.check_operation(AccountOperation::Hold)?;
                 if account.available_amount < amount {
                     warn!("Trying to hold more money then account has");
                     return Err(Box::new(AccountError::InsufficientMoney));
@@ -221,10 +347,7 @@ impl AccountStorage for InMemoryAccountsStorage {
             }
             Entry::Occupied(mut entry) => {
                 let account = entry.get_mut();
-                if account.locked {
-                    warn!("Trying to unhold money from locked account");
-                    return Err(Box::new(AccountError::AccountLocked));
-                }
+                account.check_operation(AccountOperation::Release)?;
                 if account.held_amount < amount {
                     warn!("Trying to unhold more money then account has");
                     return Err(Box::new(AccountError::InsufficientMoney));
@@ -263,20 +386,142 @@ impl AccountStorage for InMemoryAccountsStorage {
             }
             Entry::Occupied(mut entry) => {
                 let account = entry.get_mut();
-                if account.locked {
+                if account.is_locked() {
                     warn!("Trying to block already locked account");
                     return Ok(());
                 }
-                account.locked = true;
+                account.status = account.status.make_transition(AccountStatus::Locked)?;
+            }
+        };
+        Ok(())
+    }
+
+    fn open_account(&self, user_id: ClientId) -> Result<(), Box<dyn Error>> {
+        let mut storage = self.accounts.write().unwrap();
+        match storage.entry(user_id) {
+            Entry::Vacant(entry) => {
+                entry.insert(UserAccount {
+                    status: AccountStatus::PendingVerification,
+                    ..UserAccount::default()
+                });
+            }
+            Entry::Occupied(_entry) => {
+                warn!("Trying to open account which already exists");
+                return Err(Box::new(AccountError::AccountAlreadyExists));
+            }
+        };
+        Ok(())
+    }
+
+    fn update_account_status(
+        &self,
+        user_id: ClientId,
+        new_status: AccountStatus,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut storage = self.accounts.write().unwrap();
+        match storage.entry(user_id) {
+            Entry::Vacant(_entry) => {
+                warn!("Trying to update status of unknown account");
+                return Err(Box::new(AccountError::AccountNotFound));
+            }
+            Entry::Occupied(mut entry) => {
+                let account = entry.get_mut();
+                if new_status == AccountStatus::Closed && !account.total_balance().is_zero() {
+                    warn!("Trying to close account with non-zero balance");
+                    return Err(Box::new(AccountError::NonZeroBalance));
+                }
+                account.status = account.status.make_transition(new_status)?;
             }
         };
         Ok(())
     }
+
+    fn add_held_money(&self, user_id: ClientId, amount: Decimal) -> Result<(), Box<dyn Error>> {
+        let mut storage = self.accounts.write().unwrap();
+        match storage.entry(user_id) {
+            Entry::Vacant(_entry) => {
+                warn!("Trying to add held money to unknown account");
+                return Err(Box::new(AccountError::AccountNotFound));
+            }
+            Entry::Occupied(mut entry) => {
+                let account = entry.get_mut();
+                account.check_operation(AccountOperation::Hold)?;
+                match account.held_amount.checked_add(amount) {
+                    Some(new_balance) => account.held_amount = new_balance,
+                    None => {
+                        error!(
+                            "Got balance overflow for account {user_id}, need to solve this manually"
+                        );
+                        return Err(Box::new(AccountError::BalanceOverflow));
+                    }
+                };
+            }
+        }
+        Ok(())
+    }
+
+    fn charge_back_money(&self, user_id: ClientId, amount: Decimal) -> Result<(), Box<dyn Error>> {
+        let mut storage = self.accounts.write().unwrap();
+        match storage.entry(user_id) {
+            Entry::Vacant(_entry) => {
+                warn!("Trying to charge back money from unknown account");
+                return Err(Box::new(AccountError::AccountNotFound));
+            }
+            Entry::Occupied(mut entry) => {
+                let account = entry.get_mut();
+                account.check_operation(AccountOperation::Release)?;
+                if account.held_amount < amount {
+                    warn!("Trying to charge back more money then account holds");
+                    return Err(Box::new(AccountError::InsufficientMoney));
+                }
+                match account.held_amount.checked_sub(amount) {
+                    Some(new_balance) => account.held_amount = new_balance,
+                    None => {
+                        // kind of impossible, but let it be
+                        error!(
+                            "Got balance overflow for account {user_id}, need to solve this manually"
+                        );
+                        return Err(Box::new(AccountError::BalanceOverflow));
+                    }
+                };
+            }
+        }
+        Ok(())
+    }
+
+    fn reverse_chargeback(&self, user_id: ClientId, amount: Decimal) -> Result<(), Box<dyn Error>> {
+        let mut storage = self.accounts.write().unwrap();
+        match storage.entry(user_id) {
+            Entry::Vacant(_entry) => {
+                warn!("Trying to reverse chargeback for unknown account");
+                return Err(Box::new(AccountError::AccountNotFound));
+            }
+            Entry::Occupied(mut entry) => {
+                let account = entry.get_mut();
+                account.check_operation(AccountOperation::Restore)?;
+                match account.available_amount.checked_add(amount) {
+                    Some(new_balance) => account.available_amount = new_balance,
+                    None => {
+                        error!(
+                            "Got balance overflow for account {user_id}, need to solve this manually"
+                        );
+                        return Err(Box::new(AccountError::BalanceOverflow));
+                    }
+                };
+                // locked is terminal for account operations, only a won representment may lift it
+                if account.is_locked() && self.representment_policy == RepresentmentPolicy::Unlock {
+                    account.status = AccountStatus::Active;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rstest::rstest;
     use rust_decimal::dec;
     #[test]
     fn test_create_user_successful() {
@@ -296,7 +541,7 @@ mod tests {
         assert_eq!(account.available_balance(), Decimal::ZERO);
         assert_eq!(account.held_balance(), Decimal::ZERO);
         assert_eq!(account.total_balance(), Decimal::ZERO);
-        assert!(!account.locked);
+        assert_eq!(account.status(), AccountStatus::Active);
     }
 
     #[test]
@@ -327,6 +572,36 @@ mod tests {
         assert_eq!(storage.is_locked(user_id), Some(false));
     }
 
+    #[test]
+    fn test_add_money_strict_opening_unknown_account() {
+        let storage = InMemoryAccountsStorage::new().with_strict_opening();
+        let user_id = 1;
+
+        let result = storage.add_money(user_id, dec!(10));
+
+        let error = result.unwrap_err();
+        let account_error = error.downcast_ref::<AccountError>().unwrap();
+        assert_eq!(*account_error, AccountError::AccountNotFound);
+        assert_eq!(storage.get_balance(user_id), None);
+    }
+
+    #[rstest]
+    #[case(AccountStatus::PendingVerification)]
+    #[case(AccountStatus::Active)]
+    fn test_add_money_strict_opening_known_account(#[case] status: AccountStatus) {
+        let storage = InMemoryAccountsStorage::new().with_strict_opening();
+        let user_id = 1;
+        match status {
+            AccountStatus::PendingVerification => storage.open_account(user_id).unwrap(),
+            _ => storage.create_user(user_id),
+        }
+
+        storage.add_money(user_id, dec!(10)).unwrap();
+
+        assert_eq!(storage.get_balance(user_id), Some(dec!(10)));
+        assert_eq!(storage.get_status(user_id), Some(status));
+    }
+
     #[test]
     fn test_add_money_to_existing_account() {
         let storage = InMemoryAccountsStorage::new();
@@ -355,7 +630,7 @@ mod tests {
             .unwrap()
             .get_mut(&user_id)
             .unwrap()
-            .locked = true;
+            .status = AccountStatus::Locked;
 
         let result = storage.add_money(user_id, dec!(50.00));
         assert!(result.is_err());
@@ -455,7 +730,7 @@ mod tests {
         storage.add_money(user_id, initial_amount).unwrap();
         {
             let mut accounts = storage.accounts.write().unwrap();
-            accounts.get_mut(&user_id).unwrap().locked = true;
+            accounts.get_mut(&user_id).unwrap().status = AccountStatus::Locked;
         }
         let result = storage.withdraw_money(user_id, withdraw_amount);
 
@@ -533,7 +808,7 @@ mod tests {
 
         {
             let mut accounts = storage.accounts.write().unwrap();
-            accounts.get_mut(&user_id).unwrap().locked = true;
+            accounts.get_mut(&user_id).unwrap().status = AccountStatus::Locked;
         }
 
         let result = storage.hold_money(user_id, hold_amount);
@@ -599,7 +874,7 @@ mod tests {
 
         {
             let mut accounts = storage.accounts.write().unwrap();
-            accounts.get_mut(&user_id).unwrap().locked = true;
+            accounts.get_mut(&user_id).unwrap().status = AccountStatus::Locked;
         }
 
         let result = storage.unhold_money(user_id, unhold_amount);
@@ -739,4 +1014,205 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(storage.is_locked(user_id), Some(true));
     }
+
+    #[rstest]
+    #[case(AccountStatus::PendingVerification, AccountStatus::Active, true)]
+    #[case(AccountStatus::PendingVerification, AccountStatus::Frozen, false)]
+    #[case(AccountStatus::PendingVerification, AccountStatus::Locked, true)]
+    #[case(AccountStatus::PendingVerification, AccountStatus::Closed, true)]
+    #[case(AccountStatus::Active, AccountStatus::PendingVerification, false)]
+    #[case(AccountStatus::Active, AccountStatus::Active, false)]
+    #[case(AccountStatus::Active, AccountStatus::Frozen, true)]
+    #[case(AccountStatus::Active, AccountStatus::Locked, true)]
+    #[case(AccountStatus::Active, AccountStatus::Closed, true)]
+    #[case(AccountStatus::Frozen, AccountStatus::Active, true)]
+    #[case(AccountStatus::Frozen, AccountStatus::Frozen, false)]
+    #[case(AccountStatus::Frozen, AccountStatus::Locked, true)]
+    #[case(AccountStatus::Frozen, AccountStatus::Closed, false)]
+    #[case(AccountStatus::Locked, AccountStatus::Active, false)]
+    #[case(AccountStatus::Locked, AccountStatus::Closed, false)]
+    #[case(AccountStatus::Closed, AccountStatus::Active, false)]
+    #[case(AccountStatus::Closed, AccountStatus::Locked, false)]
+    fn test_account_status_transitions(
+        #[case] from: AccountStatus,
+        #[case] to: AccountStatus,
+        #[case] should_be_valid: bool,
+    ) {
+        assert_eq!(from.is_transition_available(&to), should_be_valid);
+
+        let result = from.make_transition(to);
+
+        if should_be_valid {
+            assert_eq!(result, Ok(to));
+        } else {
+            assert_eq!(result, Err(AccountError::InvalidStatusTransition));
+        }
+    }
+
+    #[rstest]
+    #[case(AccountStatus::PendingVerification, [None, Some(AccountError::AccountNotVerified), None, None, None])]
+    #[case(AccountStatus::Active, [None, None, None, None, None])]
+    #[case(AccountStatus::Frozen, [Some(AccountError::AccountFrozen), Some(AccountError::AccountFrozen), None, None, None])]
+    #[case(AccountStatus::Locked, [Some(AccountError::AccountLocked), Some(AccountError::AccountLocked), Some(AccountError::AccountLocked), Some(AccountError::AccountLocked), None])]
+    #[case(AccountStatus::Closed, [Some(AccountError::AccountClosed), Some(AccountError::AccountClosed), Some(AccountError::AccountClosed), Some(AccountError::AccountClosed), Some(AccountError::AccountClosed)])]
+    fn test_account_status_permitted_operations(
+        #[case] status: AccountStatus,
+        #[case] expected_errors: [Option<AccountError>; 5],
+    ) {
+        let operations = [
+            AccountOperation::Deposit,
+            AccountOperation::Withdraw,
+            AccountOperation::Hold,
+            AccountOperation::Release,
+            AccountOperation::Restore,
+        ];
+
+        for (operation, expected_error) in operations.into_iter().zip(expected_errors) {
+            assert_eq!(
+                status.check_operation(operation).err(),
+                expected_error,
+                "{operation:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_open_account_pending_verification() {
+        let storage = InMemoryAccountsStorage::new();
+        let user_id = 1;
+
+        storage.open_account(user_id).unwrap();
+
+        assert_eq!(
+            storage.get_status(user_id),
+            Some(AccountStatus::PendingVerification)
+        );
+        assert_eq!(storage.get_balance(user_id), Some(Decimal::ZERO));
+
+        let result = storage.open_account(user_id);
+        let error = result.unwrap_err();
+        let account_error = error.downcast_ref::<AccountError>().unwrap();
+        assert_eq!(*account_error, AccountError::AccountAlreadyExists);
+    }
+
+    #[test]
+    fn test_frozen_account_rejects_deposit_until_activated() {
+        let storage = InMemoryAccountsStorage::new();
+        let user_id = 1;
+        storage.add_money(user_id, dec!(10)).unwrap();
+
+        storage
+            .update_account_status(user_id, AccountStatus::Frozen)
+            .unwrap();
+        let result = storage.add_money(user_id, dec!(5));
+
+        let error = result.unwrap_err();
+        let account_error = error.downcast_ref::<AccountError>().unwrap();
+        assert_eq!(*account_error, AccountError::AccountFrozen);
+
+        storage
+            .update_account_status(user_id, AccountStatus::Active)
+            .unwrap();
+        storage.add_money(user_id, dec!(5)).unwrap();
+        assert_eq!(storage.get_balance(user_id), Some(dec!(15)));
+    }
+
+    #[rstest]
+    #[case(dec!(0), dec!(0), None)]
+    #[case(dec!(1), dec!(0), Some(AccountError::NonZeroBalance))]
+    #[case(dec!(1), dec!(1), Some(AccountError::NonZeroBalance))]
+    fn test_close_account_requires_zero_balance(
+        #[case] deposit: Decimal,
+        #[case] held: Decimal,
+        #[case] expected_error: Option<AccountError>,
+    ) {
+        let storage = InMemoryAccountsStorage::new();
+        let user_id = 1;
+        storage.create_user(user_id);
+        storage.add_money(user_id, deposit).unwrap();
+        storage.hold_money(user_id, held).unwrap();
+
+        let result = storage.update_account_status(user_id, AccountStatus::Closed);
+
+        match expected_error {
+            None => {
+                assert!(result.is_ok());
+                assert_eq!(storage.get_status(user_id), Some(AccountStatus::Closed));
+            }
+            Some(expected_error) => {
+                let error = result.unwrap_err();
+                let account_error = error.downcast_ref::<AccountError>().unwrap();
+                assert_eq!(*account_error, expected_error);
+                assert_eq!(storage.get_status(user_id), Some(AccountStatus::Active));
+            }
+        }
+    }
+
+    #[test]
+    fn test_update_status_of_nonexistent_account() {
+        let storage = InMemoryAccountsStorage::new();
+
+        let result = storage.update_account_status(1, AccountStatus::Frozen);
+
+        let error = result.unwrap_err();
+        let account_error = error.downcast_ref::<AccountError>().unwrap();
+        assert_eq!(*account_error, AccountError::AccountNotFound);
+    }
+
+    #[test]
+    fn test_add_held_money_and_charge_back() {
+        let storage = InMemoryAccountsStorage::new();
+        let user_id = 1;
+        storage.add_money(user_id, dec!(10)).unwrap();
+        storage
+            .update_account_status(user_id, AccountStatus::Frozen)
+            .unwrap();
+
+        storage.add_held_money(user_id, dec!(4)).unwrap();
+        storage.charge_back_money(user_id, dec!(3)).unwrap();
+        let result = storage.charge_back_money(user_id, dec!(2));
+
+        let error = result.unwrap_err();
+        let account_error = error.downcast_ref::<AccountError>().unwrap();
+        assert_eq!(*account_error, AccountError::InsufficientMoney);
+        let accounts = storage.accounts.read().unwrap();
+        let account = accounts.get(&user_id).unwrap();
+        assert_eq!(account.available_balance(), dec!(10));
+        assert_eq!(account.held_balance(), dec!(1));
+    }
+
+    #[rstest]
+    #[case(RepresentmentPolicy::KeepLocked, AccountStatus::Locked)]
+    #[case(RepresentmentPolicy::Unlock, AccountStatus::Active)]
+    fn test_reverse_chargeback_policy(
+        #[case] representment_policy: RepresentmentPolicy,
+        #[case] expected_status: AccountStatus,
+    ) {
+        let storage =
+            InMemoryAccountsStorage::new().with_representment_policy(representment_policy);
+        let user_id = 1;
+        storage.add_money(user_id, dec!(10)).unwrap();
+        storage.block_account(user_id).unwrap();
+
+        storage.reverse_chargeback(user_id, dec!(5)).unwrap();
+
+        assert_eq!(storage.get_balance(user_id), Some(dec!(15)));
+        assert_eq!(storage.get_status(user_id), Some(expected_status));
+    }
+
+    #[test]
+    fn test_reverse_chargeback_keeps_frozen_account_frozen() {
+        let storage =
+            InMemoryAccountsStorage::new().with_representment_policy(RepresentmentPolicy::Unlock);
+        let user_id = 1;
+        storage.add_money(user_id, dec!(10)).unwrap();
+        storage
+            .update_account_status(user_id, AccountStatus::Frozen)
+            .unwrap();
+
+        storage.reverse_chargeback(user_id, dec!(5)).unwrap();
+
+        assert_eq!(storage.get_balance(user_id), Some(dec!(15)));
+        assert_eq!(storage.get_status(user_id), Some(AccountStatus::Frozen));
+    }
 }
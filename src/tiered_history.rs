@@ -0,0 +1,1125 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    mem::size_of,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use rust_decimal::Decimal;
+use tracing::{error, warn};
+
+use crate::{
+    dispute_lifecycle::TransitionTable,
+    dispute_reasons::DisputeReason,
+    errors::TransactionHistoryError,
+    history::{
+        DisputeWindow, ExpiredIds, ExpiryQueue, StatusChange, TransactionHistoryStorage,
+        requeue_expiry,
+    },
+    storage::ClientId,
+    transactions::{SequenceNumber, Timestamp, TransactionId, TransactionMeta},
+    transactions_processor::{TransactionInfo, TransactionInfoType, TransactionStatus},
+};
+
+const PACKED_RECORD_SIZE: usize = 45;
+// stored in place of a missing timestamp
+const NO_TIMESTAMP: Timestamp = Timestamp::MAX;
+const SLOT_SIZE: usize = PACKED_RECORD_SIZE + 1;
+const INITIAL_DISK_CAPACITY: u64 = 1024;
+
+const EMPTY_SLOT: u8 = 0;
+const OCCUPIED_SLOT: u8 = 1;
+const DELETED_SLOT: u8 = 2;
+
+const STATUS_CHANGE_SIZE: usize = 26;
+
+const NO_DISPUTE_REASON: u8 = 0;
+// free-text reasons don't fit a fixed-size slot, their text is kept in memory
+const OTHER_DISPUTE_REASON: u8 = u8::MAX;
+
+// Worst case memory of one hot record. The hot map keeps a control byte per bucket and fills at
+// most 7/8 of its buckets, right after doubling a record takes 16/7 buckets. The eviction queue is
+// compacted at twice the hot records and its buffer doubles too, so up to four entries per record.
+// Free-text dispute notes aren't counted
+const HOT_RECORD_COST: usize =
+    (size_of::<(TransactionId, (PackedTransactionInfo, u64))>() + 1) * 16 / 7
+        + 4 * size_of::<(TransactionId, u64)>();
+// queues shorter than this aren't worth compacting
+const MIN_EVICTION_QUEUE_COMPACTION: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PackedTransactionInfo {
+    transaction_id: TransactionId,
+    sequence_number: SequenceNumber,
+    timestamp: Timestamp,
+    amount: [u8; 16],
+    client_id: ClientId,
+    transaction_type: u8,
+    status: u8,
+    dispute_reason: u8,
+}
+
+impl PackedTransactionInfo {
+    fn to_bytes(self) -> [u8; PACKED_RECORD_SIZE] {
+        let mut bytes = [0u8; PACKED_RECORD_SIZE];
+        bytes[0..8].copy_from_slice(&self.transaction_id.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.sequence_number.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes[24..40].copy_from_slice(&self.amount);
+        bytes[40..42].copy_from_slice(&self.client_id.to_le_bytes());
+        bytes[42] = self.transaction_type;
+        bytes[43] = self.status;
+        bytes[44] = self.dispute_reason;
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; PACKED_RECORD_SIZE]) -> Self {
+        let mut transaction_id = [0u8; 8];
+        transaction_id.copy_from_slice(&bytes[0..8]);
+        let mut sequence_number = [0u8; 8];
+        sequence_number.copy_from_slice(&bytes[8..16]);
+        let mut timestamp = [0u8; 8];
+        timestamp.copy_from_slice(&bytes[16..24]);
+        let mut amount = [0u8; 16];
+        amount.copy_from_slice(&bytes[24..40]);
+        let mut client_id = [0u8; 2];
+        client_id.copy_from_slice(&bytes[40..42]);
+        Self {
+            transaction_id: TransactionId::from_le_bytes(transaction_id),
+            sequence_number: SequenceNumber::from_le_bytes(sequence_number),
+            timestamp: Timestamp::from_le_bytes(timestamp),
+            amount,
+            client_id: ClientId::from_le_bytes(client_id),
+            transaction_type: bytes[42],
+            status: bytes[43],
+            dispute_reason: bytes[44],
+        }
+    }
+
+    fn unpack(
+        &self,
+        dispute_notes: &HashMap<TransactionId, String>,
+    ) -> Result<TransactionInfo, TransactionHistoryError> {
+        Ok(TransactionInfo {
+            client_id: self.client_id,
+            transaction_id: self.transaction_id,
+            transaction_type: transaction_type_from_code(self.transaction_type)?,
+            amount: Decimal::deserialize(self.amount),
+            status: status_from_code(self.status)?,
+            sequence_number: self.sequence_number,
+            timestamp: self.timestamp(),
+            dispute_reason: dispute_reason_from_code(
+                self.dispute_reason,
+                dispute_notes.get(&self.transaction_id),
+            )?,
+        })
+    }
+
+    fn timestamp(&self) -> Option<Timestamp> {
+        (self.timestamp != NO_TIMESTAMP).then_some(self.timestamp)
+    }
+}
+
+impl From<&TransactionInfo> for PackedTransactionInfo {
+    fn from(value: &TransactionInfo) -> Self {
+        Self {
+            transaction_id: value.transaction_id,
+            sequence_number: value.sequence_number,
+            timestamp: value.timestamp.unwrap_or(NO_TIMESTAMP),
+            amount: value.amount.serialize(),
+            client_id: value.client_id,
+            transaction_type: transaction_type_code(value.transaction_type),
+            status: status_code(value.status),
+            dispute_reason: dispute_reason_code(value.dispute_reason.as_ref()),
+        }
+    }
+}
+
+fn transaction_type_code(transaction_type: TransactionInfoType) -> u8 {
+    match transaction_type {
+        TransactionInfoType::Deposit => 0,
+        TransactionInfoType::Withdrawal => 1,
+    }
+}
+
+fn transaction_type_from_code(code: u8) -> Result<TransactionInfoType, TransactionHistoryError> {
+    match code {
+        0 => Ok(TransactionInfoType::Deposit),
+        1 => Ok(TransactionInfoType::Withdrawal),
+        _ => Err(TransactionHistoryError::CorruptedRecord),
+    }
+}
+
+fn status_code(status: TransactionStatus) -> u8 {
+    match status {
+        TransactionStatus::WithoutDisputes => 0,
+        TransactionStatus::Disputed => 1,
+        TransactionStatus::Resolved => 2,
+        TransactionStatus::Chargebacked => 3,
+        TransactionStatus::Represented => 4,
+        TransactionStatus::PreArbitration => 5,
+        TransactionStatus::Arbitration => 6,
+    }
+}
+
+fn status_from_code(code: u8) -> Result<TransactionStatus, TransactionHistoryError> {
+    match code {
+        0 => Ok(TransactionStatus::WithoutDisputes),
+        1 => Ok(TransactionStatus::Disputed),
+        2 => Ok(TransactionStatus::Resolved),
+        3 => Ok(TransactionStatus::Chargebacked),
+        4 => Ok(TransactionStatus::Represented),
+        5 => Ok(TransactionStatus::PreArbitration),
+        6 => Ok(TransactionStatus::Arbitration),
+        _ => Err(TransactionHistoryError::CorruptedRecord),
+    }
+}
+
+fn dispute_reason_code(dispute_reason: Option<&DisputeReason>) -> u8 {
+    match dispute_reason {
+        None => NO_DISPUTE_REASON,
+        Some(DisputeReason::Fraud) => 1,
+        Some(DisputeReason::NotReceived) => 2,
+        Some(DisputeReason::Duplicate) => 3,
+        Some(DisputeReason::NotAsDescribed) => 4,
+        Some(DisputeReason::Cancelled) => 5,
+        Some(DisputeReason::IncorrectAmount) => 6,
+        Some(DisputeReason::Other(_)) => OTHER_DISPUTE_REASON,
+    }
+}
+
+fn dispute_reason_from_code(
+    code: u8,
+    note: Option<&String>,
+) -> Result<Option<DisputeReason>, TransactionHistoryError> {
+    match (code, note) {
+        (NO_DISPUTE_REASON, _) => Ok(None),
+        (1, _) => Ok(Some(DisputeReason::Fraud)),
+        (2, _) => Ok(Some(DisputeReason::NotReceived)),
+        (3, _) => Ok(Some(DisputeReason::Duplicate)),
+        (4, _) => Ok(Some(DisputeReason::NotAsDescribed)),
+        (5, _) => Ok(Some(DisputeReason::Cancelled)),
+        (6, _) => Ok(Some(DisputeReason::IncorrectAmount)),
+        (OTHER_DISPUTE_REASON, Some(note)) => Ok(Some(DisputeReason::Other(note.clone()))),
+        _ => Err(TransactionHistoryError::CorruptedRecord),
+    }
+}
+
+// Open addressing hash table with linear probing, laid out as fixed-size slots in a file
+struct DiskIndex {
+    file: File,
+    spill_dir: PathBuf,
+    capacity: u64,
+    occupied: u64,
+    deleted: u64,
+}
+
+impl DiskIndex {
+    fn new(spill_dir: &Path, capacity: u64) -> io::Result<Self> {
+        let file = tempfile::tempfile_in(spill_dir)?;
+        file.set_len(capacity * SLOT_SIZE as u64)?;
+        Ok(Self {
+            file,
+            spill_dir: spill_dir.to_path_buf(),
+            capacity,
+            occupied: 0,
+            deleted: 0,
+        })
+    }
+
+    fn len(&self) -> u64 {
+        self.occupied
+    }
+
+    fn home_slot(&self, transaction_id: TransactionId) -> u64 {
+        // splitmix64 finalizer, sequential ids must not cluster in neighbouring slots
+        let mut hash = transaction_id.wrapping_add(0x9e3779b97f4a7c15);
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+        (hash ^ (hash >> 31)) % self.capacity
+    }
+
+    fn read_slot(&mut self, slot: u64) -> io::Result<(u8, [u8; PACKED_RECORD_SIZE])> {
+        let mut buffer = [0u8; SLOT_SIZE];
+        self.file.seek(SeekFrom::Start(slot * SLOT_SIZE as u64))?;
+        self.file.read_exact(&mut buffer)?;
+        let mut record = [0u8; PACKED_RECORD_SIZE];
+        record.copy_from_slice(&buffer[1..]);
+        Ok((buffer[0], record))
+    }
+
+    fn write_slot(
+        &mut self,
+        slot: u64,
+        flag: u8,
+        record: &[u8; PACKED_RECORD_SIZE],
+    ) -> io::Result<()> {
+        let mut buffer = [0u8; SLOT_SIZE];
+        buffer[0] = flag;
+        buffer[1..].copy_from_slice(record);
+        self.file.seek(SeekFrom::Start(slot * SLOT_SIZE as u64))?;
+        self.file.write_all(&buffer)
+    }
+
+    fn find(
+        &mut self,
+        transaction_id: TransactionId,
+    ) -> io::Result<Option<(u64, PackedTransactionInfo)>> {
+        let mut slot = self.home_slot(transaction_id);
+        for _ in 0..self.capacity {
+            let (flag, bytes) = self.read_slot(slot)?;
+            match flag {
+                EMPTY_SLOT => return Ok(None),
+                OCCUPIED_SLOT => {
+                    let record = PackedTransactionInfo::from_bytes(&bytes);
+                    if record.transaction_id == transaction_id {
+                        return Ok(Some((slot, record)));
+                    }
+                }
+                _ => {}
+            }
+            slot = (slot + 1) % self.capacity;
+        }
+        Ok(None)
+    }
+
+    fn scan(&mut self, mut visit: impl FnMut(&PackedTransactionInfo)) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(&self.file);
+        let mut buffer = [0u8; SLOT_SIZE];
+        for _ in 0..self.capacity {
+            reader.read_exact(&mut buffer)?;
+            if buffer[0] == OCCUPIED_SLOT {
+                let mut bytes = [0u8; PACKED_RECORD_SIZE];
+                bytes.copy_from_slice(&buffer[1..]);
+                visit(&PackedTransactionInfo::from_bytes(&bytes));
+            }
+        }
+        Ok(())
+    }
+
+    // caller guarantees that the record is not in the index yet
+    fn insert(&mut self, record: PackedTransactionInfo) -> io::Result<()> {
+        if (self.occupied + self.deleted + 1) * 10 > self.capacity * 7 {
+            self.rebuild()?;
+        }
+        let mut slot = self.home_slot(record.transaction_id);
+        loop {
+            let (flag, _) = self.read_slot(slot)?;
+            if flag != OCCUPIED_SLOT {
+                if flag == DELETED_SLOT {
+                    self.deleted -= 1;
+                }
+                self.occupied += 1;
+                return self.write_slot(slot, OCCUPIED_SLOT, &record.to_bytes());
+            }
+            slot = (slot + 1) % self.capacity;
+        }
+    }
+
+    fn remove(&mut self, slot: u64) -> io::Result<()> {
+        self.write_slot(slot, DELETED_SLOT, &[0u8; PACKED_RECORD_SIZE])?;
+        self.occupied -= 1;
+        self.deleted += 1;
+        Ok(())
+    }
+
+    fn rebuild(&mut self) -> io::Result<()> {
+        let capacity = if self.occupied * 2 >= self.capacity {
+            self.capacity * 2
+        } else {
+            // mostly tombstones, same size is enough to clean them up
+            self.capacity
+        };
+        let mut rebuilt = DiskIndex::new(&self.spill_dir, capacity)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(&self.file);
+        let mut buffer = [0u8; SLOT_SIZE];
+        for _ in 0..self.capacity {
+            reader.read_exact(&mut buffer)?;
+            if buffer[0] == OCCUPIED_SLOT {
+                let mut bytes = [0u8; PACKED_RECORD_SIZE];
+                bytes.copy_from_slice(&buffer[1..]);
+                rebuilt.insert(PackedTransactionInfo::from_bytes(&bytes))?;
+            }
+        }
+        *self = rebuilt;
+        Ok(())
+    }
+}
+
+// Append-only log of status changes, read back only for audit queries
+struct AuditLog {
+    file: File,
+    len: u64,
+}
+
+impl AuditLog {
+    fn new(spill_dir: &Path) -> io::Result<Self> {
+        Ok(Self {
+            file: tempfile::tempfile_in(spill_dir)?,
+            len: 0,
+        })
+    }
+
+    fn append(&mut self, status_change: &StatusChange) -> io::Result<()> {
+        let mut bytes = [0u8; STATUS_CHANGE_SIZE];
+        bytes[0..8].copy_from_slice(&status_change.transaction_id.to_le_bytes());
+        bytes[8] = status_code(status_change.previous_status);
+        bytes[9] = status_code(status_change.new_status);
+        bytes[10..18].copy_from_slice(&status_change.sequence_number.to_le_bytes());
+        bytes[18..26].copy_from_slice(
+            &status_change
+                .timestamp
+                .unwrap_or(NO_TIMESTAMP)
+                .to_le_bytes(),
+        );
+        self.file
+            .seek(SeekFrom::Start(self.len * STATUS_CHANGE_SIZE as u64))?;
+        self.file.write_all(&bytes)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn read(
+        &mut self,
+        transaction_id: Option<TransactionId>,
+    ) -> Result<Vec<StatusChange>, Box<dyn Error>> {
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(&self.file);
+        let mut bytes = [0u8; STATUS_CHANGE_SIZE];
+        let mut status_changes = Vec::new();
+        for _ in 0..self.len {
+            reader.read_exact(&mut bytes)?;
+            let mut id = [0u8; 8];
+            id.copy_from_slice(&bytes[0..8]);
+            let id = TransactionId::from_le_bytes(id);
+            if transaction_id.is_some_and(|transaction_id| transaction_id != id) {
+                continue;
+            }
+            let mut sequence_number = [0u8; 8];
+            sequence_number.copy_from_slice(&bytes[10..18]);
+            let mut timestamp = [0u8; 8];
+            timestamp.copy_from_slice(&bytes[18..26]);
+            let timestamp = Timestamp::from_le_bytes(timestamp);
+            status_changes.push(StatusChange {
+                transaction_id: id,
+                previous_status: status_from_code(bytes[8])?,
+                new_status: status_from_code(bytes[9])?,
+                sequence_number: SequenceNumber::from_le_bytes(sequence_number),
+                timestamp: (timestamp != NO_TIMESTAMP).then_some(timestamp),
+            });
+        }
+        Ok(status_changes)
+    }
+}
+
+struct TieredState {
+    hot: HashMap<TransactionId, (PackedTransactionInfo, u64)>,
+    eviction_queue: VecDeque<(TransactionId, u64)>,
+    // records of both tiers in the order they leave the dispute window, only kept with a window.
+    // Records still in the window are bounded by it rather than by the memory budget
+    expiry_queue: ExpiryQueue,
+    next_stamp: u64,
+    cold: DiskIndex,
+    dispute_notes: HashMap<TransactionId, String>,
+    audit: AuditLog,
+    expired: ExpiredIds,
+}
+
+impl TieredState {
+    fn insert_hot(&mut self, record: PackedTransactionInfo) {
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        self.hot.insert(record.transaction_id, (record, stamp));
+        self.eviction_queue
+            .push_back((record.transaction_id, stamp));
+        if self.eviction_queue.len() > MIN_EVICTION_QUEUE_COMPACTION.max(2 * self.hot.len()) {
+            self.compact_eviction_queue();
+        }
+    }
+
+    // entries promoted back from disk get a new stamp, older queue entries are stale
+    fn is_current(&self, transaction_id: TransactionId, stamp: u64) -> bool {
+        self.hot.get(&transaction_id).map(|(_, current)| *current) == Some(stamp)
+    }
+
+    // drops the stale entries left by promotions, evictions and drops of hot records
+    fn compact_eviction_queue(&mut self) {
+        let mut eviction_queue = std::mem::take(&mut self.eviction_queue);
+        eviction_queue.retain(|(transaction_id, stamp)| self.is_current(*transaction_id, *stamp));
+        self.eviction_queue = eviction_queue;
+    }
+
+    fn evict_overflow(&mut self, max_hot_records: usize) -> io::Result<()> {
+        while self.hot.len() > max_hot_records {
+            let Some((transaction_id, stamp)) = self.eviction_queue.pop_front() else {
+                break;
+            };
+            if !self.is_current(transaction_id, stamp) {
+                continue;
+            }
+            let (record, _) = self.hot.remove(&transaction_id).unwrap();
+            self.cold.insert(record)?;
+        }
+        Ok(())
+    }
+
+    fn promote(
+        &mut self,
+        transaction_id: TransactionId,
+        max_hot_records: usize,
+    ) -> io::Result<Option<PackedTransactionInfo>> {
+        if let Some((record, _)) = self.hot.get(&transaction_id) {
+            return Ok(Some(*record));
+        }
+        let Some((slot, record)) = self.cold.find(transaction_id)? else {
+            return Ok(None);
+        };
+        self.cold.remove(slot)?;
+        self.insert_hot(record);
+        self.evict_overflow(max_hot_records)?;
+        Ok(Some(record))
+    }
+}
+
+pub struct TieredTransactionStorage {
+    state: Mutex<TieredState>,
+    max_hot_records: usize,
+    dispute_window: Option<DisputeWindow>,
+    transitions: TransitionTable,
+}
+
+impl TieredTransactionStorage {
+    pub fn new(memory_budget: usize) -> io::Result<Self> {
+        Self::with_spill_dir(memory_budget, &std::env::temp_dir())
+    }
+
+    pub fn with_spill_dir(memory_budget: usize, spill_dir: &Path) -> io::Result<Self> {
+        Ok(Self {
+            state: Mutex::new(TieredState {
+                hot: HashMap::new(),
+                eviction_queue: VecDeque::new(),
+                expiry_queue: VecDeque::new(),
+                next_stamp: 0,
+                cold: DiskIndex::new(spill_dir, INITIAL_DISK_CAPACITY)?,
+                dispute_notes: HashMap::new(),
+                audit: AuditLog::new(spill_dir)?,
+                expired: ExpiredIds::default(),
+            }),
+            max_hot_records: (memory_budget / HOT_RECORD_COST).max(1),
+            dispute_window: None,
+            transitions: TransitionTable::default(),
+        })
+    }
+
+    pub fn with_dispute_window(mut self, dispute_window: DisputeWindow) -> Self {
+        self.dispute_window = Some(dispute_window);
+        self
+    }
+
+    pub fn with_transitions(mut self, transitions: TransitionTable) -> Self {
+        self.transitions = transitions;
+        self
+    }
+
+    pub fn hot_len(&self) -> usize {
+        self.state.lock().unwrap().hot.len()
+    }
+
+    pub fn cold_len(&self) -> u64 {
+        self.state.lock().unwrap().cold.len()
+    }
+}
+
+impl TransactionHistoryStorage for TieredTransactionStorage {
+    fn add_transaction(&self, transaction_info: TransactionInfo) -> Result<(), Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+        if state.expired.contains(transaction_info.transaction_id) {
+            warn!("Attempt to add transaction, that was dropped from history storage");
+            return Err(Box::new(TransactionHistoryError::TransactionAlreadyExists));
+        }
+        if state.hot.contains_key(&transaction_info.transaction_id)
+            || state.cold.find(transaction_info.transaction_id)?.is_some()
+        {
+            warn!("Attempt to add transaction, that already exists in history storage");
+            return Err(Box::new(TransactionHistoryError::TransactionAlreadyExists));
+        }
+        if let Some(DisputeReason::Other(note)) = &transaction_info.dispute_reason {
+            state
+                .dispute_notes
+                .insert(transaction_info.transaction_id, note.clone());
+        }
+        if self.dispute_window.is_some() {
+            state.expiry_queue.push_back((
+                transaction_info.sequence_number,
+                transaction_info.timestamp,
+                transaction_info.transaction_id,
+            ));
+        }
+        state.insert_hot(PackedTransactionInfo::from(&transaction_info));
+        state.evict_overflow(self.max_hot_records)?;
+        Ok(())
+    }
+
+    fn find_transaction(&self, transaction_id: TransactionId) -> Option<TransactionInfo> {
+        let mut state = self.state.lock().unwrap();
+        let record = match state.promote(transaction_id, self.max_hot_records) {
+            Ok(record) => record?,
+            Err(e) => {
+                error!("Can't read transaction {transaction_id} from spill file, got: {e}");
+                return None;
+            }
+        };
+        match record.unpack(&state.dispute_notes) {
+            Ok(transaction_info) => Some(transaction_info),
+            Err(e) => {
+                error!("Can't unpack transaction {transaction_id}, got: {e}");
+                None
+            }
+        }
+    }
+
+    fn update_transaction_status(
+        &self,
+        transaction_id: TransactionId,
+        new_status: TransactionStatus,
+        cause: &TransactionMeta,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+        if state
+            .promote(transaction_id, self.max_hot_records)?
+            .is_none()
+        {
+            warn!("Attempt to update unknown transaction");
+            return Err(Box::new(TransactionHistoryError::UnknownTransaction));
+        }
+        let (record, _) = state.hot.get_mut(&transaction_id).unwrap();
+        let current_status = status_from_code(record.status)?;
+        let status = self
+            .transitions
+            .make_transition(current_status, new_status)?;
+        record.status = status_code(status);
+        // a record kept past the window while its dispute was open is dropped once it's closed
+        let (sequence_number, timestamp) = (record.sequence_number, record.timestamp());
+        if self.dispute_window.is_some()
+            && !self.transitions.is_droppable(current_status)
+            && self.transitions.is_droppable(status)
+        {
+            requeue_expiry(
+                &mut state.expiry_queue,
+                sequence_number,
+                timestamp,
+                transaction_id,
+            );
+        }
+        state.audit.append(&StatusChange {
+            transaction_id,
+            previous_status: current_status,
+            new_status,
+            sequence_number: cause.sequence_number,
+            timestamp: cause.timestamp,
+        })?;
+        Ok(())
+    }
+
+    fn status_changes(
+        &self,
+        transaction_id: Option<TransactionId>,
+    ) -> Result<Vec<StatusChange>, Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+        let mut status_changes = state.audit.read(transaction_id)?;
+        status_changes.sort_by_key(|status_change| status_change.sequence_number);
+        Ok(status_changes)
+    }
+
+    // no per-client index here, it would grow with the whole history and break the memory budget;
+    // statements are rare enough to afford a full scan of the spill file
+    fn client_transactions(
+        &self,
+        client_id: ClientId,
+    ) -> Result<Vec<TransactionInfo>, Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+        let mut records = state
+            .hot
+            .values()
+            .map(|(record, _)| *record)
+            .filter(|record| record.client_id == client_id)
+            .collect::<Vec<_>>();
+        state.cold.scan(|record| {
+            if record.client_id == client_id {
+                records.push(*record);
+            }
+        })?;
+        let mut transactions = records
+            .iter()
+            .map(|record| record.unpack(&state.dispute_notes))
+            .collect::<Result<Vec<_>, _>>()?;
+        transactions.sort_by_key(|transaction_info| transaction_info.sequence_number);
+        Ok(transactions)
+    }
+
+    fn set_dispute_reason(
+        &self,
+        transaction_id: TransactionId,
+        dispute_reason: Option<DisputeReason>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+        if state
+            .promote(transaction_id, self.max_hot_records)?
+            .is_none()
+        {
+            warn!("Attempt to set dispute reason of unknown transaction");
+            return Err(Box::new(TransactionHistoryError::UnknownTransaction));
+        }
+        let (record, _) = state.hot.get_mut(&transaction_id).unwrap();
+        record.dispute_reason = dispute_reason_code(dispute_reason.as_ref());
+        match dispute_reason {
+            Some(DisputeReason::Other(note)) => state.dispute_notes.insert(transaction_id, note),
+            _ => state.dispute_notes.remove(&transaction_id),
+        };
+        Ok(())
+    }
+
+    fn dispute_window(&self) -> Option<DisputeWindow> {
+        self.dispute_window
+    }
+
+    fn transitions(&self) -> &TransitionTable {
+        &self.transitions
+    }
+
+    // Cold records are looked up and their slots freed in place, records that can still change
+    // are left out of the queue until their status allows dropping them
+    fn drop_expired(&self, now: &TransactionMeta) -> Result<usize, Box<dyn Error>> {
+        let Some(dispute_window) = self.dispute_window else {
+            return Ok(0);
+        };
+        let mut state = self.state.lock().unwrap();
+        let mut dropped = 0;
+        while let Some((sequence_number, timestamp, transaction_id)) =
+            state.expiry_queue.front().copied()
+        {
+            if dispute_window.is_open_since(sequence_number, timestamp, now) {
+                break;
+            }
+            state.expiry_queue.pop_front();
+            let (slot, record) = match state.hot.get(&transaction_id) {
+                Some((record, _)) => (None, *record),
+                None => match state.cold.find(transaction_id)? {
+                    Some((slot, record)) => (Some(slot), record),
+                    None => continue,
+                },
+            };
+            if !self
+                .transitions
+                .is_droppable(status_from_code(record.status)?)
+            {
+                continue;
+            }
+            match slot {
+                Some(slot) => state.cold.remove(slot)?,
+                None => {
+                    state.hot.remove(&transaction_id);
+                }
+            }
+            if record.dispute_reason == OTHER_DISPUTE_REASON {
+                state.dispute_notes.remove(&transaction_id);
+            }
+            state.expired.insert(transaction_id);
+            dropped += 1;
+        }
+        state.compact_eviction_queue();
+        Ok(dropped)
+    }
+
+    fn is_expired(&self, transaction_id: TransactionId) -> bool {
+        self.state.lock().unwrap().expired.contains(transaction_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use rust_decimal::dec;
+
+    fn transaction(transaction_id: TransactionId) -> TransactionInfo {
+        TransactionInfo {
+            client_id: (transaction_id % 7) as ClientId,
+            transaction_id,
+            amount: Decimal::new(transaction_id as i64, 2),
+            transaction_type: TransactionInfoType::Deposit,
+            status: TransactionStatus::WithoutDisputes,
+            sequence_number: transaction_id,
+            timestamp: None,
+            dispute_reason: None,
+        }
+    }
+
+    fn storage_with_hot_records(hot_records: usize) -> TieredTransactionStorage {
+        TieredTransactionStorage::new(hot_records * HOT_RECORD_COST).unwrap()
+    }
+
+    #[rstest]
+    #[case(TransactionInfoType::Deposit, TransactionStatus::WithoutDisputes, dec!(0.0001), None, None)]
+    #[case(TransactionInfoType::Deposit, TransactionStatus::Disputed, dec!(100), Some(0), Some(DisputeReason::Fraud))]
+    #[case(TransactionInfoType::Withdrawal, TransactionStatus::Resolved, dec!(-5.5), Some(1_700_000_000), Some(DisputeReason::IncorrectAmount))]
+    #[case(
+        TransactionInfoType::Withdrawal,
+        TransactionStatus::Chargebacked,
+        Decimal::MAX,
+        None,
+        Some(DisputeReason::Other("wrong size".to_string()))
+    )]
+    #[case(TransactionInfoType::Deposit, TransactionStatus::Represented, dec!(1), None, Some(DisputeReason::NotReceived))]
+    #[case(TransactionInfoType::Deposit, TransactionStatus::PreArbitration, dec!(1), None, Some(DisputeReason::Duplicate))]
+    #[case(TransactionInfoType::Withdrawal, TransactionStatus::Arbitration, dec!(1), Some(1), Some(DisputeReason::Cancelled))]
+    #[case(TransactionInfoType::Withdrawal, TransactionStatus::Disputed, dec!(1), None, Some(DisputeReason::NotAsDescribed))]
+    fn test_packed_record_roundtrip(
+        #[case] transaction_type: TransactionInfoType,
+        #[case] status: TransactionStatus,
+        #[case] amount: Decimal,
+        #[case] timestamp: Option<Timestamp>,
+        #[case] dispute_reason: Option<DisputeReason>,
+    ) {
+        let transaction_info = TransactionInfo {
+            client_id: ClientId::MAX,
+            transaction_id: TransactionId::MAX,
+            transaction_type,
+            amount,
+            status,
+            sequence_number: SequenceNumber::MAX,
+            timestamp,
+            dispute_reason,
+        };
+
+        let packed = PackedTransactionInfo::from(&transaction_info);
+        let restored = PackedTransactionInfo::from_bytes(&packed.to_bytes());
+
+        let mut dispute_notes = HashMap::new();
+        if let Some(DisputeReason::Other(note)) = &transaction_info.dispute_reason {
+            dispute_notes.insert(transaction_info.transaction_id, note.clone());
+        }
+
+        assert_eq!(restored, packed);
+        assert_eq!(restored.unpack(&dispute_notes).unwrap(), transaction_info);
+    }
+
+    #[test]
+    fn test_unpack_corrupted_record() {
+        let mut packed = PackedTransactionInfo::from(&transaction(1));
+        packed.status = u8::MAX;
+        let mut missing_note = PackedTransactionInfo::from(&transaction(2));
+        missing_note.dispute_reason = OTHER_DISPUTE_REASON;
+
+        assert_eq!(
+            packed.unpack(&HashMap::new()).unwrap_err(),
+            TransactionHistoryError::CorruptedRecord
+        );
+        assert_eq!(
+            missing_note.unpack(&HashMap::new()).unwrap_err(),
+            TransactionHistoryError::CorruptedRecord
+        );
+    }
+
+    #[test]
+    fn test_memory_budget_spills_to_disk() {
+        let storage = storage_with_hot_records(10);
+
+        for transaction_id in 0..5000 {
+            storage
+                .add_transaction(transaction(transaction_id))
+                .unwrap();
+        }
+
+        assert_eq!(storage.hot_len(), 10);
+        assert_eq!(storage.cold_len(), 4990);
+        for transaction_id in (0..5000).step_by(97) {
+            assert_eq!(
+                storage.find_transaction(transaction_id),
+                Some(transaction(transaction_id))
+            );
+        }
+        assert!(storage.find_transaction(5000).is_none());
+        assert_eq!(storage.hot_len(), 10);
+        assert_eq!(storage.cold_len(), 4990);
+    }
+
+    #[test]
+    fn test_find_brings_cold_transaction_back_to_memory() {
+        let storage = storage_with_hot_records(2);
+        for transaction_id in 1..=3 {
+            storage
+                .add_transaction(transaction(transaction_id))
+                .unwrap();
+        }
+        assert!(!storage.state.lock().unwrap().hot.contains_key(&1));
+
+        storage.find_transaction(1).unwrap();
+
+        let state = storage.state.lock().unwrap();
+        assert!(state.hot.contains_key(&1));
+        assert!(state.hot.contains_key(&3));
+        assert!(!state.hot.contains_key(&2));
+        assert_eq!(state.cold.len(), 1);
+    }
+
+    #[test]
+    fn test_eviction_queue_stays_bounded() {
+        let storage =
+            storage_with_hot_records(4).with_dispute_window(DisputeWindow::Transactions(2));
+        for transaction_id in 0..2000 {
+            storage
+                .add_transaction(transaction(transaction_id))
+                .unwrap();
+            storage
+                .drop_expired(&TransactionMeta {
+                    sequence_number: transaction_id,
+                    timestamp: None,
+                })
+                .unwrap();
+        }
+
+        let state = storage.state.lock().unwrap();
+        assert_eq!(state.hot.len(), 3);
+        assert_eq!(state.eviction_queue.len(), 3);
+    }
+
+    #[test]
+    fn test_add_transaction_duplicate_in_cold_storage() {
+        let storage = storage_with_hot_records(1);
+        storage.add_transaction(transaction(1)).unwrap();
+        storage.add_transaction(transaction(2)).unwrap();
+
+        let error = storage.add_transaction(transaction(1)).unwrap_err();
+
+        let history_error = error.downcast_ref::<TransactionHistoryError>().unwrap();
+        assert_eq!(
+            *history_error,
+            TransactionHistoryError::TransactionAlreadyExists
+        );
+        assert_eq!(storage.cold_len(), 1);
+    }
+
+    #[test]
+    fn test_update_status_of_cold_transaction() {
+        let storage = storage_with_hot_records(1);
+        for transaction_id in 1..=100 {
+            storage
+                .add_transaction(transaction(transaction_id))
+                .unwrap();
+        }
+
+        storage
+            .update_transaction_status(5, TransactionStatus::Disputed, &TransactionMeta::default())
+            .unwrap();
+        // push it back to disk and make sure the new status survived
+        storage.find_transaction(6).unwrap();
+
+        assert_eq!(
+            storage.find_transaction(5).unwrap().status,
+            TransactionStatus::Disputed
+        );
+    }
+
+    #[test]
+    fn test_status_changes_of_spilled_transactions() {
+        let storage = storage_with_hot_records(1);
+        for transaction_id in 1..=10 {
+            storage
+                .add_transaction(transaction(transaction_id))
+                .unwrap();
+        }
+        let cause = |sequence_number, timestamp| TransactionMeta {
+            sequence_number,
+            timestamp,
+        };
+
+        storage
+            .update_transaction_status(3, TransactionStatus::Disputed, &cause(11, Some(100)))
+            .unwrap();
+        storage
+            .update_transaction_status(4, TransactionStatus::Disputed, &cause(12, None))
+            .unwrap();
+        storage
+            .update_transaction_status(3, TransactionStatus::Resolved, &cause(13, None))
+            .unwrap();
+
+        assert_eq!(
+            storage.status_changes(Some(3)).unwrap(),
+            vec![
+                StatusChange {
+                    transaction_id: 3,
+                    previous_status: TransactionStatus::WithoutDisputes,
+                    new_status: TransactionStatus::Disputed,
+                    sequence_number: 11,
+                    timestamp: Some(100),
+                },
+                StatusChange {
+                    transaction_id: 3,
+                    previous_status: TransactionStatus::Disputed,
+                    new_status: TransactionStatus::Resolved,
+                    sequence_number: 13,
+                    timestamp: None,
+                },
+            ]
+        );
+        assert_eq!(storage.status_changes(None).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_dispute_reason_of_cold_transaction() {
+        let storage = storage_with_hot_records(1);
+        for transaction_id in 1..=10 {
+            storage
+                .add_transaction(transaction(transaction_id))
+                .unwrap();
+        }
+        let note = DisputeReason::Other("never ordered".to_string());
+
+        storage.set_dispute_reason(3, Some(note.clone())).unwrap();
+        storage
+            .set_dispute_reason(4, Some(DisputeReason::Fraud))
+            .unwrap();
+        storage.find_transaction(9).unwrap();
+
+        assert_eq!(
+            storage.find_transaction(3).unwrap().dispute_reason,
+            Some(note)
+        );
+        assert_eq!(
+            storage.find_transaction(4).unwrap().dispute_reason,
+            Some(DisputeReason::Fraud)
+        );
+        storage.set_dispute_reason(3, None).unwrap();
+        assert!(storage.state.lock().unwrap().dispute_notes.is_empty());
+        assert!(storage.set_dispute_reason(1000, None).is_err());
+    }
+
+    #[rstest]
+    #[case(
+        1,
+        TransactionStatus::Resolved,
+        TransactionHistoryError::InvalidStatusTransition
+    )]
+    #[case(
+        1000,
+        TransactionStatus::Disputed,
+        TransactionHistoryError::UnknownTransaction
+    )]
+    fn test_update_status_errors(
+        #[case] transaction_id: TransactionId,
+        #[case] new_status: TransactionStatus,
+        #[case] expected_error: TransactionHistoryError,
+    ) {
+        let storage = storage_with_hot_records(1);
+        for transaction_id in 1..=10 {
+            storage
+                .add_transaction(transaction(transaction_id))
+                .unwrap();
+        }
+
+        let error = storage
+            .update_transaction_status(transaction_id, new_status, &TransactionMeta::default())
+            .unwrap_err();
+
+        let history_error = error.downcast_ref::<TransactionHistoryError>().unwrap();
+        assert_eq!(*history_error, expected_error);
+    }
+
+    #[test]
+    fn test_disk_index_reuses_deleted_slots() {
+        let mut index = DiskIndex::new(&std::env::temp_dir(), 16).unwrap();
+        for round in 0..100 {
+            let record = PackedTransactionInfo::from(&transaction(round));
+            index.insert(record).unwrap();
+            let (slot, found) = index.find(round).unwrap().unwrap();
+            assert_eq!(found, record);
+            index.remove(slot).unwrap();
+        }
+
+        assert_eq!(index.len(), 0);
+        assert_eq!(index.capacity, 16);
+        assert!(index.find(0).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_drop_expired_from_both_tiers() {
+        let storage =
+            storage_with_hot_records(10).with_dispute_window(DisputeWindow::Transactions(100));
+        for transaction_id in 1..=300 {
+            storage
+                .add_transaction(transaction(transaction_id))
+                .unwrap();
+        }
+        storage
+            .update_transaction_status(50, TransactionStatus::Disputed, &TransactionMeta::default())
+            .unwrap();
+
+        let dropped = storage
+            .drop_expired(&TransactionMeta {
+                sequence_number: 300,
+                timestamp: None,
+            })
+            .unwrap();
+
+        assert_eq!(dropped, 198);
+        assert!(storage.find_transaction(1).is_none());
+        assert!(storage.find_transaction(50).is_some());
+        assert!(storage.find_transaction(199).is_none());
+        assert!(storage.find_transaction(200).is_some());
+        assert_eq!(storage.hot_len() as u64 + storage.cold_len(), 102);
+        assert!(storage.is_expired(1));
+        assert!(!storage.is_expired(50));
+        assert!(storage.is_expired(199));
+        assert!(!storage.is_expired(200));
+        assert!(storage.add_transaction(transaction(199)).is_err());
+        assert_eq!(storage.state.lock().unwrap().expired.ranges(), 2);
+
+        // kept while disputed, dropped once resolved
+        storage
+            .update_transaction_status(50, TransactionStatus::Resolved, &TransactionMeta::default())
+            .unwrap();
+        let dropped = storage
+            .drop_expired(&TransactionMeta {
+                sequence_number: 301,
+                timestamp: None,
+            })
+            .unwrap();
+
+        assert_eq!(dropped, 2);
+        assert!(storage.find_transaction(50).is_none());
+        assert!(storage.is_expired(50));
+        assert_eq!(storage.hot_len() as u64 + storage.cold_len(), 100);
+        assert_eq!(storage.state.lock().unwrap().expired.ranges(), 1);
+    }
+
+    #[test]
+    fn test_drop_expired_frees_cold_slots_in_place() {
+        let storage =
+            storage_with_hot_records(10).with_dispute_window(DisputeWindow::Transactions(100));
+        for transaction_id in 1..=1000 {
+            storage
+                .add_transaction(transaction(transaction_id))
+                .unwrap();
+        }
+        let capacity = storage.state.lock().unwrap().cold.capacity;
+
+        let dropped = storage
+            .drop_expired(&TransactionMeta {
+                sequence_number: 1000,
+                timestamp: None,
+            })
+            .unwrap();
+
+        let state = storage.state.lock().unwrap();
+        assert_eq!(dropped, 899);
+        assert_eq!(state.cold.capacity, capacity);
+        assert_eq!(state.cold.deleted, 899);
+        assert_eq!(state.expiry_queue.len(), 101);
+    }
+}
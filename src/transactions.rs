@@ -1,5 +1,6 @@
 use enum_dispatch::enum_dispatch;
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use tracing::warn;
 
@@ -8,17 +9,34 @@ const WITHDRAWAL_VALUE: &str = "withdrawal";
 const DISPUTE_VALUE: &str = "dispute";
 const RESOLVE_VALUE: &str = "resolve";
 const CHARGEBACK_VALUE: &str = "chargeback";
+const REPRESENTMENT_VALUE: &str = "representment";
+const PREARBITRATION_VALUE: &str = "prearbitration";
+const ARBITRATION_VALUE: &str = "arbitration";
+const OPEN_VALUE: &str = "open";
+const ACTIVATE_VALUE: &str = "activate";
+const FREEZE_VALUE: &str = "freeze";
+const CLOSE_VALUE: &str = "close";
 
 use crate::{
-    errors::{TransactionError, TransactionLogError},
+    dispute_reasons::DisputeReason,
+    errors::{TransactionError, TransactionHistoryError, TransactionLogError},
     history::TransactionHistoryStorage,
-    storage::{AccountStorage, ClientId},
+    storage::{AccountStatus, AccountStorage, ClientId},
     transactions_processor::{
         TransactionInfo, TransactionInfoType, TransactionLogEntry, TransactionStatus,
     },
 };
 
 pub type TransactionId = u64;
+pub type SequenceNumber = u64;
+// unix time in seconds
+pub type Timestamp = u64;
+
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct TransactionMeta {
+    pub sequence_number: SequenceNumber,
+    pub timestamp: Option<Timestamp>,
+}
 
 pub trait ExecTransaction {
     fn execute(
@@ -36,6 +54,10 @@ pub enum Transaction {
     Dispute,
     Resolve,
     Chargeback,
+    Representment,
+    Escalation,
+    OpenAccount,
+    UpdateAccountStatus,
 }
 
 impl ExecTransaction for Transaction {
@@ -50,6 +72,14 @@ impl ExecTransaction for Transaction {
             Transaction::Dispute(transaction) => transaction.execute(account_storage, history),
             Transaction::Resolve(transaction) => transaction.execute(account_storage, history),
             Transaction::Chargeback(transaction) => transaction.execute(account_storage, history),
+            Transaction::Representment(transaction) => {
+                transaction.execute(account_storage, history)
+            }
+            Transaction::Escalation(transaction) => transaction.execute(account_storage, history),
+            Transaction::OpenAccount(transaction) => transaction.execute(account_storage, history),
+            Transaction::UpdateAccountStatus(transaction) => {
+                transaction.execute(account_storage, history)
+            }
         }
     }
 }
@@ -58,12 +88,40 @@ impl TryFrom<&TransactionLogEntry> for Transaction {
     type Error = TransactionLogError;
 
     fn try_from(value: &TransactionLogEntry) -> Result<Self, Self::Error> {
+        Transaction::from_entry(value, SequenceNumber::default())
+    }
+}
+
+impl Transaction {
+    // transaction whose dispute state the row moves, if any
+    pub fn disputed_transaction_id(&self) -> Option<TransactionId> {
+        match self {
+            Transaction::Dispute(Dispute { transaction_id, .. })
+            | Transaction::Resolve(Resolve { transaction_id, .. })
+            | Transaction::Chargeback(Chargeback { transaction_id, .. })
+            | Transaction::Representment(Representment { transaction_id, .. })
+            | Transaction::Escalation(Escalation { transaction_id, .. }) => Some(*transaction_id),
+            _ => None,
+        }
+    }
+
+    pub fn from_entry(
+        value: &TransactionLogEntry,
+        sequence_number: SequenceNumber,
+    ) -> Result<Self, TransactionLogError> {
         let TransactionLogEntry {
             transaction_type,
             transaction_id,
             client_id,
             amount,
+            timestamp,
+            reason,
+            ..
         } = value;
+        let meta = TransactionMeta {
+            sequence_number,
+            timestamp: *timestamp,
+        };
         match transaction_type.as_str() {
             DEPOSIT_VALUE => {
                 let amount = amount.ok_or(TransactionLogError::MissingAmount)?;
@@ -71,6 +129,7 @@ impl TryFrom<&TransactionLogEntry> for Transaction {
                     client_id: *client_id,
                     transaction_id: *transaction_id,
                     amount,
+                    meta,
                 }))
             }
             WITHDRAWAL_VALUE => {
@@ -79,19 +138,56 @@ impl TryFrom<&TransactionLogEntry> for Transaction {
                     client_id: *client_id,
                     transaction_id: *transaction_id,
                     amount,
+                    meta,
                 }))
             }
             DISPUTE_VALUE => Ok(Transaction::Dispute(Dispute {
                 client_id: *client_id,
                 transaction_id: *transaction_id,
+                reason: reason.as_deref().map(DisputeReason::from),
+                meta,
             })),
             RESOLVE_VALUE => Ok(Transaction::Resolve(Resolve {
                 client_id: *client_id,
                 transaction_id: *transaction_id,
+                meta,
             })),
             CHARGEBACK_VALUE => Ok(Transaction::Chargeback(Chargeback {
                 client_id: *client_id,
                 transaction_id: *transaction_id,
+                meta,
+            })),
+            REPRESENTMENT_VALUE => Ok(Transaction::Representment(Representment {
+                client_id: *client_id,
+                transaction_id: *transaction_id,
+                meta,
+            })),
+            PREARBITRATION_VALUE => Ok(Transaction::Escalation(Escalation {
+                client_id: *client_id,
+                transaction_id: *transaction_id,
+                stage: TransactionStatus::PreArbitration,
+                meta,
+            })),
+            ARBITRATION_VALUE => Ok(Transaction::Escalation(Escalation {
+                client_id: *client_id,
+                transaction_id: *transaction_id,
+                stage: TransactionStatus::Arbitration,
+                meta,
+            })),
+            OPEN_VALUE => Ok(Transaction::OpenAccount(OpenAccount {
+                client_id: *client_id,
+            })),
+            ACTIVATE_VALUE => Ok(Transaction::UpdateAccountStatus(UpdateAccountStatus {
+                client_id: *client_id,
+                new_status: AccountStatus::Active,
+            })),
+            FREEZE_VALUE => Ok(Transaction::UpdateAccountStatus(UpdateAccountStatus {
+                client_id: *client_id,
+                new_status: AccountStatus::Frozen,
+            })),
+            CLOSE_VALUE => Ok(Transaction::UpdateAccountStatus(UpdateAccountStatus {
+                client_id: *client_id,
+                new_status: AccountStatus::Closed,
             })),
             _ => Err(TransactionLogError::InvalidTransactionType),
         }
@@ -103,6 +199,7 @@ pub struct Deposit {
     client_id: ClientId,
     transaction_id: TransactionId,
     amount: Decimal,
+    meta: TransactionMeta,
 }
 
 impl ExecTransaction for Deposit {
@@ -114,6 +211,10 @@ impl ExecTransaction for Deposit {
         if self.amount.is_sign_negative() {
             return Err(Box::new(TransactionError::NegativeAmount));
         }
+        // checked before moving funds, the history would only reject it afterwards
+        if history.is_expired(self.transaction_id) {
+            return Err(Box::new(TransactionHistoryError::TransactionAlreadyExists));
+        }
         account_storage.add_money(self.client_id, self.amount)?;
         let transaction_info = TransactionInfo {
             client_id: self.client_id,
@@ -121,6 +222,9 @@ impl ExecTransaction for Deposit {
             amount: self.amount,
             status: TransactionStatus::WithoutDisputes,
             transaction_type: TransactionInfoType::Deposit,
+            sequence_number: self.meta.sequence_number,
+            timestamp: self.meta.timestamp,
+            dispute_reason: None,
         };
         history.add_transaction(transaction_info)?;
         Ok(())
@@ -132,6 +236,7 @@ pub struct Withdrawal {
     client_id: ClientId,
     transaction_id: TransactionId,
     amount: Decimal,
+    meta: TransactionMeta,
 }
 
 impl ExecTransaction for Withdrawal {
@@ -143,6 +248,10 @@ impl ExecTransaction for Withdrawal {
         if self.amount.is_sign_negative() {
             return Err(Box::new(TransactionError::NegativeAmount));
         }
+        // checked before moving funds, the history would only reject it afterwards
+        if history.is_expired(self.transaction_id) {
+            return Err(Box::new(TransactionHistoryError::TransactionAlreadyExists));
+        }
         account_storage.withdraw_money(self.client_id, self.amount)?;
         let transaction_info = TransactionInfo {
             client_id: self.client_id,
@@ -150,6 +259,9 @@ impl ExecTransaction for Withdrawal {
             amount: self.amount,
             status: TransactionStatus::WithoutDisputes,
             transaction_type: TransactionInfoType::Withdrawal,
+            sequence_number: self.meta.sequence_number,
+            timestamp: self.meta.timestamp,
+            dispute_reason: None,
         };
         history.add_transaction(transaction_info)?;
         Ok(())
@@ -160,6 +272,8 @@ impl ExecTransaction for Withdrawal {
 pub struct Dispute {
     client_id: ClientId,
     transaction_id: TransactionId,
+    reason: Option<DisputeReason>,
+    meta: TransactionMeta,
 }
 
 impl ExecTransaction for Dispute {
@@ -170,26 +284,40 @@ impl ExecTransaction for Dispute {
     ) -> Result<(), Box<dyn Error>> {
         let transaction_info = match history.find_transaction(self.transaction_id) {
             Some(transaction) => transaction,
+            None if history.is_expired(self.transaction_id) => {
+                warn!("Original transaction was dropped after its dispute window");
+                return Err(Box::new(TransactionError::DisputeWindowExpired));
+            }
             None => {
                 warn!("Can't find transaction for dispute");
                 return Err(Box::new(TransactionError::OriginTransactionNotFound));
             }
         };
-        if !matches!(transaction_info.status, TransactionStatus::WithoutDisputes) {
+        if !history
+            .transitions()
+            .is_transition_available(transaction_info.status, TransactionStatus::Disputed)
+        {
             warn!("Original transaction already have been disputed");
             return Err(Box::new(TransactionError::TransactionMultipleDispute));
         }
-        match transaction_info.transaction_type {
-            TransactionInfoType::Deposit => {
-                //TODO: maybe account should be blocked if it hasn't got enough money to be held
-                account_storage.hold_money(self.client_id, transaction_info.amount)?;
-            }
-            TransactionInfoType::Withdrawal => {
-                account_storage.add_money(self.client_id, transaction_info.amount)?;
-                account_storage.hold_money(self.client_id, transaction_info.amount)?;
-            }
-        };
-        history.update_transaction_status(self.transaction_id, TransactionStatus::Disputed)?;
+        if let Some(window) = history.dispute_window()
+            && !window.is_open(&transaction_info, &self.meta)
+        {
+            warn!("Original transaction is out of dispute window");
+            return Err(Box::new(TransactionError::DisputeWindowExpired));
+        }
+        if history
+            .transitions()
+            .holds_funds(TransactionStatus::Disputed)
+        {
+            hold_disputed_funds(account_storage, self.client_id, &transaction_info)?;
+        }
+        history.update_transaction_status(
+            self.transaction_id,
+            TransactionStatus::Disputed,
+            &self.meta,
+        )?;
+        history.set_dispute_reason(self.transaction_id, self.reason.clone())?;
         Ok(())
     }
 }
@@ -198,6 +326,7 @@ impl ExecTransaction for Dispute {
 pub struct Resolve {
     client_id: ClientId,
     transaction_id: TransactionId,
+    meta: TransactionMeta,
 }
 
 impl ExecTransaction for Resolve {
@@ -213,12 +342,30 @@ impl ExecTransaction for Resolve {
                 return Err(Box::new(TransactionError::OriginTransactionNotFound));
             }
         };
-        if !matches!(transaction_info.status, TransactionStatus::Disputed) {
+        let transitions = history.transitions();
+        if !transitions
+            .is_transition_available(transaction_info.status, TransactionStatus::Resolved)
+        {
             warn!("Original transaction not in disputed state");
             return Err(Box::new(TransactionError::TransactionNotDisputed));
         }
-        history.update_transaction_status(self.transaction_id, TransactionStatus::Resolved)?;
-        account_storage.unhold_money(self.client_id, transaction_info.amount)?;
+        match (
+            transitions.holds_funds(transaction_info.status),
+            transaction_info.transaction_type,
+        ) {
+            (true, _) => account_storage.unhold_money(self.client_id, transaction_info.amount)?,
+            // released deposit is already back in available balance
+            (false, TransactionInfoType::Deposit) => {}
+            (false, TransactionInfoType::Withdrawal) => {
+                hold_disputed_funds(account_storage, self.client_id, &transaction_info)?;
+                account_storage.unhold_money(self.client_id, transaction_info.amount)?;
+            }
+        };
+        history.update_transaction_status(
+            self.transaction_id,
+            TransactionStatus::Resolved,
+            &self.meta,
+        )?;
         Ok(())
     }
 }
@@ -227,6 +374,7 @@ impl ExecTransaction for Resolve {
 pub struct Chargeback {
     client_id: ClientId,
     transaction_id: TransactionId,
+    meta: TransactionMeta,
 }
 
 impl ExecTransaction for Chargeback {
@@ -242,25 +390,204 @@ impl ExecTransaction for Chargeback {
                 return Err(Box::new(TransactionError::OriginTransactionNotFound));
             }
         };
-        if !matches!(transaction_info.status, TransactionStatus::Disputed) {
+        let transitions = history.transitions();
+        if !transitions
+            .is_transition_available(transaction_info.status, TransactionStatus::Chargebacked)
+        {
             warn!("Original transaction not in disputed state");
             return Err(Box::new(TransactionError::TransactionNotDisputed));
         }
-        history.update_transaction_status(self.transaction_id, TransactionStatus::Chargebacked)?;
-        // TODO: maybe I need to make unhold + withdraw as a one method
-        account_storage.unhold_money(self.client_id, transaction_info.amount)?;
-        account_storage.withdraw_money(self.client_id, transaction_info.amount)?;
+        match (
+            transitions.holds_funds(transaction_info.status),
+            transaction_info.transaction_type,
+        ) {
+            (true, _) => {
+                account_storage.charge_back_money(self.client_id, transaction_info.amount)?
+            }
+            (false, TransactionInfoType::Deposit) => {
+                hold_disputed_funds(account_storage, self.client_id, &transaction_info)?;
+                account_storage.charge_back_money(self.client_id, transaction_info.amount)?;
+            }
+            // released withdrawal hold is already gone
+            (false, TransactionInfoType::Withdrawal) => {}
+        };
         account_storage.block_account(self.client_id)?;
+        history.update_transaction_status(
+            self.transaction_id,
+            TransactionStatus::Chargebacked,
+            &self.meta,
+        )?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Representment {
+    client_id: ClientId,
+    transaction_id: TransactionId,
+    meta: TransactionMeta,
+}
+
+impl ExecTransaction for Representment {
+    fn execute(
+        &self,
+        account_storage: &impl AccountStorage,
+        history: &impl TransactionHistoryStorage,
+    ) -> Result<(), Box<dyn Error>> {
+        let transaction_info = match history.find_transaction(self.transaction_id) {
+            Some(transaction) => transaction,
+            None => {
+                warn!("Can't find transaction for representment");
+                return Err(Box::new(TransactionError::OriginTransactionNotFound));
+            }
+        };
+        if !history
+            .transitions()
+            .is_transition_available(transaction_info.status, TransactionStatus::Represented)
+        {
+            warn!("Original transaction not in chargebacked state");
+            return Err(Box::new(TransactionError::TransactionNotChargebacked));
+        }
+        // the chargeback of a withdrawal whose hold was released debited nothing, see Chargeback
+        let charged_back_from = history
+            .status_changes(Some(self.transaction_id))?
+            .into_iter()
+            .rev()
+            .find(|status_change| status_change.new_status == TransactionStatus::Chargebacked)
+            .map(|status_change| status_change.previous_status);
+        let debited = match (
+            charged_back_from.map(|status| history.transitions().holds_funds(status)),
+            transaction_info.transaction_type,
+        ) {
+            (Some(false), TransactionInfoType::Withdrawal) => Decimal::ZERO,
+            _ => transaction_info.amount,
+        };
+        account_storage.reverse_chargeback(self.client_id, debited)?;
+        history.update_transaction_status(
+            self.transaction_id,
+            TransactionStatus::Represented,
+            &self.meta,
+        )?;
         Ok(())
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub struct Escalation {
+    client_id: ClientId,
+    transaction_id: TransactionId,
+    stage: TransactionStatus,
+    meta: TransactionMeta,
+}
+
+impl ExecTransaction for Escalation {
+    fn execute(
+        &self,
+        account_storage: &impl AccountStorage,
+        history: &impl TransactionHistoryStorage,
+    ) -> Result<(), Box<dyn Error>> {
+        let transaction_info = match history.find_transaction(self.transaction_id) {
+            Some(transaction) => transaction,
+            None => {
+                warn!("Can't find transaction for escalation");
+                return Err(Box::new(TransactionError::OriginTransactionNotFound));
+            }
+        };
+        let transitions = history.transitions();
+        if !transitions.is_transition_available(transaction_info.status, self.stage) {
+            warn!(
+                "Original transaction can't be escalated to {:?}",
+                self.stage
+            );
+            return Err(Box::new(TransactionError::EscalationNotAllowed));
+        }
+        match (
+            transitions.holds_funds(transaction_info.status),
+            transitions.holds_funds(self.stage),
+        ) {
+            (true, false) => {
+                release_disputed_funds(account_storage, self.client_id, &transaction_info)?
+            }
+            (false, true) => {
+                hold_disputed_funds(account_storage, self.client_id, &transaction_info)?
+            }
+            _ => {}
+        };
+        history.update_transaction_status(self.transaction_id, self.stage, &self.meta)?;
+        Ok(())
+    }
+}
+
+fn hold_disputed_funds(
+    account_storage: &impl AccountStorage,
+    client_id: ClientId,
+    transaction_info: &TransactionInfo,
+) -> Result<(), Box<dyn Error>> {
+    match transaction_info.transaction_type {
+        TransactionInfoType::Deposit => {
+            //TODO: maybe account should be blocked if it hasn't got enough money to be held
+            account_storage.hold_money(client_id, transaction_info.amount)
+        }
+        TransactionInfoType::Withdrawal => {
+            account_storage.add_held_money(client_id, transaction_info.amount)
+        }
+    }
+}
+
+// reverts hold_disputed_funds, balances end up as before the dispute was opened
+fn release_disputed_funds(
+    account_storage: &impl AccountStorage,
+    client_id: ClientId,
+    transaction_info: &TransactionInfo,
+) -> Result<(), Box<dyn Error>> {
+    match transaction_info.transaction_type {
+        TransactionInfoType::Deposit => {
+            account_storage.unhold_money(client_id, transaction_info.amount)
+        }
+        TransactionInfoType::Withdrawal => {
+            account_storage.charge_back_money(client_id, transaction_info.amount)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct OpenAccount {
+    client_id: ClientId,
+}
+
+impl ExecTransaction for OpenAccount {
+    fn execute(
+        &self,
+        account_storage: &impl AccountStorage,
+        _history: &impl TransactionHistoryStorage,
+    ) -> Result<(), Box<dyn Error>> {
+        account_storage.open_account(self.client_id)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct UpdateAccountStatus {
+    client_id: ClientId,
+    new_status: AccountStatus,
+}
+
+impl ExecTransaction for UpdateAccountStatus {
+    fn execute(
+        &self,
+        account_storage: &impl AccountStorage,
+        _history: &impl TransactionHistoryStorage,
+    ) -> Result<(), Box<dyn Error>> {
+        account_storage.update_account_status(self.client_id, self.new_status)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
+        dispute_lifecycle::{HoldPolicy, TransitionTable},
         errors::{AccountError, TransactionError},
-        history::{InMemoryTransactionStorage, TransactionHistoryStorage},
+        history::{DisputeWindow, InMemoryTransactionStorage, TransactionHistoryStorage},
         storage::{AccountStorage, InMemoryAccountsStorage},
         transactions_processor::{TransactionInfo, TransactionInfoType, TransactionStatus},
     };
@@ -281,6 +608,9 @@ mod tests {
             amount,
             status,
             transaction_type,
+            sequence_number: 0,
+            timestamp: None,
+            dispute_reason: None,
         };
         history.add_transaction(transaction_info).unwrap();
     }
@@ -291,6 +621,13 @@ mod tests {
     #[case("dispute", None, 3, 300)]
     #[case("resolve", None, 4, 400)]
     #[case("chargeback", None, 5, 500)]
+    #[case("representment", None, 5, 500)]
+    #[case("prearbitration", None, 5, 500)]
+    #[case("arbitration", None, 5, 500)]
+    #[case("open", None, 6, 0)]
+    #[case("activate", None, 7, 0)]
+    #[case("freeze", None, 8, 0)]
+    #[case("close", None, 9, 0)]
     fn test_try_from_all_valid_types(
         #[case] transaction_type: &str,
         #[case] amount: Option<Decimal>,
@@ -302,6 +639,9 @@ mod tests {
             client_id,
             transaction_id,
             amount,
+            timestamp: None,
+            reason: None,
+            origin: None,
         };
 
         let result = Transaction::try_from(&entry);
@@ -315,6 +655,10 @@ mod tests {
             ("dispute", Transaction::Dispute(_)) => {}
             ("resolve", Transaction::Resolve(_)) => {}
             ("chargeback", Transaction::Chargeback(_)) => {}
+            ("representment", Transaction::Representment(_)) => {}
+            ("prearbitration" | "arbitration", Transaction::Escalation(_)) => {}
+            ("open", Transaction::OpenAccount(_)) => {}
+            ("activate" | "freeze" | "close", Transaction::UpdateAccountStatus(_)) => {}
             _ => panic!("Unexpected transaction type for {}", transaction_type),
         }
     }
@@ -331,6 +675,7 @@ mod tests {
             client_id,
             transaction_id,
             amount,
+            meta: TransactionMeta::default(),
         };
 
         assert_eq!(account_storage.get_balance(client_id), None);
@@ -367,6 +712,7 @@ mod tests {
             client_id,
             transaction_id,
             amount: negative_amount,
+            meta: TransactionMeta::default(),
         };
         let result = deposit.execute(&storage, &history);
 
@@ -396,6 +742,7 @@ mod tests {
             client_id,
             transaction_id: 100,
             amount,
+            meta: TransactionMeta::default(),
         };
 
         let result = deposit.execute(&account_storage, &history);
@@ -425,6 +772,7 @@ mod tests {
             client_id,
             transaction_id,
             amount: withdrawal_amount,
+            meta: TransactionMeta::default(),
         };
 
         assert_eq!(account_storage.get_balance(client_id), Some(initial_amount));
@@ -464,6 +812,7 @@ mod tests {
             client_id,
             transaction_id,
             amount: negative_amount,
+            meta: TransactionMeta::default(),
         };
         let result = withdrawal.execute(&storage, &history);
 
@@ -496,6 +845,7 @@ mod tests {
             client_id,
             transaction_id,
             amount: withdrawal_amount,
+            meta: TransactionMeta::default(),
         };
 
         let result = withdrawal.execute(&account_storage, &history);
@@ -521,6 +871,7 @@ mod tests {
             client_id,
             transaction_id,
             amount,
+            meta: TransactionMeta::default(),
         };
 
         let result = withdrawal.execute(&account_storage, &history);
@@ -548,6 +899,7 @@ mod tests {
             client_id,
             transaction_id,
             amount,
+            meta: TransactionMeta::default(),
         };
 
         let result = withdrawal.execute(&account_storage, &history);
@@ -581,6 +933,8 @@ mod tests {
         let dispute = Dispute {
             client_id,
             transaction_id,
+            reason: None,
+            meta: TransactionMeta::default(),
         };
 
         assert_eq!(account_storage.get_balance(client_id), Some(amount));
@@ -628,6 +982,8 @@ mod tests {
         let dispute = Dispute {
             client_id,
             transaction_id,
+            reason: None,
+            meta: TransactionMeta::default(),
         };
 
         assert_eq!(
@@ -659,6 +1015,8 @@ mod tests {
         let dispute = Dispute {
             client_id,
             transaction_id: nonexistent_transaction_id,
+            reason: None,
+            meta: TransactionMeta::default(),
         };
 
         let result = dispute.execute(&account_storage, &history);
@@ -695,6 +1053,8 @@ mod tests {
         let dispute = Dispute {
             client_id,
             transaction_id,
+            reason: None,
+            meta: TransactionMeta::default(),
         };
 
         let result = dispute.execute(&account_storage, &history);
@@ -711,6 +1071,55 @@ mod tests {
         assert_eq!(transaction_info.status, existing_status);
     }
 
+    #[rstest]
+    #[case(15, true)]
+    #[case(16, false)]
+    fn test_dispute_execute_dispute_window(
+        #[case] dispute_sequence_number: SequenceNumber,
+        #[case] should_be_accepted: bool,
+    ) {
+        let account_storage = InMemoryAccountsStorage::new();
+        let history =
+            InMemoryTransactionStorage::new().with_dispute_window(DisputeWindow::Transactions(10));
+        let client_id = 1;
+        let transaction_id = 100;
+        let amount = dec!(50.00);
+
+        let deposit = Deposit {
+            client_id,
+            transaction_id,
+            amount,
+            meta: TransactionMeta {
+                sequence_number: 5,
+                timestamp: None,
+            },
+        };
+        deposit.execute(&account_storage, &history).unwrap();
+
+        let dispute = Dispute {
+            client_id,
+            transaction_id,
+            reason: None,
+            meta: TransactionMeta {
+                sequence_number: dispute_sequence_number,
+                timestamp: None,
+            },
+        };
+        let result = dispute.execute(&account_storage, &history);
+
+        let transaction_info = history.find_transaction(transaction_id).unwrap();
+        if should_be_accepted {
+            assert!(result.is_ok());
+            assert_eq!(transaction_info.status, TransactionStatus::Disputed);
+        } else {
+            let error = result.unwrap_err();
+            let transaction_error = error.downcast_ref::<TransactionError>().unwrap();
+            assert_eq!(*transaction_error, TransactionError::DisputeWindowExpired);
+            assert_eq!(transaction_info.status, TransactionStatus::WithoutDisputes);
+            assert_eq!(account_storage.get_balance(client_id), Some(amount));
+        }
+    }
+
     #[test]
     fn test_dispute_execute_deposit_insufficient_funds_to_hold() {
         let account_storage = InMemoryAccountsStorage::new();
@@ -735,6 +1144,8 @@ mod tests {
         let dispute = Dispute {
             client_id,
             transaction_id,
+            reason: None,
+            meta: TransactionMeta::default(),
         };
 
         let result = dispute.execute(&account_storage, &history);
@@ -770,6 +1181,8 @@ mod tests {
         let dispute = Dispute {
             client_id,
             transaction_id,
+            reason: None,
+            meta: TransactionMeta::default(),
         };
 
         let result = dispute.execute(&account_storage, &history);
@@ -803,6 +1216,7 @@ mod tests {
         let resolve = Resolve {
             client_id,
             transaction_id,
+            meta: TransactionMeta::default(),
         };
         let result = resolve.execute(&storage, &history);
 
@@ -840,6 +1254,7 @@ mod tests {
         let resolve = Resolve {
             client_id,
             transaction_id,
+            meta: TransactionMeta::default(),
         };
         let result = resolve.execute(&storage, &history);
 
@@ -866,6 +1281,7 @@ mod tests {
         let resolve = Resolve {
             client_id,
             transaction_id,
+            meta: TransactionMeta::default(),
         };
         let result = resolve.execute(&storage, &history);
 
@@ -893,12 +1309,16 @@ mod tests {
             amount,
             status: TransactionStatus::WithoutDisputes,
             transaction_type: TransactionInfoType::Deposit,
+            sequence_number: 0,
+            timestamp: None,
+            dispute_reason: None,
         };
         history.add_transaction(transaction_info).unwrap();
 
         let resolve = Resolve {
             client_id,
             transaction_id,
+            meta: TransactionMeta::default(),
         };
         let result = resolve.execute(&storage, &history);
 
@@ -923,12 +1343,16 @@ mod tests {
             amount,
             status: TransactionStatus::Resolved,
             transaction_type: TransactionInfoType::Deposit,
+            sequence_number: 0,
+            timestamp: None,
+            dispute_reason: None,
         };
         history.add_transaction(transaction_info).unwrap();
 
         let resolve = Resolve {
             client_id,
             transaction_id,
+            meta: TransactionMeta::default(),
         };
         let result = resolve.execute(&storage, &history);
 
@@ -954,12 +1378,16 @@ mod tests {
             amount,
             status: TransactionStatus::Chargebacked,
             transaction_type: TransactionInfoType::Deposit,
+            sequence_number: 0,
+            timestamp: None,
+            dispute_reason: None,
         };
         history.add_transaction(transaction_info).unwrap();
 
         let resolve = Resolve {
             client_id,
             transaction_id,
+            meta: TransactionMeta::default(),
         };
         let result = resolve.execute(&storage, &history);
 
@@ -990,6 +1418,7 @@ mod tests {
         let resolve = Resolve {
             client_id,
             transaction_id,
+            meta: TransactionMeta::default(),
         };
         let result = resolve.execute(&storage, &history);
 
@@ -1022,6 +1451,7 @@ mod tests {
         let chargeback = Chargeback {
             client_id,
             transaction_id,
+            meta: TransactionMeta::default(),
         };
         let result = chargeback.execute(&storage, &history);
 
@@ -1060,6 +1490,7 @@ mod tests {
         let chargeback = Chargeback {
             client_id,
             transaction_id,
+            meta: TransactionMeta::default(),
         };
         let result = chargeback.execute(&storage, &history);
 
@@ -1087,6 +1518,7 @@ mod tests {
         let chargeback = Chargeback {
             client_id,
             transaction_id,
+            meta: TransactionMeta::default(),
         };
         let result = chargeback.execute(&storage, &history);
 
@@ -1120,6 +1552,7 @@ mod tests {
         let chargeback = Chargeback {
             client_id,
             transaction_id,
+            meta: TransactionMeta::default(),
         };
         let result = chargeback.execute(&storage, &history);
 
@@ -1150,6 +1583,7 @@ mod tests {
         let chargeback = Chargeback {
             client_id,
             transaction_id,
+            meta: TransactionMeta::default(),
         };
         let result = chargeback.execute(&storage, &history);
 
@@ -1180,6 +1614,7 @@ mod tests {
         let chargeback = Chargeback {
             client_id,
             transaction_id,
+            meta: TransactionMeta::default(),
         };
         let result = chargeback.execute(&storage, &history);
 
@@ -1211,6 +1646,7 @@ mod tests {
         let chargeback = Chargeback {
             client_id,
             transaction_id,
+            meta: TransactionMeta::default(),
         };
         let result = chargeback.execute(&storage, &history);
 
@@ -1243,6 +1679,7 @@ mod tests {
         let chargeback = Chargeback {
             client_id,
             transaction_id,
+            meta: TransactionMeta::default(),
         };
         let result = chargeback.execute(&storage, &history);
 
@@ -1251,4 +1688,515 @@ mod tests {
         let transaction_error = error.downcast_ref::<AccountError>().unwrap();
         assert_eq!(*transaction_error, AccountError::InsufficientMoney);
     }
+
+    #[test]
+    fn test_chargeback_frozen_account() {
+        let account_storage = InMemoryAccountsStorage::new();
+        let history = InMemoryTransactionStorage::new();
+        let client_id = 1;
+        let transaction_id = 100;
+        let amount = dec!(40.0);
+
+        account_storage.add_money(client_id, dec!(100.0)).unwrap();
+        account_storage.hold_money(client_id, amount).unwrap();
+        account_storage
+            .update_account_status(client_id, AccountStatus::Frozen)
+            .unwrap();
+        create_transaction_in_history(
+            &history,
+            transaction_id,
+            client_id,
+            amount,
+            TransactionInfoType::Deposit,
+            TransactionStatus::Disputed,
+        );
+
+        let chargeback = Chargeback {
+            client_id,
+            transaction_id,
+            meta: TransactionMeta::default(),
+        };
+        chargeback.execute(&account_storage, &history).unwrap();
+
+        let accounts = account_storage.accounts.read().unwrap();
+        let account = accounts.get(&client_id).unwrap();
+        assert_eq!(account.available_balance(), dec!(60.0));
+        assert_eq!(account.held_balance(), Decimal::ZERO);
+        assert_eq!(account.status(), AccountStatus::Locked);
+    }
+
+    #[rstest]
+    #[case(AccountStatus::Active, None)]
+    #[case(AccountStatus::Frozen, None)]
+    #[case(AccountStatus::Closed, Some(AccountError::NonZeroBalance))]
+    fn test_update_account_status_execute(
+        #[case] new_status: AccountStatus,
+        #[case] expected_error: Option<AccountError>,
+    ) {
+        let account_storage = InMemoryAccountsStorage::new();
+        let history = InMemoryTransactionStorage::new();
+        let client_id = 1;
+
+        OpenAccount { client_id }
+            .execute(&account_storage, &history)
+            .unwrap();
+        account_storage.add_money(client_id, dec!(1)).unwrap();
+        if new_status == AccountStatus::Frozen {
+            account_storage
+                .update_account_status(client_id, AccountStatus::Active)
+                .unwrap();
+        }
+
+        let result = UpdateAccountStatus {
+            client_id,
+            new_status,
+        }
+        .execute(&account_storage, &history);
+
+        match expected_error {
+            None => assert_eq!(account_storage.get_status(client_id), Some(new_status)),
+            Some(expected_error) => {
+                let error = result.unwrap_err();
+                let account_error = error.downcast_ref::<AccountError>().unwrap();
+                assert_eq!(*account_error, expected_error);
+            }
+        }
+    }
+
+    // the chargeback is made first, representment re-credits what it debited
+    #[rstest]
+    #[case(TransactionInfoType::Deposit, HoldPolicy::Hold, dec!(40.0))]
+    #[case(TransactionInfoType::Deposit, HoldPolicy::Release, dec!(40.0))]
+    #[case(TransactionInfoType::Withdrawal, HoldPolicy::Hold, dec!(40.0))]
+    #[case(TransactionInfoType::Withdrawal, HoldPolicy::Release, dec!(0))]
+    fn test_representment_successful_for_chargebacked_transaction(
+        #[case] transaction_type: TransactionInfoType,
+        #[case] arbitration_policy: HoldPolicy,
+        #[case] re_credited: Decimal,
+    ) {
+        let transitions = TransitionTable::default()
+            .with_hold_policy(TransactionStatus::Arbitration, arbitration_policy);
+        let storage = InMemoryAccountsStorage::new();
+        let history = InMemoryTransactionStorage::new().with_transitions(transitions);
+        let client_id = 1;
+        let transaction_id = 100;
+        let amount = dec!(40.0);
+
+        storage.create_user(client_id);
+        storage.add_money(client_id, amount).unwrap();
+        if arbitration_policy == HoldPolicy::Hold {
+            match transaction_type {
+                TransactionInfoType::Deposit => storage.hold_money(client_id, amount).unwrap(),
+                TransactionInfoType::Withdrawal => {
+                    storage.add_held_money(client_id, amount).unwrap()
+                }
+            }
+        }
+        create_transaction_in_history(
+            &history,
+            transaction_id,
+            client_id,
+            amount,
+            transaction_type,
+            TransactionStatus::Arbitration,
+        );
+        Chargeback {
+            client_id,
+            transaction_id,
+            meta: TransactionMeta::default(),
+        }
+        .execute(&storage, &history)
+        .unwrap();
+        let charged_back = storage.accounts.read().unwrap()[&client_id].available_balance();
+
+        let representment = Representment {
+            client_id,
+            transaction_id,
+            meta: TransactionMeta::default(),
+        };
+        let result = representment.execute(&storage, &history);
+
+        assert!(result.is_ok());
+
+        let transaction_info = history.find_transaction(transaction_id).unwrap();
+        assert_eq!(transaction_info.status, TransactionStatus::Represented);
+
+        let accounts = storage.accounts.read().unwrap();
+        let account = accounts.get(&client_id).unwrap();
+        assert_eq!(account.available_balance(), charged_back + re_credited);
+        assert_eq!(account.held_balance(), dec!(0));
+        assert!(account.is_locked());
+    }
+
+    #[test]
+    fn test_representment_transaction_not_found() {
+        let storage = InMemoryAccountsStorage::new();
+        let history = InMemoryTransactionStorage::new();
+        let client_id = 1;
+
+        storage.create_user(client_id);
+
+        let representment = Representment {
+            client_id,
+            transaction_id: 999,
+            meta: TransactionMeta::default(),
+        };
+        let result = representment.execute(&storage, &history);
+
+        let error = result.unwrap_err();
+        let transaction_error = error.downcast_ref::<TransactionError>().unwrap();
+        assert_eq!(
+            *transaction_error,
+            TransactionError::OriginTransactionNotFound
+        );
+    }
+
+    #[rstest]
+    #[case(TransactionStatus::WithoutDisputes)]
+    #[case(TransactionStatus::Disputed)]
+    #[case(TransactionStatus::Resolved)]
+    #[case(TransactionStatus::Represented)]
+    fn test_representment_transaction_not_chargebacked(#[case] existing_status: TransactionStatus) {
+        let storage = InMemoryAccountsStorage::new();
+        let history = InMemoryTransactionStorage::new();
+        let client_id = 1;
+        let transaction_id = 100;
+        let amount = dec!(25.0);
+
+        storage.create_user(client_id);
+        create_transaction_in_history(
+            &history,
+            transaction_id,
+            client_id,
+            amount,
+            TransactionInfoType::Deposit,
+            existing_status,
+        );
+
+        let representment = Representment {
+            client_id,
+            transaction_id,
+            meta: TransactionMeta::default(),
+        };
+        let result = representment.execute(&storage, &history);
+
+        let error = result.unwrap_err();
+        let transaction_error = error.downcast_ref::<TransactionError>().unwrap();
+        assert_eq!(
+            *transaction_error,
+            TransactionError::TransactionNotChargebacked
+        );
+        let transaction_info = history.find_transaction(transaction_id).unwrap();
+        assert_eq!(transaction_info.status, existing_status);
+        assert_eq!(storage.get_balance(client_id), Some(dec!(0)));
+    }
+
+    #[test]
+    fn test_representment_closed_account() {
+        let storage = InMemoryAccountsStorage::new();
+        let history = InMemoryTransactionStorage::new();
+        let client_id = 1;
+        let transaction_id = 100;
+
+        storage.create_user(client_id);
+        storage
+            .update_account_status(client_id, AccountStatus::Closed)
+            .unwrap();
+        create_transaction_in_history(
+            &history,
+            transaction_id,
+            client_id,
+            dec!(10),
+            TransactionInfoType::Deposit,
+            TransactionStatus::Chargebacked,
+        );
+
+        let representment = Representment {
+            client_id,
+            transaction_id,
+            meta: TransactionMeta::default(),
+        };
+        let result = representment.execute(&storage, &history);
+
+        let error = result.unwrap_err();
+        let account_error = error.downcast_ref::<AccountError>().unwrap();
+        assert_eq!(*account_error, AccountError::AccountClosed);
+        let transaction_info = history.find_transaction(transaction_id).unwrap();
+        assert_eq!(transaction_info.status, TransactionStatus::Chargebacked);
+    }
+
+    #[rstest]
+    #[case(TransactionStatus::Disputed, TransactionStatus::PreArbitration)]
+    #[case(TransactionStatus::PreArbitration, TransactionStatus::Arbitration)]
+    fn test_escalation_keeps_funds_held(
+        #[case] existing_status: TransactionStatus,
+        #[case] stage: TransactionStatus,
+    ) {
+        let storage = InMemoryAccountsStorage::new();
+        let history = InMemoryTransactionStorage::new();
+        let client_id = 1;
+        let transaction_id = 100;
+        let amount = dec!(20.0);
+
+        storage.create_user(client_id);
+        storage.add_money(client_id, amount * dec!(2)).unwrap();
+        storage.hold_money(client_id, amount).unwrap();
+        create_transaction_in_history(
+            &history,
+            transaction_id,
+            client_id,
+            amount,
+            TransactionInfoType::Deposit,
+            existing_status,
+        );
+
+        let escalation = Escalation {
+            client_id,
+            transaction_id,
+            stage,
+            meta: TransactionMeta::default(),
+        };
+        let result = escalation.execute(&storage, &history);
+
+        assert!(result.is_ok());
+        let transaction_info = history.find_transaction(transaction_id).unwrap();
+        assert_eq!(transaction_info.status, stage);
+
+        let accounts = storage.accounts.read().unwrap();
+        let account = accounts.get(&client_id).unwrap();
+        assert_eq!(account.available_balance(), amount);
+        assert_eq!(account.held_balance(), amount);
+    }
+
+    #[rstest]
+    #[case(TransactionStatus::WithoutDisputes, TransactionStatus::PreArbitration)]
+    #[case(TransactionStatus::Disputed, TransactionStatus::Arbitration)]
+    #[case(TransactionStatus::Arbitration, TransactionStatus::PreArbitration)]
+    #[case(TransactionStatus::Chargebacked, TransactionStatus::PreArbitration)]
+    #[case(TransactionStatus::Resolved, TransactionStatus::Arbitration)]
+    fn test_escalation_not_allowed(
+        #[case] existing_status: TransactionStatus,
+        #[case] stage: TransactionStatus,
+    ) {
+        let storage = InMemoryAccountsStorage::new();
+        let history = InMemoryTransactionStorage::new();
+        let client_id = 1;
+        let transaction_id = 100;
+
+        storage.create_user(client_id);
+        create_transaction_in_history(
+            &history,
+            transaction_id,
+            client_id,
+            dec!(10),
+            TransactionInfoType::Deposit,
+            existing_status,
+        );
+
+        let escalation = Escalation {
+            client_id,
+            transaction_id,
+            stage,
+            meta: TransactionMeta::default(),
+        };
+        let result = escalation.execute(&storage, &history);
+
+        let error = result.unwrap_err();
+        let transaction_error = error.downcast_ref::<TransactionError>().unwrap();
+        assert_eq!(*transaction_error, TransactionError::EscalationNotAllowed);
+        let transaction_info = history.find_transaction(transaction_id).unwrap();
+        assert_eq!(transaction_info.status, existing_status);
+    }
+
+    #[rstest]
+    #[case(TransactionInfoType::Deposit, dec!(30), dec!(0))]
+    #[case(TransactionInfoType::Withdrawal, dec!(30), dec!(0))]
+    fn test_escalation_releases_funds(
+        #[case] transaction_type: TransactionInfoType,
+        #[case] expected_available: Decimal,
+        #[case] expected_held: Decimal,
+    ) {
+        let storage = InMemoryAccountsStorage::new();
+        let transitions = TransitionTable::default()
+            .with_hold_policy(TransactionStatus::PreArbitration, HoldPolicy::Release);
+        let history = InMemoryTransactionStorage::new().with_transitions(transitions);
+        let client_id = 1;
+        let transaction_id = 100;
+        let amount = dec!(10);
+
+        storage.create_user(client_id);
+        storage.add_money(client_id, dec!(30)).unwrap();
+        create_transaction_in_history(
+            &history,
+            transaction_id,
+            client_id,
+            amount,
+            transaction_type,
+            TransactionStatus::WithoutDisputes,
+        );
+        let dispute = Dispute {
+            client_id,
+            transaction_id,
+            reason: None,
+            meta: TransactionMeta::default(),
+        };
+        dispute.execute(&storage, &history).unwrap();
+
+        let escalation = Escalation {
+            client_id,
+            transaction_id,
+            stage: TransactionStatus::PreArbitration,
+            meta: TransactionMeta::default(),
+        };
+        escalation.execute(&storage, &history).unwrap();
+
+        let accounts = storage.accounts.read().unwrap();
+        let account = accounts.get(&client_id).unwrap();
+        assert_eq!(account.available_balance(), expected_available);
+        assert_eq!(account.held_balance(), expected_held);
+    }
+
+    #[rstest]
+    #[case(TransactionInfoType::Deposit, dec!(30), dec!(20))]
+    #[case(TransactionInfoType::Withdrawal, dec!(40), dec!(30))]
+    fn test_resolve_and_chargeback_after_released_stage(
+        #[case] transaction_type: TransactionInfoType,
+        #[case] expected_available_after_resolve: Decimal,
+        #[case] expected_available_after_chargeback: Decimal,
+    ) {
+        let transitions = TransitionTable::default()
+            .with_hold_policy(TransactionStatus::Arbitration, HoldPolicy::Release);
+        let client_id = 1;
+        let amount = dec!(10);
+
+        for (resolution, expected_available) in [
+            (
+                TransactionStatus::Resolved,
+                expected_available_after_resolve,
+            ),
+            (
+                TransactionStatus::Chargebacked,
+                expected_available_after_chargeback,
+            ),
+        ] {
+            let storage = InMemoryAccountsStorage::new();
+            let history = InMemoryTransactionStorage::new().with_transitions(transitions.clone());
+            let transaction_id = 100;
+            storage.create_user(client_id);
+            storage.add_money(client_id, dec!(30)).unwrap();
+            create_transaction_in_history(
+                &history,
+                transaction_id,
+                client_id,
+                amount,
+                transaction_type,
+                TransactionStatus::Arbitration,
+            );
+
+            let result = match resolution {
+                TransactionStatus::Resolved => Resolve {
+                    client_id,
+                    transaction_id,
+                    meta: TransactionMeta::default(),
+                }
+                .execute(&storage, &history),
+                _ => Chargeback {
+                    client_id,
+                    transaction_id,
+                    meta: TransactionMeta::default(),
+                }
+                .execute(&storage, &history),
+            };
+
+            assert!(result.is_ok());
+            let transaction_info = history.find_transaction(transaction_id).unwrap();
+            assert_eq!(transaction_info.status, resolution);
+            let accounts = storage.accounts.read().unwrap();
+            let account = accounts.get(&client_id).unwrap();
+            assert_eq!(account.available_balance(), expected_available);
+            assert_eq!(account.held_balance(), dec!(0));
+        }
+    }
+
+    #[test]
+    fn test_chargeback_after_released_stage_without_funds_keeps_status() {
+        let transitions = TransitionTable::default()
+            .with_hold_policy(TransactionStatus::Arbitration, HoldPolicy::Release);
+        let storage = InMemoryAccountsStorage::new();
+        let history = InMemoryTransactionStorage::new().with_transitions(transitions);
+        let client_id = 1;
+        let transaction_id = 100;
+        storage.create_user(client_id);
+        storage.add_money(client_id, dec!(5)).unwrap();
+        create_transaction_in_history(
+            &history,
+            transaction_id,
+            client_id,
+            dec!(10),
+            TransactionInfoType::Deposit,
+            TransactionStatus::Arbitration,
+        );
+
+        let result = Chargeback {
+            client_id,
+            transaction_id,
+            meta: TransactionMeta::default(),
+        }
+        .execute(&storage, &history);
+
+        let error = result.unwrap_err();
+        let account_error = error.downcast_ref::<AccountError>().unwrap();
+        assert_eq!(*account_error, AccountError::InsufficientMoney);
+        let transaction_info = history.find_transaction(transaction_id).unwrap();
+        assert_eq!(transaction_info.status, TransactionStatus::Arbitration);
+        assert!(
+            history
+                .status_changes(Some(transaction_id))
+                .unwrap()
+                .is_empty()
+        );
+        let accounts = storage.accounts.read().unwrap();
+        let account = accounts.get(&client_id).unwrap();
+        assert_eq!(account.available_balance(), dec!(5));
+        assert_eq!(account.held_balance(), dec!(0));
+        assert!(!account.is_locked());
+    }
+
+    #[rstest]
+    #[case(TransactionStatus::PreArbitration)]
+    #[case(TransactionStatus::Arbitration)]
+    fn test_resolve_successful_for_escalated_dispute(#[case] existing_status: TransactionStatus) {
+        let storage = InMemoryAccountsStorage::new();
+        let history = InMemoryTransactionStorage::new();
+        let client_id = 1;
+        let transaction_id = 100;
+        let amount = dec!(10);
+
+        storage.create_user(client_id);
+        storage.add_money(client_id, amount).unwrap();
+        storage.hold_money(client_id, amount).unwrap();
+        create_transaction_in_history(
+            &history,
+            transaction_id,
+            client_id,
+            amount,
+            TransactionInfoType::Deposit,
+            existing_status,
+        );
+
+        let resolve = Resolve {
+            client_id,
+            transaction_id,
+            meta: TransactionMeta::default(),
+        };
+        let result = resolve.execute(&storage, &history);
+
+        assert!(result.is_ok());
+        let transaction_info = history.find_transaction(transaction_id).unwrap();
+        assert_eq!(transaction_info.status, TransactionStatus::Resolved);
+        let accounts = storage.accounts.read().unwrap();
+        let account = accounts.get(&client_id).unwrap();
+        assert_eq!(account.available_balance(), amount);
+        assert_eq!(account.held_balance(), dec!(0));
+    }
 }
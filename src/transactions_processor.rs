@@ -1,54 +1,51 @@
-use std::error::Error;
+use std::{
+    error::Error,
+    fmt,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Instant,
+};
 
 use rust_decimal::Decimal;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, error_span};
 
 use crate::{
-    errors::TransactionHistoryError,
-    history::InMemoryTransactionStorage,
+    dispute_reasons::{DisputeReason, DisputeReport},
+    held_funds::HeldDisputes,
+    history::{InMemoryTransactionStorage, TransactionHistoryStorage},
+    metrics::Metrics,
     storage::{ClientId, InMemoryAccountsStorage},
-    transactions::{ExecTransaction, Transaction, TransactionId},
+    transactions::{
+        ExecTransaction, SequenceNumber, Timestamp, Transaction, TransactionId, TransactionMeta,
+    },
 };
 
-#[derive(Debug, PartialEq, Copy, Clone)]
+// rows between two drops of the transactions that left the dispute window
+const EXPIRY_INTERVAL: SequenceNumber = 10_000;
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TransactionStatus {
     WithoutDisputes,
     Resolved,
     Disputed,
+    PreArbitration,
+    Arbitration,
     Chargebacked,
+    Represented,
 }
 
-impl TransactionStatus {
-    fn is_transition_available(self, new_status: &TransactionStatus) -> bool {
-        matches!(
-            (self, new_status),
-            (
-                TransactionStatus::WithoutDisputes,
-                TransactionStatus::Disputed
-            ) | (TransactionStatus::Disputed, TransactionStatus::Chargebacked)
-                | (TransactionStatus::Disputed, TransactionStatus::Resolved)
-        )
-    }
-
-    pub fn make_transition(
-        self,
-        new_status: TransactionStatus,
-    ) -> Result<TransactionStatus, Box<dyn Error>> {
-        if self.is_transition_available(&new_status) {
-            Ok(new_status)
-        } else {
-            Err(Box::new(TransactionHistoryError::InvalidStatusTransition))
-        }
-    }
-}
-
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TransactionInfoType {
     Deposit,
     Withdrawal,
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct TransactionLogEntry {
     #[serde(rename = "type")]
     pub transaction_type: String,
@@ -58,37 +55,154 @@ pub struct TransactionLogEntry {
     pub transaction_id: TransactionId,
     #[serde(default)]
     pub amount: Option<Decimal>,
+    #[serde(default)]
+    pub timestamp: Option<Timestamp>,
+    #[serde(default)]
+    pub reason: Option<String>,
+    // where the row was read from, set by the reader
+    #[serde(skip)]
+    pub origin: Option<RowOrigin>,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RowOrigin {
+    pub input: Arc<str>,
+    pub line: u64,
+}
+
+impl fmt::Display for RowOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.input, self.line)
+    }
+}
+
+impl fmt::Display for TransactionLogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        write!(
+            f,
+            "{},{},{},{},{},{}",
+            self.transaction_type,
+            self.client_id,
+            self.transaction_id,
+            optional(self.amount.map(|amount| amount.to_string())),
+            optional(self.timestamp.map(|timestamp| timestamp.to_string())),
+            optional(self.reason.clone()),
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct TransactionInfo {
     pub client_id: ClientId,
     pub transaction_id: TransactionId,
     pub transaction_type: TransactionInfoType,
     pub amount: Decimal,
     pub status: TransactionStatus,
+    pub sequence_number: SequenceNumber,
+    pub timestamp: Option<Timestamp>,
+    pub dispute_reason: Option<DisputeReason>,
 }
 
 pub trait TransactionProcessor {
     fn process(&self, transaction_entry: TransactionLogEntry) -> Result<(), Box<dyn Error>>;
 }
 
-pub struct InMemoryTransactionProcessor {
+pub struct InMemoryTransactionProcessor<H = InMemoryTransactionStorage> {
     storage: InMemoryAccountsStorage,
-    history: InMemoryTransactionStorage,
+    history: H,
+    last_sequence_number: AtomicU64,
+    latest_timestamp: RwLock<Option<Timestamp>>,
+    dispute_report: DisputeReport,
+    held_disputes: RwLock<HeldDisputes>,
+    metrics: Metrics,
+    expiry_interval: SequenceNumber,
+    // A row checks and then changes accounts and history under separate locks, concurrent rows
+    // could both pass a check before either applies it. Rows are processed one at a time
+    processing: Mutex<()>,
 }
 
 impl InMemoryTransactionProcessor {
     pub fn new() -> Self {
+        Self::with_history(InMemoryTransactionStorage::new())
+    }
+}
+
+impl<H: TransactionHistoryStorage> InMemoryTransactionProcessor<H> {
+    pub fn with_history(history: H) -> Self {
+        Self::with_storages(InMemoryAccountsStorage::new(), history)
+    }
+
+    pub fn with_storages(storage: InMemoryAccountsStorage, history: H) -> Self {
         Self {
-            storage: InMemoryAccountsStorage::new(),
-            history: InMemoryTransactionStorage::new(),
+            storage,
+            history,
+            last_sequence_number: AtomicU64::new(0),
+            latest_timestamp: RwLock::new(None),
+            dispute_report: DisputeReport::new(),
+            held_disputes: RwLock::new(HeldDisputes::default()),
+            metrics: Metrics::default(),
+            expiry_interval: EXPIRY_INTERVAL,
+            processing: Mutex::new(()),
         }
     }
 
+    pub fn with_expiry_interval(mut self, expiry_interval: SequenceNumber) -> Self {
+        self.expiry_interval = expiry_interval.max(1);
+        self
+    }
+
     pub fn get_accounts_storage(&self) -> &InMemoryAccountsStorage {
         &self.storage
     }
+
+    pub fn get_history(&self) -> &H {
+        &self.history
+    }
+
+    pub fn get_dispute_report(&self) -> &DisputeReport {
+        &self.dispute_report
+    }
+
+    pub fn held_disputes(&self) -> HeldDisputes {
+        *self.held_disputes.read().unwrap()
+    }
+
+    pub fn restore_held_disputes(&self, held_disputes: HeldDisputes) {
+        *self.held_disputes.write().unwrap() = held_disputes;
+    }
+
+    pub fn get_metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    // Processes the rows in order without rows of other callers in between
+    pub fn process_batch(
+        &self,
+        transaction_entries: Vec<TransactionLogEntry>,
+    ) -> Vec<Result<(), Box<dyn Error>>> {
+        let _processing = self.processing.lock().unwrap();
+        transaction_entries
+            .into_iter()
+            .map(|transaction_entry| self.process_row(transaction_entry))
+            .collect()
+    }
+
+    // continues numbering rows after a run that processed rows up to `meta`
+    pub fn restore_meta(&self, meta: TransactionMeta) {
+        self.last_sequence_number
+            .store(meta.sequence_number, Ordering::SeqCst);
+        *self.latest_timestamp.write().unwrap() = meta.timestamp;
+    }
+
+    // position of the last processed row and the latest timestamp seen so far,
+    // rejected rows included
+    pub fn current_meta(&self) -> TransactionMeta {
+        TransactionMeta {
+            sequence_number: self.last_sequence_number.load(Ordering::SeqCst),
+            timestamp: *self.latest_timestamp.read().unwrap(),
+        }
+    }
 }
 
 impl Default for InMemoryTransactionProcessor {
@@ -97,18 +211,87 @@ impl Default for InMemoryTransactionProcessor {
     }
 }
 
-impl TransactionProcessor for InMemoryTransactionProcessor {
-    fn process(&self, transaction_entry: TransactionLogEntry) -> Result<(), Box<dyn Error>> {
-        let transaction = Transaction::try_from(&transaction_entry)?;
+impl<H: TransactionHistoryStorage> InMemoryTransactionProcessor<H> {
+    fn process_entry(&self, transaction_entry: &TransactionLogEntry) -> Result<(), Box<dyn Error>> {
+        let sequence_number = self.last_sequence_number.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Some(timestamp) = transaction_entry.timestamp {
+            let mut latest_timestamp = self.latest_timestamp.write().unwrap();
+            *latest_timestamp = latest_timestamp.max(Some(timestamp));
+        }
+        let transaction = Transaction::from_entry(transaction_entry, sequence_number)?;
+        let transitions = self.history.transitions();
+        let held_before = transaction
+            .disputed_transaction_id()
+            .and_then(|transaction_id| self.history.find_transaction(transaction_id))
+            .is_some_and(|transaction_info| transitions.holds_funds(transaction_info.status));
         transaction.execute(&self.storage, &self.history)?;
+        if let Some(transaction_id) = transaction.disputed_transaction_id()
+            && let Some(transaction_info) = self.history.find_transaction(transaction_id)
+        {
+            self.dispute_report.record(&transaction_info);
+            self.held_disputes.write().unwrap().update(
+                transaction_info.amount,
+                held_before,
+                transitions.holds_funds(transaction_info.status),
+            );
+        }
         Ok(())
     }
+
+    // Every log line of the row is attributed to it by the span fields. The span is at error level
+    // so it is kept with any log level
+    fn process_row(&self, transaction_entry: TransactionLogEntry) -> Result<(), Box<dyn Error>> {
+        let origin = transaction_entry.origin.as_ref();
+        let span = error_span!(
+            "process",
+            client = transaction_entry.client_id,
+            tx = transaction_entry.transaction_id,
+            "type" = %transaction_entry.transaction_type,
+            input = origin.map(|origin| &*origin.input),
+            line = origin.map(|origin| origin.line),
+        );
+        let _entered = span.enter();
+        let started = Instant::now();
+        let result = self.process_entry(&transaction_entry);
+        self.drop_expired();
+        self.metrics.record(
+            &transaction_entry.transaction_type,
+            result.as_ref().err().map(AsRef::as_ref),
+            started.elapsed(),
+        );
+        result
+    }
+
+    // Runs every `expiry_interval` rows, counted by sequence number so a replay of the same rows
+    // drops the same transactions. A failed drop keeps the records and doesn't reject the row
+    fn drop_expired(&self) {
+        let now = self.current_meta();
+        if self.history.dispute_window().is_none()
+            || !now.sequence_number.is_multiple_of(self.expiry_interval)
+        {
+            return;
+        }
+        match self.history.drop_expired(&now) {
+            Ok(dropped) => debug!("Dropped {dropped} transactions out of the dispute window"),
+            Err(e) => error!("Can't drop transactions out of the dispute window, got: {e}"),
+        }
+    }
 }
 
+impl<H: TransactionHistoryStorage> TransactionProcessor for InMemoryTransactionProcessor<H> {
+    fn process(&self, transaction_entry: TransactionLogEntry) -> Result<(), Box<dyn Error>> {
+        let _processing = self.processing.lock().unwrap();
+        self.process_row(transaction_entry)
+    }
+}
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::history::TransactionHistoryStorage;
+    use crate::{
+        errors::{AccountError, TransactionError, TransactionHistoryError},
+        history::{DisputeWindow, HistoryStorage, StatusChange},
+        tiered_history::TieredTransactionStorage,
+    };
     use rstest::rstest;
     use rust_decimal::dec;
 
@@ -124,6 +307,9 @@ mod tests {
             client_id,
             transaction_id,
             amount: Some(amount),
+            timestamp: None,
+            reason: None,
+            origin: None,
         };
 
         let result = processor.process(entry);
@@ -141,15 +327,278 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_process_dispute_of_spilled_transaction() {
+        let history = TieredTransactionStorage::new(0).unwrap();
+        let processor = InMemoryTransactionProcessor::with_history(history);
+
+        for transaction_id in 1..=50 {
+            let entry = TransactionLogEntry {
+                transaction_type: "deposit".to_string(),
+                client_id: 1,
+                transaction_id,
+                amount: Some(dec!(1)),
+                timestamp: None,
+                reason: None,
+                origin: None,
+            };
+            processor.process(entry).unwrap();
+        }
+        assert_eq!(processor.get_history().cold_len(), 49);
+
+        let dispute_entry = TransactionLogEntry {
+            transaction_type: "dispute".to_string(),
+            client_id: 1,
+            transaction_id: 3,
+            amount: None,
+            timestamp: None,
+            reason: None,
+            origin: None,
+        };
+        processor.process(dispute_entry).unwrap();
+
+        let transaction_info = processor.get_history().find_transaction(3).unwrap();
+        assert_eq!(transaction_info.status, TransactionStatus::Disputed);
+        let accounts = processor.get_accounts_storage().accounts.read().unwrap();
+        assert_eq!(accounts.get(&1).unwrap().held_balance(), dec!(1));
+    }
+
+    #[test]
+    fn test_process_assigns_sequence_numbers() {
+        let history =
+            InMemoryTransactionStorage::new().with_dispute_window(DisputeWindow::Transactions(2));
+        let processor = InMemoryTransactionProcessor::with_history(history);
+        let entries = [
+            ("deposit", 1, Some(dec!(10))),
+            ("withdrawal", 2, Some(dec!(100))),
+            ("deposit", 3, Some(dec!(10))),
+            ("dispute", 1, None),
+            ("dispute", 3, None),
+        ];
+
+        let results = entries
+            .into_iter()
+            .map(|(transaction_type, transaction_id, amount)| {
+                processor.process(TransactionLogEntry {
+                    transaction_type: transaction_type.to_string(),
+                    client_id: 1,
+                    transaction_id,
+                    amount,
+                    timestamp: None,
+                    reason: None,
+                    origin: None,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        assert!(results[1].is_err());
+        let error = results[3].as_ref().unwrap_err();
+        let transaction_error = error.downcast_ref::<TransactionError>().unwrap();
+        assert_eq!(*transaction_error, TransactionError::DisputeWindowExpired);
+        assert!(results[4].is_ok());
+        assert_eq!(
+            processor
+                .history
+                .find_transaction(3)
+                .unwrap()
+                .sequence_number,
+            3
+        );
+        assert_eq!(
+            processor.current_meta(),
+            TransactionMeta {
+                sequence_number: 5,
+                timestamp: None,
+            }
+        );
+    }
+
     #[rstest]
-    #[case(TransactionLogEntry{transaction_type: "deposit".to_string(), client_id: 1, transaction_id: 2, amount: Some(dec!(10))}, false, TransactionStatus::WithoutDisputes)]
-    #[case(TransactionLogEntry{transaction_type: "withdrawal".to_string(), client_id: 1, transaction_id: 2, amount: Some(dec!(10))}, false, TransactionStatus::WithoutDisputes)]
-    #[case(TransactionLogEntry{transaction_type: "dispute".to_string(), client_id: 1, transaction_id: 1, amount: None}, false, TransactionStatus::Disputed)]
-    #[case(TransactionLogEntry{transaction_type: "resolve".to_string(), client_id: 1, transaction_id: 1, amount: None}, true, TransactionStatus::Resolved)]
-    #[case(TransactionLogEntry{transaction_type: "chargeback".to_string(), client_id: 1, transaction_id: 1, amount: None}, true, TransactionStatus::Chargebacked)]
+    #[case(InMemoryTransactionStorage::new().with_dispute_window(DisputeWindow::Transactions(2)).into())]
+    #[case(TieredTransactionStorage::new(0).unwrap().with_dispute_window(DisputeWindow::Transactions(2)).into())]
+    fn test_process_after_drop_of_expired_transaction(#[case] history: HistoryStorage) {
+        let processor = InMemoryTransactionProcessor::with_history(history).with_expiry_interval(1);
+        let entry = |transaction_type: &str, transaction_id, amount| TransactionLogEntry {
+            transaction_type: transaction_type.to_string(),
+            client_id: 1,
+            transaction_id,
+            amount,
+            timestamp: None,
+            reason: None,
+            origin: None,
+        };
+        for transaction_id in 1..=4 {
+            processor
+                .process(entry("deposit", transaction_id, Some(dec!(10))))
+                .unwrap();
+        }
+        assert!(processor.get_history().find_transaction(1).is_none());
+
+        let late_dispute = processor.process(entry("dispute", 1, None)).unwrap_err();
+        let reused_deposit = processor
+            .process(entry("deposit", 1, Some(dec!(5))))
+            .unwrap_err();
+        let reused_withdrawal = processor
+            .process(entry("withdrawal", 1, Some(dec!(5))))
+            .unwrap_err();
+        let unknown_dispute = processor.process(entry("dispute", 9, None)).unwrap_err();
+
+        assert_eq!(
+            *late_dispute.downcast_ref::<TransactionError>().unwrap(),
+            TransactionError::DisputeWindowExpired
+        );
+        for error in [reused_deposit, reused_withdrawal] {
+            assert_eq!(
+                *error.downcast_ref::<TransactionHistoryError>().unwrap(),
+                TransactionHistoryError::TransactionAlreadyExists
+            );
+        }
+        assert_eq!(
+            *unknown_dispute.downcast_ref::<TransactionError>().unwrap(),
+            TransactionError::OriginTransactionNotFound
+        );
+        assert_eq!(
+            processor.get_accounts_storage().get_balance(1),
+            Some(dec!(40))
+        );
+    }
+
+    #[test]
+    fn test_process_strict_account_opening() {
+        let processor = InMemoryTransactionProcessor::with_storages(
+            InMemoryAccountsStorage::new().with_strict_opening(),
+            InMemoryTransactionStorage::new(),
+        );
+        let entry = |transaction_type: &str, client_id, amount| TransactionLogEntry {
+            transaction_type: transaction_type.to_string(),
+            client_id,
+            transaction_id: 1,
+            amount,
+            timestamp: None,
+            reason: None,
+            origin: None,
+        };
+
+        let result = processor.process(entry("deposit", 1, Some(dec!(10))));
+        let error = result.unwrap_err();
+        let account_error = error.downcast_ref::<AccountError>().unwrap();
+        assert_eq!(*account_error, AccountError::AccountNotFound);
+
+        processor.process(entry("open", 1, None)).unwrap();
+        processor
+            .process(entry("deposit", 1, Some(dec!(10))))
+            .unwrap();
+
+        assert_eq!(processor.storage.get_balance(1), Some(dec!(10)));
+    }
+
+    #[test]
+    fn test_process_dispute_reason_is_reported() {
+        let processor = InMemoryTransactionProcessor::new();
+        let entry = |transaction_type: &str, amount, reason: Option<&str>| TransactionLogEntry {
+            transaction_type: transaction_type.to_string(),
+            client_id: 1,
+            transaction_id: 1,
+            amount,
+            timestamp: None,
+            reason: reason.map(str::to_string),
+            origin: None,
+        };
+
+        processor
+            .process(entry("deposit", Some(dec!(10)), None))
+            .unwrap();
+        processor
+            .process(entry("dispute", None, Some("duplicate")))
+            .unwrap();
+        processor.process(entry("chargeback", None, None)).unwrap();
+        // rejected rows don't count
+        processor.process(entry("resolve", None, None)).unwrap_err();
+
+        let transaction_info = processor.history.find_transaction(1).unwrap();
+        assert_eq!(
+            transaction_info.dispute_reason,
+            Some(DisputeReason::Duplicate)
+        );
+        let report = processor.get_dispute_report().snapshot();
+        assert_eq!(report.len(), 1);
+        let (reason, stats) = &report[0];
+        assert_eq!(*reason, Some(DisputeReason::Duplicate));
+        assert_eq!(stats.disputes, 1);
+        assert_eq!(stats.chargebacks, 1);
+        assert_eq!(stats.resolved, 0);
+        assert_eq!(stats.disputed_amount, dec!(10));
+    }
+
+    #[test]
+    fn test_process_records_status_changes() {
+        let processor = InMemoryTransactionProcessor::new();
+        let entry = |transaction_type: &str, transaction_id, amount| TransactionLogEntry {
+            transaction_type: transaction_type.to_string(),
+            client_id: 1,
+            transaction_id,
+            amount,
+            timestamp: None,
+            reason: None,
+            origin: None,
+        };
+
+        processor
+            .process(entry("deposit", 1, Some(dec!(10))))
+            .unwrap();
+        processor
+            .process(entry("deposit", 2, Some(dec!(10))))
+            .unwrap();
+        processor.process(entry("dispute", 1, None)).unwrap();
+        processor.process(entry("dispute", 2, None)).unwrap();
+        processor.process(entry("resolve", 1, None)).unwrap();
+
+        let status_changes = processor.history.status_changes(Some(1)).unwrap();
+        assert_eq!(
+            status_changes,
+            vec![
+                StatusChange {
+                    transaction_id: 1,
+                    previous_status: TransactionStatus::WithoutDisputes,
+                    new_status: TransactionStatus::Disputed,
+                    sequence_number: 3,
+                    timestamp: None,
+                },
+                StatusChange {
+                    transaction_id: 1,
+                    previous_status: TransactionStatus::Disputed,
+                    new_status: TransactionStatus::Resolved,
+                    sequence_number: 5,
+                    timestamp: None,
+                },
+            ]
+        );
+        let sequence_numbers = processor
+            .history
+            .status_changes(None)
+            .unwrap()
+            .iter()
+            .map(|status_change| status_change.sequence_number)
+            .collect::<Vec<_>>();
+        assert_eq!(sequence_numbers, vec![3, 4, 5]);
+    }
+
+    #[rstest]
+    #[case(TransactionLogEntry{transaction_type: "deposit".to_string(), client_id: 1, transaction_id: 2, amount: Some(dec!(10)), timestamp: None, reason: None, origin: None}, &[], TransactionStatus::WithoutDisputes)]
+    #[case(TransactionLogEntry{transaction_type: "withdrawal".to_string(), client_id: 1, transaction_id: 2, amount: Some(dec!(10)), timestamp: None, reason: None, origin: None}, &[], TransactionStatus::WithoutDisputes)]
+    #[case(TransactionLogEntry{transaction_type: "dispute".to_string(), client_id: 1, transaction_id: 1, amount: None, timestamp: None, reason: None, origin: None}, &[], TransactionStatus::Disputed)]
+    #[case(TransactionLogEntry{transaction_type: "resolve".to_string(), client_id: 1, transaction_id: 1, amount: None, timestamp: None, reason: None, origin: None}, &["dispute"], TransactionStatus::Resolved)]
+    #[case(TransactionLogEntry{transaction_type: "chargeback".to_string(), client_id: 1, transaction_id: 1, amount: None, timestamp: None, reason: None, origin: None}, &["dispute"], TransactionStatus::Chargebacked)]
+    #[case(TransactionLogEntry{transaction_type: "representment".to_string(), client_id: 1, transaction_id: 1, amount: None, timestamp: None, reason: None, origin: None}, &["dispute", "chargeback"], TransactionStatus::Represented)]
+    #[case(TransactionLogEntry{transaction_type: "representment".to_string(), client_id: 1, transaction_id: 1, amount: None, timestamp: None, reason: None, origin: None}, &["dispute"], TransactionStatus::Disputed)]
+    #[case(TransactionLogEntry{transaction_type: "prearbitration".to_string(), client_id: 1, transaction_id: 1, amount: None, timestamp: None, reason: None, origin: None}, &["dispute"], TransactionStatus::PreArbitration)]
+    #[case(TransactionLogEntry{transaction_type: "arbitration".to_string(), client_id: 1, transaction_id: 1, amount: None, timestamp: None, reason: None, origin: None}, &["dispute", "prearbitration"], TransactionStatus::Arbitration)]
+    #[case(TransactionLogEntry{transaction_type: "arbitration".to_string(), client_id: 1, transaction_id: 1, amount: None, timestamp: None, reason: None, origin: None}, &["dispute"], TransactionStatus::Disputed)]
+    #[case(TransactionLogEntry{transaction_type: "resolve".to_string(), client_id: 1, transaction_id: 1, amount: None, timestamp: None, reason: None, origin: None}, &["dispute", "prearbitration", "arbitration"], TransactionStatus::Resolved)]
+    #[case(TransactionLogEntry{transaction_type: "chargeback".to_string(), client_id: 1, transaction_id: 1, amount: None, timestamp: None, reason: None, origin: None}, &["dispute", "prearbitration"], TransactionStatus::Chargebacked)]
     fn test_transaction_status(
         #[case] transaction_log: TransactionLogEntry,
-        #[case] need_dispute: bool,
+        #[case] prior_steps: &[&str],
         #[case] expected_status: TransactionStatus,
     ) {
         let processor = InMemoryTransactionProcessor::new();
@@ -160,17 +609,23 @@ mod tests {
             client_id: 1,
             transaction_id: 1,
             amount: Some(dec!(100)),
+            timestamp: None,
+            reason: None,
+            origin: None,
         };
         let _result = processor.process(deposit_entry);
 
-        if need_dispute {
-            let dispute_entry = TransactionLogEntry {
-                transaction_type: "dispute".to_string(),
+        for transaction_type in prior_steps {
+            let prior_entry = TransactionLogEntry {
+                transaction_type: transaction_type.to_string(),
                 client_id: 1,
                 transaction_id: 1,
                 amount: None,
+                timestamp: None,
+                reason: None,
+                origin: None,
             };
-            let _result = processor.process(dispute_entry);
+            let _result = processor.process(prior_entry);
         }
 
         let transaction_id = transaction_log.transaction_id;
@@ -178,60 +633,4 @@ mod tests {
         let transaction_info = processor.history.find_transaction(transaction_id).unwrap();
         assert_eq!(transaction_info.status, expected_status);
     }
-
-    #[rstest]
-    #[case(
-        TransactionStatus::WithoutDisputes,
-        TransactionStatus::WithoutDisputes,
-        false
-    )]
-    #[case(TransactionStatus::WithoutDisputes, TransactionStatus::Disputed, true)]
-    #[case(TransactionStatus::WithoutDisputes, TransactionStatus::Resolved, false)]
-    #[case(
-        TransactionStatus::WithoutDisputes,
-        TransactionStatus::Chargebacked,
-        false
-    )]
-    #[case(TransactionStatus::Disputed, TransactionStatus::WithoutDisputes, false)]
-    #[case(TransactionStatus::Disputed, TransactionStatus::Disputed, false)]
-    #[case(TransactionStatus::Disputed, TransactionStatus::Resolved, true)]
-    #[case(TransactionStatus::Disputed, TransactionStatus::Chargebacked, true)]
-    #[case(TransactionStatus::Resolved, TransactionStatus::WithoutDisputes, false)]
-    #[case(TransactionStatus::Resolved, TransactionStatus::Disputed, false)]
-    #[case(TransactionStatus::Resolved, TransactionStatus::Resolved, false)]
-    #[case(TransactionStatus::Resolved, TransactionStatus::Chargebacked, false)]
-    #[case(
-        TransactionStatus::Chargebacked,
-        TransactionStatus::WithoutDisputes,
-        false
-    )]
-    #[case(TransactionStatus::Chargebacked, TransactionStatus::Disputed, false)]
-    #[case(TransactionStatus::Chargebacked, TransactionStatus::Resolved, false)]
-    #[case(
-        TransactionStatus::Chargebacked,
-        TransactionStatus::Chargebacked,
-        false
-    )]
-    fn test_status_transitions(
-        #[case] from: TransactionStatus,
-        #[case] to: TransactionStatus,
-        #[case] should_be_valid: bool,
-    ) {
-        assert_eq!(from.is_transition_available(&to), should_be_valid);
-
-        let result = from.make_transition(to);
-
-        if should_be_valid {
-            assert!(result.is_ok());
-            assert_eq!(result.unwrap(), to);
-        } else {
-            assert!(result.is_err());
-            let error = result.unwrap_err();
-            let history_error = error.downcast_ref::<TransactionHistoryError>().unwrap();
-            assert_eq!(
-                *history_error,
-                TransactionHistoryError::InvalidStatusTransition
-            );
-        }
-    }
 }